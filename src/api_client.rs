@@ -1,8 +1,23 @@
 use crate::climate::ClimateInfo;
-use crate::climate::climate_state_api::ClimateState;
+use crate::climate::climate_state_api::{ClimateState, Context};
 use anyhow::anyhow;
 use reqwest::{Client, RequestBuilder, Url};
+use serde::Deserialize;
 
+/// State of an arbitrary Home Assistant entity
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EntityState {
+    pub entity_id: String,
+    pub state: String,
+    #[serde(default)]
+    pub attributes: serde_json::Value,
+    #[serde(default)]
+    pub last_changed: String,
+    #[serde(default)]
+    pub context: Context,
+}
+
+#[derive(Clone)]
 pub struct ApiClient {
     client: Client,
     base_url: Url,
@@ -26,12 +41,28 @@ impl ApiClient {
             .send()
             .await
             .map_err(|e| anyhow!(e))?
+            .error_for_status()?
             .json::<ClimateState>()
             .await?;
 
         Ok(resp.into())
     }
 
+    /// Read any entity, e.g. a `binary_sensor`, without assuming its attributes
+    pub async fn fetch_entity_state(&self, entity_id: &str) -> Result<EntityState, anyhow::Error> {
+        let endpoint = format!("/api/states/{}", entity_id);
+        let resp = self
+            .get(&endpoint)
+            .send()
+            .await
+            .map_err(|e| anyhow!(e))?
+            .error_for_status()?
+            .json::<EntityState>()
+            .await?;
+
+        Ok(resp)
+    }
+
     pub fn get(&self, endpoint: &str) -> RequestBuilder {
         let url = self.base_url.join(endpoint).expect("Invalid endpoint");
         self.client
@@ -5,12 +5,19 @@ use async_trait::async_trait;
 #[async_trait]
 pub trait ClimateEntity: Send + Sync {
     fn get_entity_id(&self) -> &str;
+    /// Whether commands are only logged rather than sent
+    fn is_dry_run(&self) -> bool {
+        false
+    }
     fn get_cached_state(&self) -> &Option<ClimateInfo>;
     fn update_cached_state(&mut self, climate_info: Option<ClimateInfo>);
 
     fn get_boosted_status(&self) -> &Option<BoostInfo>;
     fn set_boost(&mut self, boost: Option<BoostInfo>);
 
+    /// Read the room temperature from this `sensor` instead of the device's own reading
+    fn set_temperature_sensor(&mut self, _sensor: Option<String>) {}
+
     async fn fetch_and_update_state(&mut self, api_client: &ApiClient)
     -> Result<(), anyhow::Error>;
     async fn turn_on(&self, api_client: &ApiClient) -> Result<(), anyhow::Error>;
@@ -1,22 +1,28 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// The `state` of a climate entity: one of Home Assistant's hvac modes, or the
+/// placeholders it reports when the device can't be reached
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ApiHeatingState {
+    #[default]
     Off,
     Heat,
-}
-
-impl Default for ApiHeatingState {
-    fn default() -> Self {
-        ApiHeatingState::Off
-    }
+    Cool,
+    HeatCool,
+    Auto,
+    Dry,
+    FanOnly,
+    Unavailable,
+    Unknown,
+    /// Anything newer Home Assistant versions or custom integrations report
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct ClimateState {
     #[serde(rename = "entity_id")]
     pub entity_id: String,
@@ -32,23 +38,23 @@ pub struct ClimateState {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct Attributes {
     #[serde(rename = "hvac_modes")]
     pub hvac_modes: Vec<String>,
     #[serde(rename = "min_temp")]
-    pub min_temp: f64,
+    pub min_temp: Option<f64>,
     #[serde(rename = "max_temp")]
-    pub max_temp: f64,
+    pub max_temp: Option<f64>,
     #[serde(rename = "current_temperature")]
-    pub current_temperature: f64,
+    pub current_temperature: Option<f64>,
     pub temperature: Value,
     #[serde(rename = "friendly_name")]
-    pub friendly_name: String,
+    pub friendly_name: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct Context {
     pub id: String,
     #[serde(rename = "parent_id")]
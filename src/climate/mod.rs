@@ -1,31 +1,206 @@
 use crate::api_client::ApiClient;
 use crate::climate::climate_state_api::{ApiHeatingState, ClimateState as ApiClimateState};
+use crate::clock::{SharedClock, SystemClock};
 use crate::schedule::HeatingState;
 use anyhow::anyhow;
-use chrono::NaiveTime;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
+#[allow(clippy::module_inception)]
 pub mod climate;
 pub mod climate_state_api;
+pub mod on_off;
+pub mod thermal;
 
 pub use climate::ClimateEntity;
+pub use on_off::OnOffClimate;
+pub use thermal::{SimulatedRoom, ThermalModel};
 
 #[derive(Debug, Clone)]
 pub struct ClimateInfo {
-    pub current_temperature: f64,
+    /// Missing while the thermostat is unavailable or hasn't reported yet
+    pub current_temperature: Option<f64>,
+    pub target_temperature: Option<f64>,
     pub state: HeatingState,
+    /// The mode as reported by Home Assistant, before it's reduced to on/off
+    pub hvac_mode: ApiHeatingState,
+    pub availability: Availability,
+    /// When Home Assistant last saw the state change
+    pub last_changed: Option<DateTime<Local>>,
+    /// The Home Assistant user behind the last change; None for changes made on the device
+    pub changed_by: Option<String>,
 }
 
-#[derive(Debug, Default, Clone)]
+/// Whether Home Assistant can currently reach the device behind an entity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Availability {
+    #[default]
+    Available,
+    Unavailable,
+    Unknown,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct BoostInfo {
-    pub boost_start: NaiveTime,
-    pub boost_end: NaiveTime,
+    pub boost_start: DateTime<Local>,
+    pub boost_end: DateTime<Local>,
+}
+
+/// How entities talk to Home Assistant, chosen at startup with `RUN_MODE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    /// Simulated entities, no Home Assistant calls at all
+    Mock,
+    /// Real state is read from Home Assistant, but commands are only logged
+    DryRun,
+    /// Real state is read and commands are sent
+    Live,
+}
+
+impl Default for RunMode {
+    /// Mock in debug builds so a plain `cargo run` never touches the real heating
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            RunMode::Mock
+        } else {
+            RunMode::Live
+        }
+    }
 }
 
-/// Wrapper enum to allow using either Mock or Real climate entities
+impl FromStr for RunMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "mock" => Ok(RunMode::Mock),
+            "dry_run" => Ok(RunMode::DryRun),
+            "live" => Ok(RunMode::Live),
+            other => Err(anyhow!("Unknown run mode: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for RunMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunMode::Mock => write!(f, "MOCK"),
+            RunMode::DryRun => write!(f, "DRY RUN"),
+            RunMode::Live => write!(f, "LIVE"),
+        }
+    }
+}
+
+/// The Home Assistant domains an entity can be controlled through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityDomain {
+    Climate,
+    Switch,
+    InputBoolean,
+    WaterHeater,
+}
+
+impl EntityDomain {
+    /// The domain of an entity ID, if it's one the scheduler can control
+    pub fn of(entity_id: &str) -> Option<Self> {
+        match entity_id.split_once('.')?.0 {
+            "climate" => Some(EntityDomain::Climate),
+            "switch" => Some(EntityDomain::Switch),
+            "input_boolean" => Some(EntityDomain::InputBoolean),
+            "water_heater" => Some(EntityDomain::WaterHeater),
+            _ => None,
+        }
+    }
+
+    /// The domain of an entity ID, or an error naming the domains the scheduler can control
+    pub fn parse(entity_id: &str) -> anyhow::Result<Self> {
+        Self::of(entity_id).ok_or_else(|| {
+            anyhow!(
+                "Unsupported entity {}: expected a climate, switch, input_boolean or water_heater",
+                entity_id
+            )
+        })
+    }
+}
+
+/// Wrapper enum to allow using either Mock or Real climate entities, or the on/off
+/// entities that stand in for a thermostat
 #[derive(Debug, Clone)]
 pub enum ClimateEntityWrapper {
     Mock(MockClimate),
     Real(DefaultClimate),
+    /// A `switch`, `input_boolean` or `water_heater`
+    OnOff(OnOffClimate),
+}
+
+/// Creates entities the same way at startup and when they're added through the API
+#[derive(Debug, Clone)]
+pub struct ClimateEntityFactory {
+    pub run_mode: RunMode,
+    /// Room model used for mock entities
+    pub thermal_model: ThermalModel,
+    pub clock: SharedClock,
+}
+
+impl ClimateEntityFactory {
+    pub fn new(run_mode: RunMode, thermal_model: ThermalModel, clock: SharedClock) -> Self {
+        ClimateEntityFactory {
+            run_mode,
+            thermal_model,
+            clock,
+        }
+    }
+
+    /// Create the entity implementation matching the run mode and the entity's domain.
+    /// An entity outside the supported domains is an error.
+    pub fn create(&self, entity_id: String) -> anyhow::Result<ClimateEntityWrapper> {
+        let domain = EntityDomain::parse(&entity_id)?;
+        let dry_run = match self.run_mode {
+            RunMode::Mock => {
+                return Ok(ClimateEntityWrapper::Mock(MockClimate::with_simulation(
+                    entity_id,
+                    SimulatedRoom::new(
+                        self.thermal_model,
+                        HeatingState::Off,
+                        MOCK_INITIAL_TEMPERATURE,
+                    ),
+                    Arc::clone(&self.clock),
+                )));
+            }
+            RunMode::DryRun => true,
+            RunMode::Live => false,
+        };
+        Ok(match domain {
+            EntityDomain::Switch | EntityDomain::InputBoolean => {
+                ClimateEntityWrapper::OnOff(OnOffClimate::switch(entity_id).with_dry_run(dry_run))
+            }
+            EntityDomain::WaterHeater => ClimateEntityWrapper::OnOff(
+                OnOffClimate::water_heater(entity_id).with_dry_run(dry_run),
+            ),
+            EntityDomain::Climate => {
+                ClimateEntityWrapper::Real(DefaultClimate::new(entity_id).with_dry_run(dry_run))
+            }
+        })
+    }
+
+    /// Create the entities loaded from configuration, skipping any that can't be controlled
+    pub fn create_all(&self, entity_ids: Vec<String>) -> Vec<ClimateEntityWrapper> {
+        entity_ids
+            .into_iter()
+            .filter_map(|entity_id| match self.create(entity_id) {
+                Ok(entity) => Some(entity),
+                Err(e) => {
+                    eprintln!("Warning: {}, skipping", e);
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 #[async_trait::async_trait]
@@ -34,6 +209,15 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.get_entity_id(),
             ClimateEntityWrapper::Real(r) => r.get_entity_id(),
+            ClimateEntityWrapper::OnOff(o) => o.get_entity_id(),
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        match self {
+            ClimateEntityWrapper::Mock(m) => m.is_dry_run(),
+            ClimateEntityWrapper::Real(r) => r.is_dry_run(),
+            ClimateEntityWrapper::OnOff(o) => o.is_dry_run(),
         }
     }
 
@@ -41,6 +225,7 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.get_cached_state(),
             ClimateEntityWrapper::Real(r) => r.get_cached_state(),
+            ClimateEntityWrapper::OnOff(o) => o.get_cached_state(),
         }
     }
 
@@ -48,6 +233,7 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.update_cached_state(climate_info),
             ClimateEntityWrapper::Real(r) => r.update_cached_state(climate_info),
+            ClimateEntityWrapper::OnOff(o) => o.update_cached_state(climate_info),
         }
     }
 
@@ -55,12 +241,22 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.get_boosted_status(),
             ClimateEntityWrapper::Real(r) => r.get_boosted_status(),
+            ClimateEntityWrapper::OnOff(o) => o.get_boosted_status(),
         }
     }
     fn set_boost(&mut self, boost: Option<BoostInfo>) {
         match self {
             ClimateEntityWrapper::Mock(m) => m.set_boost(boost),
             ClimateEntityWrapper::Real(r) => r.set_boost(boost),
+            ClimateEntityWrapper::OnOff(o) => o.set_boost(boost),
+        }
+    }
+
+    fn set_temperature_sensor(&mut self, sensor: Option<String>) {
+        match self {
+            ClimateEntityWrapper::Mock(m) => m.set_temperature_sensor(sensor),
+            ClimateEntityWrapper::Real(r) => r.set_temperature_sensor(sensor),
+            ClimateEntityWrapper::OnOff(o) => o.set_temperature_sensor(sensor),
         }
     }
 
@@ -71,6 +267,7 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.fetch_and_update_state(api_client).await,
             ClimateEntityWrapper::Real(r) => r.fetch_and_update_state(api_client).await,
+            ClimateEntityWrapper::OnOff(o) => o.fetch_and_update_state(api_client).await,
         }
     }
 
@@ -78,6 +275,7 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.turn_on(api_client).await,
             ClimateEntityWrapper::Real(r) => r.turn_on(api_client).await,
+            ClimateEntityWrapper::OnOff(o) => o.turn_on(api_client).await,
         }
     }
 
@@ -85,6 +283,7 @@ impl ClimateEntity for ClimateEntityWrapper {
         match self {
             ClimateEntityWrapper::Mock(m) => m.turn_off(api_client).await,
             ClimateEntityWrapper::Real(r) => r.turn_off(api_client).await,
+            ClimateEntityWrapper::OnOff(o) => o.turn_off(api_client).await,
         }
     }
 }
@@ -94,6 +293,10 @@ pub struct DefaultClimate {
     pub entity_id: String,
     pub info: Option<ClimateInfo>,
     pub boosted: Option<BoostInfo>,
+    /// Only log commands instead of sending them
+    pub dry_run: bool,
+    /// Room temperature sensor read in place of the device's `current_temperature`
+    pub temperature_sensor: Option<String>,
 }
 
 impl DefaultClimate {
@@ -102,8 +305,37 @@ impl DefaultClimate {
             entity_id,
             info: None,
             boosted: Default::default(),
+            dry_run: false,
+            temperature_sensor: None,
         }
     }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Call `climate.set_hvac_mode`, failing on any non-success response
+    async fn set_hvac_mode(
+        &self,
+        api_client: &ApiClient,
+        hvac_mode: &str,
+    ) -> Result<(), anyhow::Error> {
+        let body = serde_json::json!({
+            "entity_id": self.entity_id,
+            "hvac_mode": hvac_mode
+        });
+
+        api_client
+            .post("/api/services/climate/set_hvac_mode")
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| anyhow!(e))?
+            .error_for_status()?;
+
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
@@ -112,6 +344,10 @@ impl ClimateEntity for DefaultClimate {
         &self.entity_id
     }
 
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
     fn get_cached_state(&self) -> &Option<ClimateInfo> {
         &self.info
     }
@@ -127,69 +363,103 @@ impl ClimateEntity for DefaultClimate {
         self.boosted = boost;
     }
 
+    fn set_temperature_sensor(&mut self, sensor: Option<String>) {
+        self.temperature_sensor = sensor;
+    }
+
     async fn fetch_and_update_state(
         &mut self,
         api_client: &ApiClient,
     ) -> Result<(), anyhow::Error> {
         // Actually calls the Home Assistant API
-        let climate_info = api_client.fetch_climate_state(&self.entity_id).await?;
+        let mut climate_info = api_client.fetch_climate_state(&self.entity_id).await?;
+        read_temperature_sensor(
+            api_client,
+            self.temperature_sensor.as_deref(),
+            &mut climate_info,
+        )
+        .await;
         self.info = Some(climate_info);
         Ok(())
     }
 
     async fn turn_on(&self, api_client: &ApiClient) -> Result<(), anyhow::Error> {
+        if self.dry_run {
+            println!("  [DRY RUN] Would turn ON: {}", self.entity_id);
+            return Ok(());
+        }
         println!("  → Turning ON: {}", self.entity_id);
-        let body = serde_json::json!({
-            "entity_id": self.entity_id,
-            "hvac_mode": "heat"
-        });
-
-        api_client
-            .post("/api/services/climate/set_hvac_mode")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| anyhow!(e))?;
-
-        Ok(())
+        self.set_hvac_mode(api_client, "heat").await
     }
 
     async fn turn_off(&self, api_client: &ApiClient) -> Result<(), anyhow::Error> {
+        if self.dry_run {
+            println!("  [DRY RUN] Would turn OFF: {}", self.entity_id);
+            return Ok(());
+        }
         println!("  → Turning OFF: {}", self.entity_id);
-        let body = serde_json::json!({
-            "entity_id": self.entity_id,
-            "hvac_mode": "off"
-        });
-
-        api_client
-            .post("/api/services/climate/set_hvac_mode")
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| anyhow!(e))?;
+        self.set_hvac_mode(api_client, "off").await
+    }
+}
 
-        Ok(())
+/// Starting room temperature for mock entities
+const MOCK_INITIAL_TEMPERATURE: f64 = 18.0;
+
+/// What a simulated room reports, as Home Assistant would for a heating thermostat
+fn room_info(room: &SimulatedRoom) -> ClimateInfo {
+    ClimateInfo {
+        current_temperature: Some(room.temperature),
+        target_temperature: None,
+        state: room.state.clone(),
+        hvac_mode: match room.state {
+            HeatingState::On => ApiHeatingState::Heat,
+            HeatingState::Off => ApiHeatingState::Off,
+        },
+        availability: Availability::Available,
+        last_changed: None,
+        changed_by: None,
     }
 }
 
+/// Climate entity backed by a simulated room instead of Home Assistant
+///
+/// Clones share the same room, so commands sent through one copy are seen by the others.
 #[derive(Debug, Clone)]
 pub struct MockClimate {
     pub entity_id: String,
     pub info: Option<ClimateInfo>,
     pub boosted: Option<BoostInfo>,
+    pub room: Arc<Mutex<SimulatedRoom>>,
+    clock: SharedClock,
 }
 
 impl MockClimate {
     pub fn new(entity_id: String, initial_state: HeatingState) -> Self {
+        MockClimate::with_simulation(
+            entity_id,
+            SimulatedRoom::new(ThermalModel::default(), initial_state, 20.0),
+            Arc::new(SystemClock),
+        )
+    }
+
+    /// Create a mock whose room evolves with the given model as the clock moves
+    pub fn with_simulation(entity_id: String, room: SimulatedRoom, clock: SharedClock) -> Self {
+        let info = room_info(&room);
+        let mut room = room;
+        room.advance(clock.now());
+
         MockClimate {
             entity_id,
-            info: Some(ClimateInfo {
-                current_temperature: 20.0,
-                state: initial_state,
-            }),
+            info: Some(info),
             boosted: Default::default(),
+            room: Arc::new(Mutex::new(room)),
+            clock,
         }
     }
+
+    fn set_room_state(&self, state: HeatingState) {
+        self.room.lock().unwrap().set_state(state, self.clock.now());
+    }
 }
 
 #[async_trait::async_trait]
@@ -217,29 +487,26 @@ impl ClimateEntity for MockClimate {
         &mut self,
         _api_client: &ApiClient,
     ) -> Result<(), anyhow::Error> {
-        // Mock: doesn't call API, just returns success
-        println!("[MOCK] Fetching state for {} (no API call)", self.entity_id);
-        // Optionally update with mock data
-        self.info = Some(ClimateInfo {
-            current_temperature: 21.0,
-            state: self
-                .info
-                .as_ref()
-                .map(|i| i.state.clone())
-                .unwrap_or(HeatingState::Off),
-        });
+        // Mock: doesn't call API, reads the simulated room instead
+        let mut room = self.room.lock().unwrap();
+        room.advance(self.clock.now());
+        println!(
+            "[MOCK] Fetching state for {} (no API call): {:?} at {:.1}°C",
+            self.entity_id, room.state, room.temperature
+        );
+        self.info = Some(room_info(&room));
         Ok(())
     }
 
     async fn turn_on(&self, _api_client: &ApiClient) -> Result<(), anyhow::Error> {
         println!("[MOCK] Turning ON: {}", self.entity_id);
-        // In a real mock, you might update internal state here
+        self.set_room_state(HeatingState::On);
         Ok(())
     }
 
     async fn turn_off(&self, _api_client: &ApiClient) -> Result<(), anyhow::Error> {
         println!("[MOCK] Turning OFF: {}", self.entity_id);
-        // In a real mock, you might update internal state here
+        self.set_room_state(HeatingState::Off);
         Ok(())
     }
 }
@@ -250,16 +517,55 @@ impl ClimateEntity for MockClimate {
 
 impl From<ApiClimateState> for ClimateInfo {
     fn from(state: ApiClimateState) -> Self {
+        let availability = match state.state {
+            ApiHeatingState::Unavailable => Availability::Unavailable,
+            ApiHeatingState::Unknown => Availability::Unknown,
+            _ => Availability::Available,
+        };
         ClimateInfo {
             current_temperature: state.attributes.current_temperature,
+            target_temperature: state.attributes.temperature.as_f64(),
+            // Modes where the thermostat may call for heat count as on
             state: match state.state {
-                ApiHeatingState::Off => HeatingState::Off,
-                ApiHeatingState::Heat => HeatingState::On,
+                ApiHeatingState::Heat | ApiHeatingState::HeatCool | ApiHeatingState::Auto => {
+                    HeatingState::On
+                }
+                _ => HeatingState::Off,
             },
+            hvac_mode: state.state,
+            availability,
+            last_changed: DateTime::parse_from_rfc3339(&state.last_changed)
+                .ok()
+                .map(|time| time.with_timezone(&Local)),
+            changed_by: state.context.user_id.as_str().map(str::to_string),
         }
     }
 }
 
+/// Replace a device's temperature with the reading of its room sensor, if it has one.
+/// The device's own reading is kept while the sensor can't be read or isn't a number.
+pub(crate) async fn read_temperature_sensor(
+    api_client: &ApiClient,
+    sensor: Option<&str>,
+    info: &mut ClimateInfo,
+) {
+    let Some(sensor) = sensor else {
+        return;
+    };
+    match api_client.fetch_entity_state(sensor).await {
+        Ok(reading) => match reading.state.parse::<f64>() {
+            Ok(temperature) if temperature.is_finite() => {
+                info.current_temperature = Some(temperature);
+            }
+            _ => eprintln!(
+                "  {} reads {:?}, using the device's own",
+                sensor, reading.state
+            ),
+        },
+        Err(e) => eprintln!("  Error reading temperature sensor {}: {}", sensor, e),
+    }
+}
+
 pub async fn get_initial_states(
     inital_strings: Vec<String>,
 ) -> Result<Vec<DefaultClimate>, anyhow::Error> {
@@ -276,6 +582,60 @@ pub async fn get_initial_states(
 mod tests {
     use super::*;
 
+    fn parse(json: serde_json::Value) -> ClimateInfo {
+        serde_json::from_value::<ApiClimateState>(json)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_parse_unavailable_state_without_attributes() {
+        let info = parse(serde_json::json!({
+            "entity_id": "climate.bedroom",
+            "state": "unavailable",
+            "attributes": { "friendly_name": "Bedroom" },
+        }));
+
+        assert_eq!(info.availability, Availability::Unavailable);
+        assert_eq!(info.hvac_mode, ApiHeatingState::Unavailable);
+        assert_eq!(info.state, HeatingState::Off);
+        assert_eq!(info.current_temperature, None);
+        assert_eq!(info.target_temperature, None);
+    }
+
+    #[test]
+    fn test_parse_hvac_modes() {
+        let info = parse(serde_json::json!({
+            "state": "heat_cool",
+            "attributes": { "current_temperature": null, "temperature": null },
+        }));
+        assert_eq!(info.hvac_mode, ApiHeatingState::HeatCool);
+        assert_eq!(info.state, HeatingState::On);
+        assert_eq!(info.availability, Availability::Available);
+        assert_eq!(info.current_temperature, None);
+
+        assert_eq!(
+            parse(serde_json::json!({ "state": "auto" })).state,
+            HeatingState::On
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "state": "cool" })).state,
+            HeatingState::Off
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "state": "fan_only" })).hvac_mode,
+            ApiHeatingState::FanOnly
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "state": "unknown" })).availability,
+            Availability::Unknown
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "state": "eco_boost" })).hvac_mode,
+            ApiHeatingState::Other
+        );
+    }
+
     #[tokio::test]
     async fn test_mock_climate() {
         let mut mock = MockClimate::new("climate.test".to_string(), HeatingState::Off);
@@ -294,4 +654,172 @@ mod tests {
         mock.turn_on(&fake_client).await.unwrap();
         mock.turn_off(&fake_client).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_dry_run_climate_does_not_send_commands() {
+        let climate = DefaultClimate::new("climate.test".to_string()).with_dry_run(true);
+        assert!(climate.is_dry_run());
+
+        // Nothing listens on this address, so a real request would fail
+        let unreachable_client = ApiClient::new(
+            reqwest::Url::parse("http://127.0.0.1:9").unwrap(),
+            "fake_token".to_string(),
+        );
+
+        climate.turn_on(&unreachable_client).await.unwrap();
+        climate.turn_off(&unreachable_client).await.unwrap();
+    }
+
+    #[test]
+    fn test_run_mode_from_str() {
+        assert_eq!("mock".parse::<RunMode>().unwrap(), RunMode::Mock);
+        assert_eq!("dry-run".parse::<RunMode>().unwrap(), RunMode::DryRun);
+        assert_eq!("DRY_RUN".parse::<RunMode>().unwrap(), RunMode::DryRun);
+        assert_eq!("live".parse::<RunMode>().unwrap(), RunMode::Live);
+        assert!("production".parse::<RunMode>().is_err());
+        // Tests are debug builds
+        assert_eq!(RunMode::default(), RunMode::Mock);
+    }
+
+    #[test]
+    fn test_factory_creates_entity_for_mode() {
+        let factory = |run_mode| {
+            ClimateEntityFactory::new(run_mode, ThermalModel::default(), Arc::new(SystemClock))
+        };
+        let entity_id = "climate.test".to_string();
+        assert!(matches!(
+            factory(RunMode::Mock).create(entity_id.clone()).unwrap(),
+            ClimateEntityWrapper::Mock(_)
+        ));
+        assert!(
+            factory(RunMode::DryRun)
+                .create(entity_id.clone())
+                .unwrap()
+                .is_dry_run()
+        );
+        assert!(
+            !factory(RunMode::Live)
+                .create(entity_id)
+                .unwrap()
+                .is_dry_run()
+        );
+    }
+
+    #[test]
+    fn test_factory_selects_implementation_by_domain() {
+        let factory = |run_mode| {
+            ClimateEntityFactory::new(run_mode, ThermalModel::default(), Arc::new(SystemClock))
+        };
+        let create = |entity_id: &str| {
+            factory(RunMode::Live)
+                .create(entity_id.to_string())
+                .unwrap()
+        };
+        assert!(matches!(
+            create("climate.bedroom"),
+            ClimateEntityWrapper::Real(_)
+        ));
+        let on_off = |entity_id: &str| match create(entity_id) {
+            ClimateEntityWrapper::OnOff(entity) => entity.domain,
+            other => panic!("{:?} is not an on/off entity", other),
+        };
+        assert_eq!(on_off("switch.immersion"), "switch");
+        assert_eq!(on_off("input_boolean.underfloor"), "input_boolean");
+        assert_eq!(on_off("water_heater.tank"), "water_heater");
+
+        let switch = factory(RunMode::DryRun)
+            .create("switch.immersion".to_string())
+            .unwrap();
+        assert!(switch.is_dry_run());
+        assert_eq!(EntityDomain::of("light.kitchen"), None);
+        assert_eq!(EntityDomain::of("switch"), None);
+
+        // Unsupported domains are rejected whatever the run mode, and skipped when loading
+        assert!(
+            factory(RunMode::Mock)
+                .create("light.kitchen".to_string())
+                .is_err()
+        );
+        let loaded = factory(RunMode::Live).create_all(vec![
+            "light.kitchen".to_string(),
+            "climate.bedroom".to_string(),
+        ]);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].get_entity_id(), "climate.bedroom");
+    }
+
+    #[test]
+    fn test_parse_on_off_states() {
+        let parse = |json: serde_json::Value| {
+            serde_json::from_value::<crate::api_client::EntityState>(json).unwrap()
+        };
+        let switch = OnOffClimate::switch("switch.immersion".to_string());
+        let info = switch.info(parse(serde_json::json!({
+            "entity_id": "switch.immersion",
+            "state": "on",
+            "last_changed": "2025-01-15T07:00:00+00:00",
+            "context": { "user_id": "abc" },
+        })));
+        assert_eq!(info.state, HeatingState::On);
+        assert_eq!(info.changed_by.as_deref(), Some("abc"));
+        assert!(info.last_changed.is_some());
+
+        let info = switch.info(parse(serde_json::json!({
+            "entity_id": "switch.immersion",
+            "state": "unavailable",
+        })));
+        assert_eq!(info.availability, Availability::Unavailable);
+        assert_eq!(info.state, HeatingState::Off);
+
+        let tank = OnOffClimate::water_heater("water_heater.tank".to_string());
+        let water_heater = |state: &str| {
+            tank.info(parse(serde_json::json!({
+                "entity_id": "water_heater.tank",
+                "state": state,
+            })))
+        };
+        assert_eq!(water_heater("eco").state, HeatingState::On);
+        assert_eq!(water_heater("eco").hvac_mode, ApiHeatingState::Heat);
+        assert_eq!(water_heater("off").state, HeatingState::Off);
+        assert_eq!(water_heater("unknown").availability, Availability::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_mock_climate_tracks_state_and_temperature() {
+        use crate::clock::MockClock;
+        use chrono::{Duration, Local, TimeZone};
+
+        let clock = MockClock::new(Local.with_ymd_and_hms(2025, 1, 15, 6, 0, 0).unwrap());
+        let model = ThermalModel {
+            heat_up_rate: 2.0,
+            heat_loss_rate: 0.0,
+            outdoor_temperature: 5.0,
+        };
+        let mut mock = MockClimate::with_simulation(
+            "climate.test".to_string(),
+            SimulatedRoom::new(model, HeatingState::Off, 18.0),
+            Arc::new(clock.clone()),
+        );
+        let fake_client = ApiClient::new(
+            reqwest::Url::parse("http://fake").unwrap(),
+            "fake_token".to_string(),
+        );
+
+        // A clone, like the scheduler uses each tick, drives the same room
+        mock.clone().turn_on(&fake_client).await.unwrap();
+        clock.advance(Duration::minutes(30));
+        mock.fetch_and_update_state(&fake_client).await.unwrap();
+
+        let info = mock.get_cached_state().clone().unwrap();
+        assert_eq!(info.state, HeatingState::On);
+        assert_eq!(info.current_temperature, Some(19.0));
+
+        mock.turn_off(&fake_client).await.unwrap();
+        clock.advance(Duration::minutes(30));
+        mock.fetch_and_update_state(&fake_client).await.unwrap();
+
+        let info = mock.get_cached_state().clone().unwrap();
+        assert_eq!(info.state, HeatingState::Off);
+        assert_eq!(info.current_temperature, Some(19.0));
+    }
 }
@@ -0,0 +1,161 @@
+use crate::api_client::{ApiClient, EntityState};
+use crate::climate::climate_state_api::ApiHeatingState;
+use crate::climate::{
+    Availability, BoostInfo, ClimateEntity, ClimateInfo, read_temperature_sensor,
+};
+use crate::schedule::HeatingState;
+use anyhow::anyhow;
+use chrono::{DateTime, Local};
+
+/// Tells from an entity's `state` whether it's heating
+pub type StateParser = fn(&str) -> bool;
+
+/// A zone or tank without a thermostat, switched with its domain's `turn_on` and `turn_off`
+/// services: a `switch` or `input_boolean` relay, or a `water_heater`
+#[derive(Debug, Clone)]
+pub struct OnOffClimate {
+    pub entity_id: String,
+    /// The domain whose services control the entity
+    pub domain: String,
+    pub is_on: StateParser,
+    pub info: Option<ClimateInfo>,
+    pub boosted: Option<BoostInfo>,
+    /// Only log commands instead of sending them
+    pub dry_run: bool,
+    /// Room temperature sensor read in place of the device's `current_temperature`
+    pub temperature_sensor: Option<String>,
+}
+
+impl OnOffClimate {
+    pub fn new(entity_id: String, domain: &str, is_on: StateParser) -> Self {
+        OnOffClimate {
+            entity_id,
+            domain: domain.to_string(),
+            is_on,
+            info: None,
+            boosted: Default::default(),
+            dry_run: false,
+            temperature_sensor: None,
+        }
+    }
+
+    /// A `switch` or `input_boolean`, whichever the entity belongs to, heating while `on`
+    pub fn switch(entity_id: String) -> Self {
+        let domain = entity_id
+            .split_once('.')
+            .map_or("switch", |(domain, _)| domain)
+            .to_string();
+        OnOffClimate::new(entity_id, &domain, |state| state == "on")
+    }
+
+    /// A `water_heater` such as an immersion, heating in any operation mode but `off`
+    pub fn water_heater(entity_id: String) -> Self {
+        OnOffClimate::new(entity_id, "water_heater", |state| {
+            !matches!(state, "off" | "unavailable" | "unknown")
+        })
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Read the entity's state as heating on or off, reporting `heat` or `off` as its mode
+    pub(crate) fn info(&self, state: EntityState) -> ClimateInfo {
+        let on = (self.is_on)(&state.state);
+        let (availability, hvac_mode) = match state.state.as_str() {
+            "unavailable" => (Availability::Unavailable, ApiHeatingState::Unavailable),
+            "unknown" => (Availability::Unknown, ApiHeatingState::Unknown),
+            _ if on => (Availability::Available, ApiHeatingState::Heat),
+            _ => (Availability::Available, ApiHeatingState::Off),
+        };
+        let attribute = |name: &str| state.attributes.get(name).and_then(|v| v.as_f64());
+        ClimateInfo {
+            current_temperature: attribute("current_temperature"),
+            target_temperature: attribute("temperature"),
+            state: if on {
+                HeatingState::On
+            } else {
+                HeatingState::Off
+            },
+            hvac_mode,
+            availability,
+            last_changed: DateTime::parse_from_rfc3339(&state.last_changed)
+                .ok()
+                .map(|time| time.with_timezone(&Local)),
+            changed_by: state.context.user_id.as_str().map(str::to_string),
+        }
+    }
+
+    /// Call `<domain>.<service>` for the entity, failing on any non-success response
+    async fn call_service(&self, api_client: &ApiClient, service: &str) -> anyhow::Result<()> {
+        api_client
+            .post(&format!("/api/services/{}/{}", self.domain, service))
+            .json(&serde_json::json!({ "entity_id": self.entity_id }))
+            .send()
+            .await
+            .map_err(|e| anyhow!(e))?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ClimateEntity for OnOffClimate {
+    fn get_entity_id(&self) -> &str {
+        &self.entity_id
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn get_cached_state(&self) -> &Option<ClimateInfo> {
+        &self.info
+    }
+
+    fn update_cached_state(&mut self, climate_info: Option<ClimateInfo>) {
+        self.info = climate_info;
+    }
+
+    fn get_boosted_status(&self) -> &Option<BoostInfo> {
+        &self.boosted
+    }
+    fn set_boost(&mut self, boost: Option<BoostInfo>) {
+        self.boosted = boost;
+    }
+
+    fn set_temperature_sensor(&mut self, sensor: Option<String>) {
+        self.temperature_sensor = sensor;
+    }
+
+    async fn fetch_and_update_state(
+        &mut self,
+        api_client: &ApiClient,
+    ) -> Result<(), anyhow::Error> {
+        let state = api_client.fetch_entity_state(&self.entity_id).await?;
+        let mut info = self.info(state);
+        read_temperature_sensor(api_client, self.temperature_sensor.as_deref(), &mut info).await;
+        self.info = Some(info);
+        Ok(())
+    }
+
+    async fn turn_on(&self, api_client: &ApiClient) -> Result<(), anyhow::Error> {
+        if self.dry_run {
+            println!("  [DRY RUN] Would turn ON: {}", self.entity_id);
+            return Ok(());
+        }
+        println!("  → Turning ON: {}", self.entity_id);
+        self.call_service(api_client, "turn_on").await
+    }
+
+    async fn turn_off(&self, api_client: &ApiClient) -> Result<(), anyhow::Error> {
+        if self.dry_run {
+            println!("  [DRY RUN] Would turn OFF: {}", self.entity_id);
+            return Ok(());
+        }
+        println!("  → Turning OFF: {}", self.entity_id);
+        self.call_service(api_client, "turn_off").await
+    }
+}
@@ -0,0 +1,128 @@
+use crate::schedule::HeatingState;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// First-order model of a room: the heater adds heat at a fixed rate while the room loses heat
+/// in proportion to how much warmer it is than outside
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermalModel {
+    /// Temperature rise per hour the heater alone would cause (°C/h)
+    pub heat_up_rate: f64,
+    /// Fraction of the indoor/outdoor difference lost per hour
+    pub heat_loss_rate: f64,
+    pub outdoor_temperature: f64,
+}
+
+impl Default for ThermalModel {
+    fn default() -> Self {
+        ThermalModel {
+            heat_up_rate: 3.0,
+            heat_loss_rate: 0.1,
+            outdoor_temperature: 5.0,
+        }
+    }
+}
+
+impl ThermalModel {
+    /// Temperature after `hours` starting from `temperature`, solved exactly rather than stepped
+    pub fn temperature_after(&self, temperature: f64, heating: bool, hours: f64) -> f64 {
+        let heat_input = if heating { self.heat_up_rate } else { 0.0 };
+
+        if self.heat_loss_rate <= 0.0 {
+            return temperature + heat_input * hours;
+        }
+
+        // The room settles where heat input balances heat loss
+        let equilibrium = self.outdoor_temperature + heat_input / self.heat_loss_rate;
+        equilibrium + (temperature - equilibrium) * (-self.heat_loss_rate * hours).exp()
+    }
+}
+
+/// Simulated device behind a `MockClimate`: its hvac state and how the room temperature evolves
+#[derive(Debug, Clone)]
+pub struct SimulatedRoom {
+    pub model: ThermalModel,
+    pub state: HeatingState,
+    pub temperature: f64,
+    last_update: Option<DateTime<Local>>,
+}
+
+impl SimulatedRoom {
+    pub fn new(model: ThermalModel, state: HeatingState, temperature: f64) -> Self {
+        SimulatedRoom {
+            model,
+            state,
+            temperature,
+            last_update: None,
+        }
+    }
+
+    /// Move the simulation forward to `now`
+    pub fn advance(&mut self, now: DateTime<Local>) {
+        if let Some(last_update) = self.last_update
+            && now > last_update
+        {
+            let hours = (now - last_update).num_milliseconds() as f64 / 3_600_000.0;
+            self.temperature = self.model.temperature_after(
+                self.temperature,
+                self.state == HeatingState::On,
+                hours,
+            );
+        }
+        if self.last_update.is_none_or(|last_update| now > last_update) {
+            self.last_update = Some(now);
+        }
+    }
+
+    /// Switch the heater, accounting for the time spent in the previous state first
+    pub fn set_state(&mut self, state: HeatingState, now: DateTime<Local>) {
+        self.advance(now);
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_heating_warms_and_idle_cools_towards_outdoor() {
+        let model = ThermalModel {
+            heat_up_rate: 2.0,
+            heat_loss_rate: 0.0,
+            outdoor_temperature: 5.0,
+        };
+        assert_eq!(model.temperature_after(18.0, true, 1.5), 21.0);
+
+        let model = ThermalModel {
+            heat_up_rate: 2.0,
+            heat_loss_rate: 0.5,
+            outdoor_temperature: 5.0,
+        };
+        let cooled = model.temperature_after(20.0, false, 2.0);
+        assert!(cooled < 20.0 && cooled > 5.0);
+        // After a long time heating the room settles at outdoor + heat_up_rate / heat_loss_rate
+        assert!((model.temperature_after(20.0, true, 1000.0) - 9.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_simulated_room_accounts_for_time_in_each_state() {
+        let start = Local.with_ymd_and_hms(2025, 1, 15, 6, 0, 0).unwrap();
+        let model = ThermalModel {
+            heat_up_rate: 2.0,
+            heat_loss_rate: 0.0,
+            outdoor_temperature: 5.0,
+        };
+        let mut room = SimulatedRoom::new(model, HeatingState::Off, 18.0);
+
+        room.advance(start);
+        room.set_state(HeatingState::On, start + Duration::hours(1));
+        room.set_state(HeatingState::Off, start + Duration::hours(2));
+        room.advance(start + Duration::hours(3));
+
+        // Only the hour spent heating counts
+        assert_eq!(room.temperature, 20.0);
+        assert_eq!(room.state, HeatingState::Off);
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Duration, Local};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Source of the current time, so scheduling and boost logic can run against a simulated clock
+pub trait Clock: Send + Sync + Debug {
+    fn now(&self) -> DateTime<Local>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// A clock that only moves when told to; clones share the same time
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<DateTime<Local>>>,
+}
+
+impl MockClock {
+    pub fn new(start: DateTime<Local>) -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn set(&self, time: DateTime<Local>) {
+        *self.now.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// A time on the fixed day tests run on, 15 January 2025
+#[cfg(test)]
+pub(crate) fn at(hour: u32, minute: u32) -> DateTime<Local> {
+    use chrono::TimeZone;
+    Local
+        .with_ymd_and_hms(2025, 1, 15, hour, minute, 0)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_mock_clock_clones_share_time() {
+        let start = Local.with_ymd_and_hms(2025, 1, 15, 6, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        let shared: SharedClock = Arc::new(clock.clone());
+
+        clock.advance(Duration::minutes(90));
+        assert_eq!(shared.now(), start + Duration::minutes(90));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+}
@@ -1,24 +1,135 @@
+use crate::schedule::ScheduleEntry;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+/// Optional per-entity settings, keyed by entity ID in the entities file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntitySettings {
+    /// Power drawn while heating, used for energy estimates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_kw: Option<f64>,
+    /// How the scheduler decides when to heat
+    #[serde(default, skip_serializing_if = "ControlMode::is_on_off")]
+    pub control_mode: ControlMode,
+    /// Temperature held in thermostat mode when the schedule entry doesn't set one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_temperature: Option<f64>,
+    /// Half-width of the band around the target in thermostat mode, in °C
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hysteresis: Option<f64>,
+    /// Shortest time heating stays on once started, to protect boilers from short-cycling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_on_minutes: Option<u32>,
+    /// Shortest time heating stays off once stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_off_minutes: Option<u32>,
+    /// Heating is forced on below this, instead of the global frost protection temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frost_protection_temperature: Option<f64>,
+    /// Pause heating while a window is open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_window: Option<OpenWindowSettings>,
+    /// Start heating early so the room reaches its target when an On entry begins
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optimum_start: bool,
+    /// `sensor` reporting the room temperature, used instead of the device's own reading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_sensor: Option<String>,
+}
+
+/// How to tell that a window is open; either form can be used on its own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenWindowSettings {
+    /// `binary_sensor` window contact; heating pauses while it's on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor: Option<String>,
+    /// A fall of at least this many °C within `drop_minutes` counts as an open window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop_celsius: Option<f64>,
+    #[serde(default = "default_drop_minutes")]
+    pub drop_minutes: u32,
+    /// How long heating pauses after a temperature drop
+    #[serde(default = "default_pause_minutes")]
+    pub pause_minutes: u32,
+}
+
+fn default_drop_minutes() -> u32 {
+    5
+}
+
+fn default_pause_minutes() -> u32 {
+    30
+}
+
+/// Hysteresis used in thermostat mode when an entity doesn't set its own
+pub const DEFAULT_HYSTERESIS: f64 = 0.5;
+
+/// Whether the scheduler switches an entity straight from the schedule or regulates room temperature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlMode {
+    /// The device follows the schedule's On/Off and regulates itself
+    #[default]
+    OnOff,
+    /// The scheduler heats to a target using `current_temperature`, for dumb on/off devices
+    Thermostat,
+}
+
+impl ControlMode {
+    fn is_on_off(&self) -> bool {
+        *self == ControlMode::OnOff
+    }
+}
+
+impl EntitySettings {
+    /// The temperature to hold during `entry`, if this entity is regulated by the scheduler
+    pub fn thermostat_target(&self, entry: Option<&ScheduleEntry>) -> Option<f64> {
+        if self.control_mode != ControlMode::Thermostat {
+            return None;
+        }
+        self.target_for(entry)
+    }
+
+    /// The temperature wanted during `entry`, whatever the control mode
+    pub fn target_for(&self, entry: Option<&ScheduleEntry>) -> Option<f64> {
+        entry
+            .and_then(|entry| entry.target_temperature)
+            .or(self.target_temperature)
+    }
+}
+
 /// Represents the persisted entities configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EntitiesConfig {
     pub climate_entities: Vec<String>,
+    /// Entities heating domestic hot water, switched by the hot water schedule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hot_water_entities: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub settings: HashMap<String, EntitySettings>,
 }
 
 impl EntitiesConfig {
     pub fn new(climate_entities: Vec<String>) -> Self {
-        Self { climate_entities }
-    }
-
-    pub fn default() -> Self {
         Self {
-            climate_entities: Vec::new(),
+            climate_entities,
+            hot_water_entities: Vec::new(),
+            settings: HashMap::new(),
         }
     }
+
+    pub fn with_hot_water(mut self, hot_water_entities: Vec<String>) -> Self {
+        self.hot_water_entities = hot_water_entities;
+        self
+    }
+
+    pub fn with_settings(mut self, settings: HashMap<String, EntitySettings>) -> Self {
+        self.settings = settings;
+        self
+    }
 }
 
 /// Load entities from a JSON file
@@ -36,8 +147,8 @@ pub fn load_entities<P: AsRef<Path>>(path: P) -> Result<EntitiesConfig> {
 /// Save entities to a JSON file
 pub fn save_entities<P: AsRef<Path>>(entities: &EntitiesConfig, path: P) -> Result<()> {
     let path = path.as_ref();
-    let json = serde_json::to_string_pretty(entities)
-        .context("Failed to serialize entities to JSON")?;
+    let json =
+        serde_json::to_string_pretty(entities).context("Failed to serialize entities to JSON")?;
 
     fs::write(path, json)
         .with_context(|| format!("Failed to write entities file: {}", path.display()))?;
@@ -59,8 +170,7 @@ pub fn load_or_create_default<P: AsRef<Path>>(path: P) -> Result<EntitiesConfig>
         let entities = EntitiesConfig::default();
 
         // Save the default entities for next time
-        save_entities(&entities, path)
-            .context("Failed to save default entities config")?;
+        save_entities(&entities, path).context("Failed to save default entities config")?;
 
         println!("Empty entities config saved to: {}", path.display());
         Ok(entities)
@@ -93,6 +203,35 @@ mod tests {
         assert_eq!(loaded.climate_entities[0], "climate.living_room");
     }
 
+    #[test]
+    fn test_settings_round_trip_and_default_when_missing() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("entities.json");
+
+        // Files written before settings existed still load
+        fs::write(&file_path, r#"{"climate_entities": ["climate.bedroom"]}"#).unwrap();
+        let legacy = load_entities(&file_path).unwrap();
+        assert!(legacy.settings.is_empty());
+        assert!(legacy.hot_water_entities.is_empty());
+
+        let mut settings = HashMap::new();
+        settings.insert(
+            "climate.bedroom".to_string(),
+            EntitySettings {
+                power_kw: Some(1.5),
+                ..Default::default()
+            },
+        );
+        let entities = legacy
+            .with_settings(settings)
+            .with_hot_water(vec!["water_heater.tank".to_string()]);
+        save_entities(&entities, &file_path).unwrap();
+
+        let loaded = load_entities(&file_path).unwrap();
+        assert_eq!(loaded.settings["climate.bedroom"].power_kw, Some(1.5));
+        assert_eq!(loaded.hot_water_entities, ["water_heater.tank"]);
+    }
+
     #[test]
     fn test_load_or_create_default() {
         let dir = tempdir().unwrap();
@@ -105,6 +244,34 @@ mod tests {
 
         // Second call should load existing
         let entities2 = load_or_create_default(&file_path).unwrap();
-        assert_eq!(entities1.climate_entities.len(), entities2.climate_entities.len());
+        assert_eq!(
+            entities1.climate_entities.len(),
+            entities2.climate_entities.len()
+        );
+    }
+
+    #[test]
+    fn test_thermostat_target() {
+        use crate::schedule::{HeatingState, TimePeriod};
+
+        let settings: EntitySettings =
+            serde_json::from_str(r#"{"control_mode": "thermostat", "target_temperature": 20.0}"#)
+                .unwrap();
+        let entry = ScheduleEntry::new("Evening", TimePeriod::new(17, 0, 22, 0), HeatingState::On);
+
+        // The entry's own target wins over the entity's default
+        assert_eq!(settings.thermostat_target(Some(&entry)), Some(20.0));
+        let warmer = entry.with_target_temperature(Some(21.5));
+        assert_eq!(settings.thermostat_target(Some(&warmer)), Some(21.5));
+
+        // On/off entities are never regulated
+        assert_eq!(
+            EntitySettings::default().thermostat_target(Some(&warmer)),
+            None
+        );
+        assert_eq!(
+            serde_json::to_string(&EntitySettings::default()).unwrap(),
+            "{}"
+        );
     }
 }
@@ -1,24 +1,236 @@
 pub mod entities_persistence;
 
+use crate::climate::{RunMode, ThermalModel};
+use crate::config::entities_persistence::EntitySettings;
+use crate::history::RetentionPolicy;
+use crate::mqtt::MqttSettings;
+use crate::mqtt::discovery::DiscoverySettings;
+use crate::presence::{AwayMode, PresenceSettings};
+use crate::scheduler::runtime::OverridePolicy;
+use crate::stats::EnergySettings;
+use crate::timeseries::SamplingPolicy;
+use anyhow::Context;
+use std::collections::HashMap;
 use std::path::Path;
+use std::str::FromStr;
+
+/// Room temperature below which heating is forced on, unless FROST_PROTECTION_TEMPERATURE says otherwise
+pub const DEFAULT_FROST_PROTECTION_TEMPERATURE: f64 = 5.0;
+/// Temperature the schedule's On periods hold while away in eco mode, unless AWAY_TEMPERATURE says otherwise
+pub const DEFAULT_AWAY_TEMPERATURE: f64 = 16.0;
 
 pub struct Config {
     pub ha_url: String,
     pub ha_token: String,
     pub climate_entities: Vec<String>,
+    /// Entities on the hot water channel, from the entities file
+    pub hot_water_entities: Vec<String>,
     pub data_path: String,
+    pub history_retention: RetentionPolicy,
+    pub temperature_sampling: SamplingPolicy,
+    pub entity_settings: HashMap<String, EntitySettings>,
+    pub energy: EnergySettings,
+    pub run_mode: RunMode,
+    pub mock_thermal_model: ThermalModel,
+    pub bind_address: String,
+    pub scheduler_interval_secs: u64,
+    pub command_verify_delay_secs: u64,
+    pub override_policy: OverridePolicy,
+    pub frost_protection_temperature: Option<f64>,
+    pub optimum_start_max_lead_minutes: u32,
+    /// Set when PRESENCE_ENTITIES lists any trackers
+    pub presence: Option<PresenceSettings>,
+    /// Home Assistant sensor whose attributes list upcoming energy prices
+    pub tariff_sensor: Option<String>,
+    /// Set when MQTT_HOST names a broker to bridge to
+    pub mqtt: Option<MqttSettings>,
+}
+
+/// Read an optional env var, falling back to the default if unset or unparsable
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
+
+/// Read an optional env var, returning None if unset or unparsable
+fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        eprintln!("Warning: Invalid value for {}: {}, ignoring", name, value);
+    }
+    parsed
+}
+
+/// Read a setting that can be turned off with `off` or `none`, keeping `default` if unset or
+/// unparsable
+fn env_or_off<T: FromStr>(name: &str, default: Option<T>) -> Option<T> {
+    let Ok(value) = std::env::var(name) else {
+        return default;
+    };
+    if matches!(value.trim().to_lowercase().as_str(), "off" | "none") {
+        return None;
+    }
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            eprintln!("Warning: Invalid value for {}: {}, ignoring", name, value);
+            default
+        }
+    }
+}
+
+/// Presence settings from PRESENCE_ENTITIES, AWAY_MODE, AWAY_TEMPERATURE and AWAY_DELAY_MINUTES
+fn presence_from_env() -> Option<PresenceSettings> {
+    let trackers: Vec<String> = std::env::var("PRESENCE_ENTITIES")
+        .ok()?
+        .split(',')
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if trackers.is_empty() {
+        return None;
+    }
+    let away_mode = match std::env::var("AWAY_MODE").as_deref().map(str::trim) {
+        Ok("eco") => AwayMode::Eco {
+            temperature: env_or("AWAY_TEMPERATURE", DEFAULT_AWAY_TEMPERATURE),
+        },
+        Ok("off") | Err(_) => AwayMode::Off,
+        Ok(other) => {
+            eprintln!("Warning: Invalid value for AWAY_MODE: {}, using off", other);
+            AwayMode::Off
+        }
+    };
+    Some(PresenceSettings {
+        trackers,
+        away_mode,
+        away_delay: chrono::Duration::minutes(env_or("AWAY_DELAY_MINUTES", 15)),
+    })
+}
+
+/// Home Assistant discovery from MQTT_DISCOVERY_PREFIX, `off` to disable, and MQTT_BOOST_PRESETS
+fn discovery_from_env() -> Option<DiscoverySettings> {
+    let prefix = env_or("MQTT_DISCOVERY_PREFIX", "homeassistant".to_string());
+    if matches!(prefix.as_str(), "off" | "none" | "") {
+        return None;
+    }
+    let boost_presets = match std::env::var("MQTT_BOOST_PRESETS") {
+        Ok(presets) => presets
+            .split(',')
+            .filter_map(|minutes| match minutes.trim().parse::<u8>() {
+                Ok(minutes) if minutes > 0 => Some(minutes),
+                _ => {
+                    eprintln!("Warning: Invalid boost preset {}, ignoring", minutes.trim());
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => vec![30, 60],
+    };
+    Some(DiscoverySettings {
+        prefix,
+        boost_presets,
+    })
+}
+
+/// MQTT bridge settings from MQTT_HOST, MQTT_PORT, MQTT_USERNAME, MQTT_PASSWORD,
+/// MQTT_CLIENT_ID, MQTT_TOPIC_PREFIX and MQTT_PUBLISH_SECONDS
+fn mqtt_from_env() -> Option<MqttSettings> {
+    let host = env_opt::<String>("MQTT_HOST").filter(|host| !host.is_empty())?;
+    Some(MqttSettings {
+        host,
+        port: env_or("MQTT_PORT", 1883),
+        client_id: env_or("MQTT_CLIENT_ID", "ha-heating-scheduler".to_string()),
+        username: env_opt("MQTT_USERNAME"),
+        password: env_opt("MQTT_PASSWORD"),
+        topic_prefix: env_or("MQTT_TOPIC_PREFIX", "heating".to_string()),
+        publish_interval: std::time::Duration::from_secs(env_or("MQTT_PUBLISH_SECONDS", 15).max(1)),
+        discovery: discovery_from_env(),
+    })
 }
 
 impl Config {
-    pub fn new(ha_url: &str, ha_token: &str, climate_entities: Vec<String>, data_path: String) -> Self {
+    pub fn new(
+        ha_url: &str,
+        ha_token: &str,
+        climate_entities: Vec<String>,
+        data_path: String,
+    ) -> Self {
         Config {
             ha_url: ha_url.to_string(),
             ha_token: ha_token.to_string(),
             climate_entities,
+            hot_water_entities: Vec::new(),
             data_path,
+            history_retention: RetentionPolicy::default(),
+            temperature_sampling: SamplingPolicy::default(),
+            entity_settings: HashMap::new(),
+            energy: EnergySettings::default(),
+            run_mode: RunMode::default(),
+            mock_thermal_model: ThermalModel::default(),
+            bind_address: "0.0.0.0:3000".to_string(),
+            scheduler_interval_secs: 15,
+            command_verify_delay_secs: 2,
+            override_policy: OverridePolicy::default(),
+            frost_protection_temperature: Some(DEFAULT_FROST_PROTECTION_TEMPERATURE),
+            optimum_start_max_lead_minutes: 120,
+            presence: None,
+            tariff_sensor: None,
+            mqtt: None,
         }
     }
 
+    /// Apply the optional settings that have sensible defaults
+    fn with_optional_env(mut self) -> anyhow::Result<Self> {
+        // A mistyped run mode must not quietly fall back to another one
+        if let Ok(run_mode) = std::env::var("RUN_MODE") {
+            self.run_mode = run_mode.parse().context("Invalid RUN_MODE")?;
+        }
+        self.bind_address = env_or("BIND_ADDRESS", self.bind_address.clone());
+        self.scheduler_interval_secs =
+            env_or("SCHEDULER_INTERVAL_SECONDS", self.scheduler_interval_secs).max(1);
+        self.command_verify_delay_secs = env_or(
+            "COMMAND_VERIFY_DELAY_SECONDS",
+            self.command_verify_delay_secs,
+        );
+        self.override_policy = env_or("MANUAL_OVERRIDE", self.override_policy);
+        self.frost_protection_temperature = env_or_off(
+            "FROST_PROTECTION_TEMPERATURE",
+            self.frost_protection_temperature,
+        );
+        self.optimum_start_max_lead_minutes = env_or(
+            "OPTIMUM_START_MAX_LEAD_MINUTES",
+            self.optimum_start_max_lead_minutes,
+        );
+        self.presence = presence_from_env();
+        self.tariff_sensor = env_opt("TARIFF_SENSOR");
+        self.mqtt = mqtt_from_env();
+        let model = ThermalModel::default();
+        self.mock_thermal_model = ThermalModel {
+            heat_up_rate: env_or("MOCK_HEAT_UP_RATE", model.heat_up_rate),
+            heat_loss_rate: env_or("MOCK_HEAT_LOSS_RATE", model.heat_loss_rate),
+            outdoor_temperature: env_or("MOCK_OUTDOOR_TEMPERATURE", model.outdoor_temperature),
+        };
+        let retention = RetentionPolicy::default();
+        self.history_retention = RetentionPolicy {
+            max_age_days: env_or("HISTORY_RETENTION_DAYS", retention.max_age_days),
+            max_events: env_or("HISTORY_MAX_EVENTS", retention.max_events),
+        };
+        let sampling = SamplingPolicy::default();
+        self.temperature_sampling = SamplingPolicy {
+            sample_interval_secs: env_or(
+                "TEMPERATURE_SAMPLE_SECONDS",
+                sampling.sample_interval_secs,
+            )
+            .max(1),
+            max_age_days: env_or("TEMPERATURE_HISTORY_DAYS", sampling.max_age_days),
+        };
+        self.energy = EnergySettings {
+            default_power_kw: env_opt("HEATING_POWER_KW"),
+            price_per_kwh: env_opt("ENERGY_PRICE_PER_KWH"),
+        };
+        Ok(self)
+    }
+
     /// Load config from environment variables only (legacy method)
     /// Climate entities are loaded from CLIMATE_ENTITY env var
     pub fn from_env() -> Self {
@@ -27,8 +239,13 @@ impl Config {
         let ha_token = std::env::var("HA_TOKEN").expect("HA_TOKEN must be set");
         let climate_entity = std::env::var("CLIMATE_ENTITY").expect("CLIMATE_ENTITY must be set");
         let data_path = std::env::var("DATA_PATH").expect("DATA_PATH must be set");
-        let climates: Vec<String> = climate_entity.split(",").map(|s| s.trim().to_owned()).collect();
+        let climates: Vec<String> = climate_entity
+            .split(",")
+            .map(|s| s.trim().to_owned())
+            .collect();
         Config::new(&ha_url, &ha_token, climates, data_path)
+            .with_optional_env()
+            .expect("Invalid configuration")
     }
 
     /// Load config with entities from persisted file
@@ -48,15 +265,30 @@ impl Config {
             // Fall back to environment variable if no entities in file
             println!("No entities in persisted file, checking environment variable...");
             if let Ok(climate_entity) = std::env::var("CLIMATE_ENTITY") {
-                let climates: Vec<String> = climate_entity.split(",").map(|s| s.trim().to_owned()).collect();
-                println!("Loaded {} entities from CLIMATE_ENTITY env var", climates.len());
+                let climates: Vec<String> = climate_entity
+                    .split(",")
+                    .map(|s| s.trim().to_owned())
+                    .collect();
+                println!(
+                    "Loaded {} entities from CLIMATE_ENTITY env var",
+                    climates.len()
+                );
 
                 // Save to file so next time we don't need the env var
-                let new_config = entities_persistence::EntitiesConfig::new(climates.clone());
-                if let Err(e) = entities_persistence::save_entities(&new_config, &entities_file_path) {
-                    eprintln!("Warning: Failed to save entities from env var to file: {}", e);
+                let new_config = entities_persistence::EntitiesConfig::new(climates.clone())
+                    .with_hot_water(entities_config.hot_water_entities.clone());
+                if let Err(e) =
+                    entities_persistence::save_entities(&new_config, &entities_file_path)
+                {
+                    eprintln!(
+                        "Warning: Failed to save entities from env var to file: {}",
+                        e
+                    );
                 } else {
-                    println!("Saved entities to {} for future use", entities_file_path.display());
+                    println!(
+                        "Saved entities to {} for future use",
+                        entities_file_path.display()
+                    );
                     println!("You can now remove CLIMATE_ENTITY from your .env file");
                 }
 
@@ -66,10 +298,17 @@ impl Config {
                 Vec::new()
             }
         } else {
-            println!("Loaded {} entities from persisted file", entities_config.climate_entities.len());
+            println!(
+                "Loaded {} entities from persisted file",
+                entities_config.climate_entities.len()
+            );
             entities_config.climate_entities
         };
 
-        Ok(Config::new(&ha_url, &ha_token, climate_entities, data_path))
+        let mut config =
+            Config::new(&ha_url, &ha_token, climate_entities, data_path).with_optional_env()?;
+        config.entity_settings = entities_config.settings;
+        config.hot_water_entities = entities_config.hot_water_entities;
+        Ok(config)
     }
 }
@@ -0,0 +1,315 @@
+use crate::presence::AwayMode;
+use crate::schedule::{HeatingState, ScheduleEntry};
+use crate::scheduler::runtime::WindowSource;
+use crate::tariff::HeatingSlot;
+use chrono::{DateTime, Duration, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod persistence;
+
+/// Limits applied to the event journal so it doesn't grow forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub max_age_days: i64,
+    pub max_events: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            max_age_days: 30,
+            max_events: 10_000,
+        }
+    }
+}
+
+/// What happened, with enough detail to explain why
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The scheduler changed an entity's state
+    SchedulerDecision {
+        active_entry: Option<ScheduleEntry>,
+        previous_state: HeatingState,
+        desired_state: HeatingState,
+        boosted: bool,
+        /// The command was only logged, the entity wasn't changed
+        #[serde(default)]
+        dry_run: bool,
+    },
+    /// A boost was requested through the API. The times are local clock times on the day of the
+    /// event, so an end before the start is on the next day.
+    Boost {
+        boost_start: NaiveTime,
+        boost_end: NaiveTime,
+    },
+    /// A boost ran out and was cleared by the scheduler, or was cancelled
+    BoostEnded,
+    /// The entity changed state without the scheduler asking it to
+    ManualChange {
+        expected_state: HeatingState,
+        observed_state: HeatingState,
+    },
+    /// A manual change is being honoured instead of the schedule
+    ManualOverride {
+        state: HeatingState,
+        until: Option<DateTime<Local>>,
+        user_id: Option<String>,
+    },
+    /// A manual override ran out or was cleared through the API
+    ManualOverrideEnded,
+    /// The room fell below its frost threshold and heating was forced on
+    FrostProtection { temperature: f64, threshold: f64 },
+    /// The room warmed up enough for frost protection to let go
+    FrostProtectionEnded,
+    /// Heating was paused for an open window
+    WindowOpen { source: WindowSource },
+    /// The window closed or the pause ran out
+    WindowClosed,
+    /// The scheduler was told to leave the entity alone
+    Paused,
+    /// The scheduler manages the entity again
+    Resumed,
+    /// Everyone has been out for the away delay and the away behaviour took over
+    Away { away_mode: AwayMode },
+    /// Someone came home and the schedule applies again
+    Home,
+    /// It's warm enough outside that the schedule's On periods are skipped
+    WarmWeather {
+        outdoor_temperature: f64,
+        cutoff: f64,
+    },
+    /// It cooled down outside and On periods heat again
+    WarmWeatherEnded,
+    /// The outdoor sensor couldn't be read for too long, so weather compensation stopped
+    /// until it reads again
+    OutdoorReadingStale {
+        sensor: String,
+        read_at: DateTime<Local>,
+    },
+    /// The slots a flexible entry heats in were picked for its current period
+    FlexibleHeatingPlanned {
+        entry_name: String,
+        slots: Vec<HeatingSlot>,
+        average_price: Option<f64>,
+    },
+    /// Heating started early so the room reaches the next On entry's target on time
+    PreheatStarted {
+        target_time: DateTime<Local>,
+        target_temperature: f64,
+        heat_up_rate: f64,
+    },
+    /// Fetching state or applying an action failed. An entity that can't be read or is
+    /// unavailable is journaled once when the outage starts.
+    Error { message: String },
+    /// An entity that couldn't be read or was unavailable is back
+    Reachable,
+    /// Commands repeatedly failed to bring the entity to the desired state
+    Degraded {
+        desired_state: HeatingState,
+        failed_attempts: u32,
+    },
+    /// A degraded entity reached the desired state again
+    Recovered,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEvent {
+    pub id: Uuid,
+    pub timestamp: DateTime<Local>,
+    pub entity_id: Option<String>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl HistoryEvent {
+    pub fn new(timestamp: DateTime<Local>, entity_id: Option<String>, kind: EventKind) -> Self {
+        HistoryEvent {
+            id: Uuid::new_v4(),
+            timestamp,
+            entity_id,
+            kind,
+        }
+    }
+}
+
+/// Filters for querying the event journal, all optional
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    pub entity_id: Option<String>,
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+}
+
+/// Journal of heating events, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventLog {
+    #[serde(skip)]
+    pub retention: RetentionPolicy,
+    pub events: Vec<HistoryEvent>,
+    /// Events were recorded since the journal was last written to disk
+    #[serde(skip)]
+    pub unsaved: bool,
+    /// Events recorded since the scheduler last turned them into notifications
+    #[serde(skip)]
+    pub unnotified: Vec<HistoryEvent>,
+}
+
+impl EventLog {
+    pub fn new(retention: RetentionPolicy) -> Self {
+        EventLog {
+            retention,
+            events: Vec::new(),
+            unsaved: false,
+            unnotified: Vec::new(),
+        }
+    }
+
+    /// Append an event and apply the retention limits
+    pub fn record(
+        &mut self,
+        timestamp: DateTime<Local>,
+        entity_id: Option<String>,
+        kind: EventKind,
+    ) {
+        let event = HistoryEvent::new(timestamp, entity_id, kind);
+        self.unnotified.push(event.clone());
+        self.events.push(event);
+        self.prune(&timestamp);
+        self.unsaved = true;
+    }
+
+    /// Events recorded since the last call, whether by the scheduler or an API call, so each
+    /// is considered for a notification exactly once
+    pub fn take_unnotified(&mut self) -> Vec<HistoryEvent> {
+        std::mem::take(&mut self.unnotified)
+    }
+
+    /// Drop events older than the retention window, then the oldest events over the cap
+    pub fn prune(&mut self, now: &DateTime<Local>) {
+        let cutoff = *now - Duration::days(self.retention.max_age_days);
+        self.events.retain(|e| e.timestamp >= cutoff);
+
+        if self.events.len() > self.retention.max_events {
+            let excess = self.events.len() - self.retention.max_events;
+            self.events.drain(..excess);
+        }
+    }
+
+    /// Return events matching the query, oldest first
+    pub fn query(&self, query: &HistoryQuery) -> Vec<HistoryEvent> {
+        self.events
+            .iter()
+            .filter(|e| match &query.entity_id {
+                Some(entity_id) => e.entity_id.as_deref() == Some(entity_id.as_str()),
+                None => true,
+            })
+            .filter(|e| query.from.is_none_or(|from| e.timestamp >= from))
+            .filter(|e| query.to.is_none_or(|to| e.timestamp <= to))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 1, 15, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_record_applies_max_events() {
+        let mut log = EventLog::new(RetentionPolicy {
+            max_age_days: 30,
+            max_events: 2,
+        });
+
+        log.record(at(1), None, EventKind::BoostEnded);
+        log.record(at(2), None, EventKind::BoostEnded);
+        log.record(at(3), None, EventKind::BoostEnded);
+
+        // Oldest event should have been dropped
+        assert_eq!(log.events.len(), 2);
+        assert_eq!(log.events[0].timestamp, at(2));
+    }
+
+    #[test]
+    fn test_prune_drops_old_events() {
+        let mut log = EventLog::new(RetentionPolicy {
+            max_age_days: 1,
+            max_events: 100,
+        });
+
+        log.record(at(1), None, EventKind::BoostEnded);
+        log.prune(&(at(1) + Duration::days(2)));
+
+        assert!(log.events.is_empty());
+    }
+
+    #[test]
+    fn test_query_filters_entity_and_time_range() {
+        let mut log = EventLog::new(RetentionPolicy::default());
+        log.record(
+            at(1),
+            Some("climate.bedroom".to_string()),
+            EventKind::BoostEnded,
+        );
+        log.record(
+            at(2),
+            Some("climate.living_room".to_string()),
+            EventKind::BoostEnded,
+        );
+        log.record(
+            at(3),
+            Some("climate.bedroom".to_string()),
+            EventKind::BoostEnded,
+        );
+        log.record(
+            at(4),
+            None,
+            EventKind::Error {
+                message: "boom".to_string(),
+            },
+        );
+
+        let bedroom = log.query(&HistoryQuery {
+            entity_id: Some("climate.bedroom".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(bedroom.len(), 2);
+
+        let late_bedroom = log.query(&HistoryQuery {
+            entity_id: Some("climate.bedroom".to_string()),
+            from: Some(at(2)),
+            to: None,
+        });
+        assert_eq!(late_bedroom.len(), 1);
+        assert_eq!(late_bedroom[0].timestamp, at(3));
+
+        let window = log.query(&HistoryQuery {
+            entity_id: None,
+            from: Some(at(2)),
+            to: Some(at(3)),
+        });
+        assert_eq!(window.len(), 2);
+    }
+
+    #[test]
+    fn test_recorded_events_are_taken_for_notifying_once() {
+        let mut log = EventLog::new(RetentionPolicy::default());
+        log.record(
+            at(8),
+            Some("climate.bedroom".to_string()),
+            EventKind::BoostEnded,
+        );
+        log.record(at(9), None, EventKind::Home);
+
+        let unnotified = log.take_unnotified();
+        assert_eq!(unnotified, log.events);
+        assert!(log.take_unnotified().is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use super::{EventLog, RetentionPolicy};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Load the event journal from a JSON file
+pub fn load_history<P: AsRef<Path>>(path: P) -> Result<EventLog> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read history file: {}", path.display()))?;
+
+    let history: EventLog = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse history JSON from: {}", path.display()))?;
+
+    Ok(history)
+}
+
+/// Save the event journal to a JSON file
+pub fn save_history<P: AsRef<Path>>(history: &EventLog, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string(history).context("Failed to serialize history to JSON")?;
+
+    fs::write(path, json)
+        .with_context(|| format!("Failed to write history file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Load the event journal from file, or start an empty one if it doesn't exist
+pub fn load_or_create_default<P: AsRef<Path>>(
+    path: P,
+    retention: RetentionPolicy,
+) -> Result<EventLog> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        println!("Loading history from: {}", path.display());
+        let mut history = load_history(path)?;
+        history.retention = retention;
+        history.prune(&chrono::Local::now());
+        Ok(history)
+    } else {
+        println!("No history file found at: {}", path.display());
+
+        let history = EventLog::new(retention);
+        save_history(&history, path).context("Failed to save empty history")?;
+
+        println!("Empty history saved to: {}", path.display());
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EventKind;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_load_history() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("history.json");
+
+        let mut history = EventLog::new(RetentionPolicy::default());
+        history.record(
+            chrono::Local::now(),
+            Some("climate.bedroom".to_string()),
+            EventKind::Error {
+                message: "timeout".to_string(),
+            },
+        );
+
+        save_history(&history, &file_path).unwrap();
+        let loaded = load_history(&file_path).unwrap();
+
+        assert_eq!(loaded.events, history.events);
+    }
+
+    #[test]
+    fn test_load_or_create_default_applies_retention() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("history.json");
+
+        let history = load_or_create_default(&file_path, RetentionPolicy::default()).unwrap();
+        assert!(file_path.exists());
+        assert!(history.events.is_empty());
+
+        let retention = RetentionPolicy {
+            max_age_days: 7,
+            max_events: 50,
+        };
+        let loaded = load_or_create_default(&file_path, retention).unwrap();
+        assert_eq!(loaded.retention, retention);
+    }
+}
@@ -1,11 +1,31 @@
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
 pub mod api_client;
 pub mod climate;
+pub mod clock;
 pub mod config;
+pub mod history;
+pub mod mqtt;
+pub mod notify;
+pub mod presence;
 pub mod schedule;
 pub mod server;
+pub mod simulation;
+pub mod stats;
+pub mod status;
+pub mod tariff;
+pub mod timeseries;
+pub mod weather;
 
 pub mod scheduler;
 
-pub type ScheduleState = Arc<RwLock<schedule::Schedule>>;
\ No newline at end of file
+pub type ScheduleState = Arc<RwLock<schedule::Schedule>>;
+pub type HistoryState = Arc<RwLock<history::EventLog>>;
+pub type TemperatureHistoryState = Arc<RwLock<timeseries::TemperatureHistory>>;
+pub type PresenceState = Arc<RwLock<presence::Presence>>;
+pub type WeatherState = Arc<RwLock<Option<weather::OutdoorWeather>>>;
+pub type TariffState = Arc<RwLock<tariff::TariffPlans>>;
+pub type EntityRuntimeState = Arc<RwLock<HashMap<String, scheduler::runtime::EntityRuntime>>>;
+pub type EntitySettingsState =
+    Arc<RwLock<HashMap<String, config::entities_persistence::EntitySettings>>>;
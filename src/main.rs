@@ -1,17 +1,23 @@
-use ha_heating_scheduler::climate::ClimateEntityWrapper;
-#[cfg(not(debug_assertions))]
-use ha_heating_scheduler::climate::DefaultClimate;
-#[cfg(debug_assertions)]
-use ha_heating_scheduler::climate::MockClimate;
+use ha_heating_scheduler::climate::{ClimateEntityFactory, ClimateEntityWrapper};
+use ha_heating_scheduler::clock::{SharedClock, SystemClock};
 use ha_heating_scheduler::config;
+use ha_heating_scheduler::history::persistence as history_persistence;
+use ha_heating_scheduler::mqtt::run_mqtt_bridge;
+use ha_heating_scheduler::notify::persistence as notify_persistence;
+use ha_heating_scheduler::presence::Presence;
 use ha_heating_scheduler::schedule::persistence;
-#[cfg(debug_assertions)]
-use ha_heating_scheduler::schedule::HeatingState;
-use ha_heating_scheduler::scheduler::{run_scheduler, SchedulerState};
-use ha_heating_scheduler::server::start_server;
-use ha_heating_scheduler::{api_client, ScheduleState};
+use ha_heating_scheduler::scheduler::{SchedulerState, run_scheduler};
+use ha_heating_scheduler::server::{AppState, start_server};
+use ha_heating_scheduler::tariff::{TariffPlans, persistence as tariff_persistence};
+use ha_heating_scheduler::timeseries::persistence as timeseries_persistence;
+use ha_heating_scheduler::{
+    EntityRuntimeState, EntitySettingsState, HistoryState, PresenceState, ScheduleState,
+    TariffState, TemperatureHistoryState, WeatherState, api_client,
+};
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -26,29 +32,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     std::fs::create_dir_all(data_dir)?;
 
     let schedule_file_path = data_dir.join("schedule.json");
+    let hot_water_schedule_file_path = data_dir.join("hot_water_schedule.json");
     let entities_file_path = data_dir.join("entities.json");
+    let history_file_path = data_dir.join("history.json");
+    let temperature_history_file_path = data_dir.join("temperature_history.json");
+    let tariff_file_path = data_dir.join("tariff.json");
+    let notifications_file_path = data_dir.join("notifications.json");
 
     let schedule = persistence::load_or_create_default(&schedule_file_path)?;
-    // Use mock climate entities in debug mode, real ones in release mode
-    let climate_entities: Arc<RwLock<Vec<ClimateEntityWrapper>>> = {
-        #[cfg(debug_assertions)]
-        {
-            println!("=== DEBUG MODE: Using Mock Climate Entities ===");
-            Arc::new(RwLock::new(config.climate_entities
-                .into_iter()
-                .map(|entity_id| ClimateEntityWrapper::Mock(MockClimate::new(entity_id, HeatingState::Off)))
-                .collect()))
-        }
+    let hot_water_schedule =
+        persistence::load_or_create_named(&hot_water_schedule_file_path, "Hot Water")?;
+    let entity_settings: EntitySettingsState =
+        Arc::new(RwLock::new(config.entity_settings.clone()));
+    let history: HistoryState = Arc::new(RwLock::new(history_persistence::load_or_create_default(
+        &history_file_path,
+        config.history_retention,
+    )?));
+    let temperature_history: TemperatureHistoryState =
+        Arc::new(RwLock::new(timeseries_persistence::load_or_create_default(
+            &temperature_history_file_path,
+            config.temperature_sampling,
+        )?));
+    let tariff: TariffState = Arc::new(RwLock::new(TariffPlans {
+        tariff: tariff_persistence::load_or_default(&tariff_file_path)?,
+        ..Default::default()
+    }));
+    let notifications = notify_persistence::load_if_present(&notifications_file_path)?;
+    let clock: SharedClock = Arc::new(SystemClock);
+    let entity_factory = ClimateEntityFactory::new(
+        config.run_mode,
+        config.mock_thermal_model,
+        Arc::clone(&clock),
+    );
 
-        #[cfg(not(debug_assertions))]
-        {
-            println!("=== PRODUCTION MODE: Using Real Climate Entities ===");
-            Arc::new(RwLock::new(config.climate_entities
-                .into_iter()
-                .map(|entity_id| ClimateEntityWrapper::Real(DefaultClimate::new(entity_id)))
-                .collect()))
-        }
-    };
+    println!("=== {} MODE ===", config.run_mode);
+    let climate_entities: Arc<RwLock<Vec<ClimateEntityWrapper>>> = Arc::new(RwLock::new(
+        entity_factory.create_all(config.climate_entities),
+    ));
+    let hot_water_entities: Arc<RwLock<Vec<ClimateEntityWrapper>>> = Arc::new(RwLock::new(
+        entity_factory.create_all(config.hot_water_entities),
+    ));
 
     println!("=== Loaded Schedule: {} ===", schedule.name);
     println!("Total entries: {}", schedule.entries.len());
@@ -63,15 +86,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
     println!();
     let schedule: ScheduleState = Arc::new(RwLock::new(schedule));
-    let api_task = tokio::spawn(start_server(
-        Arc::clone(&schedule),
-        schedule_file_path.to_string_lossy().to_string(),
-        Arc::clone(&climate_entities),
-        entities_file_path.to_string_lossy().to_string(),
-    ));
-
+    let hot_water_schedule: ScheduleState = Arc::new(RwLock::new(hot_water_schedule));
+    let history_file_path = history_file_path.to_string_lossy().to_string();
+    let runtime: EntityRuntimeState = Arc::new(RwLock::new(HashMap::new()));
+    // Known up front so the house can be declared away before the trackers are first read
+    let presence: PresenceState = Arc::new(RwLock::new(Presence {
+        away_mode: config.presence.as_ref().map(|settings| settings.away_mode),
+        ..Default::default()
+    }));
+    let weather: WeatherState = Arc::new(RwLock::new(None));
+    let app_state = AppState {
+        schedule: Arc::clone(&schedule),
+        schedule_file_path: schedule_file_path.to_string_lossy().to_string(),
+        climate_entities: Arc::clone(&climate_entities),
+        entities_file_path: entities_file_path.to_string_lossy().to_string(),
+        hot_water_schedule: Arc::clone(&hot_water_schedule),
+        hot_water_schedule_file_path: hot_water_schedule_file_path.to_string_lossy().to_string(),
+        hot_water_entities: Arc::clone(&hot_water_entities),
+        history: Arc::clone(&history),
+        temperature_history: Arc::clone(&temperature_history),
+        entity_settings: Arc::clone(&entity_settings),
+        energy: config.energy,
+        entity_factory,
+        clock: Arc::clone(&clock),
+        runtime: Arc::clone(&runtime),
+        presence: Arc::clone(&presence),
+        weather: Arc::clone(&weather),
+        tariff: Arc::clone(&tariff),
+    };
+    if let Some(mqtt) = config.mqtt {
+        tokio::spawn(run_mqtt_bridge(mqtt, app_state.clone()));
+    }
+    let api_task = tokio::spawn(start_server(app_state, config.bind_address));
 
-    let scheduler_task = tokio::spawn(run_scheduler(SchedulerState { api_client, schedule, climate_entities: Arc::clone(&climate_entities) }));
+    let scheduler_task = tokio::spawn(run_scheduler(SchedulerState {
+        api_client,
+        schedule,
+        climate_entities: Arc::clone(&climate_entities),
+        hot_water_schedule,
+        hot_water_entities,
+        history,
+        history_file_path,
+        temperature_history,
+        temperature_history_file_path: temperature_history_file_path.to_string_lossy().to_string(),
+        clock,
+        tick_interval: Duration::from_secs(config.scheduler_interval_secs),
+        verify_delay: Duration::from_secs(config.command_verify_delay_secs),
+        runtime,
+        override_policy: config.override_policy,
+        entity_settings,
+        frost_protection_temperature: config.frost_protection_temperature,
+        optimum_start_max_lead: chrono::Duration::minutes(
+            config.optimum_start_max_lead_minutes as i64,
+        ),
+        presence_settings: config.presence,
+        presence,
+        weather,
+        tariff_sensor: config.tariff_sensor,
+        tariff,
+        notifications,
+    }));
 
     tokio::try_join!(api_task, scheduler_task).unwrap();
     Ok(())
@@ -0,0 +1,247 @@
+//! Home Assistant MQTT discovery, so the scheduler's own controls show up as devices: one for
+//! the scheduler with its next heating and hot water transitions, and one per heating zone or
+//! hot water entity with a "scheduler enabled" switch and boost buttons. Every control sends the
+//! bridge's ordinary commands.
+
+use crate::mqtt::{HOT_WATER_TOPIC, MqttSettings};
+use crate::status::SchedulerStatus;
+use serde_json::{Value, json};
+
+/// What to announce to Home Assistant
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoverySettings {
+    /// Home Assistant's discovery prefix, `homeassistant` unless changed there
+    pub prefix: String,
+    /// Each zone gets a boost button for each of these lengths, in minutes
+    pub boost_presets: Vec<u8>,
+}
+
+/// Shown as the attributes of the next transition sensor
+const NEXT_TRANSITION_ATTRIBUTES: &str = "{{ {'heating_state': value_json.heating_state, \
+     'entry': value_json.entry.name} | tojson if value_json else '{}' }}";
+
+/// Topic IDs may only use letters, digits, `_` and `-`
+fn object_id(id: &str) -> String {
+    id.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// The retained discovery config for every control, by config topic
+pub fn discovery_messages(
+    settings: &MqttSettings,
+    discovery: &DiscoverySettings,
+    status: &SchedulerStatus,
+) -> Vec<(String, String)> {
+    let prefix = &settings.topic_prefix;
+    let node_id = object_id(&settings.client_id);
+    let availability_topic = format!("{}/availability", prefix);
+    let config_topic = |component: &str, object: &str| {
+        format!(
+            "{}/{}/{}/{}/config",
+            discovery.prefix, component, node_id, object
+        )
+    };
+    let with_common = |mut config: Value, unique_id: String, device: &Value| {
+        config["unique_id"] = json!(unique_id);
+        config["availability_topic"] = json!(availability_topic);
+        config["device"] = device.clone();
+        config.to_string()
+    };
+
+    let scheduler_device = json!({
+        "identifiers": [node_id],
+        "name": "Heating scheduler",
+        "model": "ha-heating-scheduler",
+    });
+    let next_transition_sensor = |name: &str, object: &str, state_topic: String| {
+        (
+            config_topic("sensor", object),
+            with_common(
+                json!({
+                    "name": name,
+                    "device_class": "timestamp",
+                    "state_topic": state_topic,
+                    "value_template": "{{ value_json.time if value_json else none }}",
+                    "json_attributes_topic": state_topic,
+                    "json_attributes_template": NEXT_TRANSITION_ATTRIBUTES,
+                }),
+                format!("{}_{}", node_id, object),
+                &scheduler_device,
+            ),
+        )
+    };
+    let mut messages = vec![next_transition_sensor(
+        "Next transition",
+        "next_transition",
+        format!("{}/next_transition", prefix),
+    )];
+    if status.hot_water.is_some() {
+        messages.push(next_transition_sensor(
+            "Next hot water transition",
+            "hot_water_next_transition",
+            format!("{}/{}/next_transition", prefix, HOT_WATER_TOPIC),
+        ));
+    }
+
+    for entity in status.all_entities() {
+        let zone = object_id(&entity.entity_id);
+        let zone_topic = |name: &str| format!("{}/{}/{}", prefix, entity.entity_id, name);
+        let zone_device = json!({
+            "identifiers": [format!("{}_{}", node_id, zone)],
+            "name": entity.entity_id,
+            "via_device": node_id,
+        });
+
+        let object = format!("{}_enabled", zone);
+        messages.push((
+            config_topic("switch", &object),
+            with_common(
+                json!({
+                    "name": "Scheduler enabled",
+                    "state_topic": zone_topic("enabled"),
+                    "command_topic": zone_topic("enabled/set"),
+                }),
+                format!("{}_{}", node_id, object),
+                &zone_device,
+            ),
+        ));
+        for minutes in &discovery.boost_presets {
+            let object = format!("{}_boost_{}", zone, minutes);
+            messages.push((
+                config_topic("button", &object),
+                with_common(
+                    json!({
+                        "name": format!("Boost {} min", minutes),
+                        "command_topic": zone_topic("boost/set"),
+                        "payload_press": minutes.to_string(),
+                    }),
+                    format!("{}_{}", node_id, object),
+                    &zone_device,
+                ),
+            ));
+        }
+        let object = format!("{}_cancel_boost", zone);
+        messages.push((
+            config_topic("button", &object),
+            with_common(
+                json!({
+                    "name": "Cancel boost",
+                    "command_topic": zone_topic("cancel_boost/set"),
+                }),
+                format!("{}_{}", node_id, object),
+                &zone_device,
+            ),
+        ));
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::HeatingState;
+    use crate::status::{EntityStatus, HotWaterStatus};
+    use chrono::{Local, TimeZone};
+    use std::collections::HashMap;
+
+    fn zone(entity_id: &str) -> EntityStatus {
+        EntityStatus {
+            entity_id: entity_id.to_string(),
+            current_state: Some(HeatingState::Off),
+            current_temperature: None,
+            boost: None,
+            manual_override: None,
+            open_window: None,
+            frost_protection: None,
+            optimum_start: None,
+            paused_since: None,
+            effective_state: HeatingState::Off,
+        }
+    }
+
+    #[test]
+    fn test_discovery_messages() {
+        let settings = MqttSettings {
+            host: "broker".to_string(),
+            port: 1883,
+            client_id: "ha-heating-scheduler".to_string(),
+            username: None,
+            password: None,
+            topic_prefix: "heating".to_string(),
+            publish_interval: std::time::Duration::from_secs(15),
+            discovery: None,
+        };
+        let discovery = DiscoverySettings {
+            prefix: "homeassistant".to_string(),
+            boost_presets: vec![30, 60],
+        };
+        let status = SchedulerStatus {
+            now: Local.with_ymd_and_hms(2025, 1, 15, 12, 0, 0).unwrap(),
+            active_entry: None,
+            scheduled_state: HeatingState::Off,
+            next_transition: None,
+            presence: None,
+            weather: None,
+            flexible_plan: None,
+            entities: vec![zone("climate.bedroom")],
+            hot_water: None,
+        };
+
+        let messages: HashMap<String, Value> = discovery_messages(&settings, &discovery, &status)
+            .into_iter()
+            .map(|(topic, payload)| (topic, serde_json::from_str(&payload).unwrap()))
+            .collect();
+        assert_eq!(messages.len(), 5);
+
+        let sensor = &messages["homeassistant/sensor/ha-heating-scheduler/next_transition/config"];
+        assert_eq!(sensor["state_topic"], "heating/next_transition");
+        assert_eq!(sensor["availability_topic"], "heating/availability");
+
+        let switch =
+            &messages["homeassistant/switch/ha-heating-scheduler/climate_bedroom_enabled/config"];
+        assert_eq!(
+            switch["command_topic"],
+            "heating/climate.bedroom/enabled/set"
+        );
+        assert_eq!(
+            switch["unique_id"],
+            "ha-heating-scheduler_climate_bedroom_enabled"
+        );
+        assert_eq!(switch["device"]["via_device"], "ha-heating-scheduler");
+
+        let button =
+            &messages["homeassistant/button/ha-heating-scheduler/climate_bedroom_boost_60/config"];
+        assert_eq!(button["command_topic"], "heating/climate.bedroom/boost/set");
+        assert_eq!(button["payload_press"], "60");
+        // Hot water entities get the same controls, and the channel its own next transition
+        let status = SchedulerStatus {
+            hot_water: Some(HotWaterStatus {
+                active_entry: None,
+                scheduled_state: HeatingState::Off,
+                next_transition: None,
+                entities: vec![zone("water_heater.tank")],
+            }),
+            ..status
+        };
+        let messages: HashMap<String, Value> = discovery_messages(&settings, &discovery, &status)
+            .into_iter()
+            .map(|(topic, payload)| (topic, serde_json::from_str(&payload).unwrap()))
+            .collect();
+        assert_eq!(messages.len(), 10);
+        let sensor =
+            &messages["homeassistant/sensor/ha-heating-scheduler/hot_water_next_transition/config"];
+        assert_eq!(sensor["state_topic"], "heating/hot_water/next_transition");
+        let button = &messages["homeassistant/button/ha-heating-scheduler/water_heater_tank_boost_30/config"];
+        assert_eq!(
+            button["command_topic"],
+            "heating/water_heater.tank/boost/set"
+        );
+    }
+}
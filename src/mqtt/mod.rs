@@ -0,0 +1,444 @@
+//! Optional MQTT bridge for automations outside Home Assistant. It publishes retained state
+//! for the heating and hot water channels under the topic prefix and takes boost, cancel-boost,
+//! away and pause commands on `…/set` topics, handling them exactly like the matching API calls.
+
+use crate::climate::ClimateEntity;
+use crate::presence::Occupancy;
+use crate::server::handlers::{
+    DEFAULT_BOOST_MINUTES, cancel_boost, current_status, set_away, set_paused, start_boost,
+};
+use crate::server::{AppState, Channel};
+use crate::status::SchedulerStatus;
+use anyhow::{Context, Result, anyhow, bail};
+use discovery::{DiscoverySettings, discovery_messages};
+use rumqttc::{
+    AsyncClient, Event, LastWill, MqttOptions, Packet, QoS, SubscribeFilter, SubscribeReasonCode,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{interval, sleep};
+
+pub mod discovery;
+
+/// Topic segment for the hot water channel as a whole, e.g. `heating/hot_water/boost/set`
+pub const HOT_WATER_TOPIC: &str = "hot_water";
+
+/// How long to wait before connecting again after the broker goes away
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// How long the broker has to accept a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The broker drops us if it hears nothing for one and a half times this
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Where the broker is and which topics to use, set with the MQTT_* variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Every topic starts with this, e.g. `heating/climate.bedroom/state`
+    pub topic_prefix: String,
+    /// How often state is checked and any changes published
+    pub publish_interval: Duration,
+    /// Set to announce the scheduler's controls to Home Assistant
+    pub discovery: Option<DiscoverySettings>,
+}
+
+/// The entities a boost or cancel-boost command is for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Every entity of a channel: `<prefix>/boost/set` for heating,
+    /// `<prefix>/hot_water/boost/set` for hot water
+    Channel(Channel),
+    /// `<prefix>/<entity_id>/boost/set`, in whichever channel the entity belongs to
+    Entity(String),
+}
+
+/// Something asked for on a command topic
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The payload is the length in minutes, or empty for the default
+    Boost { target: Target, minutes: i64 },
+    /// `<prefix>/cancel_boost/set`, `<prefix>/hot_water/cancel_boost/set` or
+    /// `<prefix>/<entity_id>/cancel_boost/set`, any payload
+    CancelBoost { target: Target },
+    /// `<prefix>/away/set` with `ON` or `OFF`
+    Away(bool),
+    /// `<prefix>/pause/set` or `<prefix>/<entity_id>/pause/set` with `ON` or `OFF`, or the
+    /// same on `enabled` topics with the meaning flipped
+    Pause {
+        entity_ids: Option<Vec<String>>,
+        paused: bool,
+    },
+}
+
+/// Work out the command a message on one of our `set` topics asks for
+pub fn parse_command(prefix: &str, topic: &str, payload: &[u8]) -> Result<Command> {
+    let path = topic
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .and_then(|rest| rest.strip_suffix("/set"))
+        .ok_or_else(|| anyhow!("{} is not a command topic", topic))?;
+    let (zone, name) = match path.split_once('/') {
+        Some((zone, name)) => (Some(zone), name),
+        None => (None, path),
+    };
+    let target = match zone {
+        None => Target::Channel(Channel::Heating),
+        Some(HOT_WATER_TOPIC) => Target::Channel(Channel::HotWater),
+        Some(entity_id) => Target::Entity(entity_id.to_string()),
+    };
+    let entity_ids = zone.map(|entity_id| vec![entity_id.to_string()]);
+    let payload = std::str::from_utf8(payload)?.trim();
+
+    match name {
+        "boost" => {
+            let minutes = if payload.is_empty() {
+                DEFAULT_BOOST_MINUTES
+            } else {
+                payload
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|minutes| *minutes > 0)
+                    .with_context(|| format!("Invalid boost length: {}", payload))?
+                    as i64
+            };
+            Ok(Command::Boost { target, minutes })
+        }
+        "cancel_boost" => Ok(Command::CancelBoost { target }),
+        "away" if entity_ids.is_none() => Ok(Command::Away(parse_switch(payload)?)),
+        // Only boosts apply to the hot water channel as a whole
+        _ if zone == Some(HOT_WATER_TOPIC) => bail!("Unknown command topic {}", topic),
+        "pause" => Ok(Command::Pause {
+            entity_ids,
+            paused: parse_switch(payload)?,
+        }),
+        "enabled" => Ok(Command::Pause {
+            entity_ids,
+            paused: !parse_switch(payload)?,
+        }),
+        _ => bail!("Unknown command topic {}", topic),
+    }
+}
+
+fn parse_switch(payload: &str) -> Result<bool> {
+    match payload.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => bail!("Expected ON or OFF, got {}", payload),
+    }
+}
+
+fn switch_payload(on: bool) -> String {
+    if on { "ON" } else { "OFF" }.to_string()
+}
+
+/// The retained messages describing the scheduler's current state, by topic
+pub fn state_messages(prefix: &str, status: &SchedulerStatus) -> Vec<(String, String)> {
+    let away = status
+        .presence
+        .as_ref()
+        .is_some_and(|presence| presence.occupancy == Occupancy::Away);
+    let mut messages = vec![
+        (
+            format!("{}/active_entry", prefix),
+            serde_json::to_string(&status.active_entry).unwrap_or_default(),
+        ),
+        (format!("{}/away", prefix), switch_payload(away)),
+        (
+            format!("{}/next_transition", prefix),
+            serde_json::to_string(&status.next_transition).unwrap_or_default(),
+        ),
+    ];
+    if let Some(hot_water) = &status.hot_water {
+        let topic = |name: &str| format!("{}/{}/{}", prefix, HOT_WATER_TOPIC, name);
+        messages.push((
+            topic("active_entry"),
+            serde_json::to_string(&hot_water.active_entry).unwrap_or_default(),
+        ));
+        messages.push((
+            topic("next_transition"),
+            serde_json::to_string(&hot_water.next_transition).unwrap_or_default(),
+        ));
+    }
+    for entity in status.all_entities() {
+        let topic = |name: &str| format!("{}/{}/{}", prefix, entity.entity_id, name);
+        messages.push((
+            topic("state"),
+            serde_json::to_string(entity).unwrap_or_default(),
+        ));
+        messages.push((
+            topic("boost"),
+            serde_json::to_string(&entity.boost).unwrap_or_default(),
+        ));
+        messages.push((
+            topic("paused"),
+            switch_payload(entity.paused_since.is_some()),
+        ));
+        messages.push((
+            topic("enabled"),
+            switch_payload(entity.paused_since.is_none()),
+        ));
+    }
+    messages
+}
+
+/// The channel a command is for and the entities in it, or None for all of them
+fn resolve<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    target: Target,
+) -> (Channel, Option<Vec<String>>) {
+    match target {
+        Target::Channel(channel) => (channel, None),
+        Target::Entity(entity_id) => {
+            let hot_water = state
+                .hot_water_entities
+                .read()
+                .unwrap()
+                .iter()
+                .any(|entity| entity.get_entity_id() == entity_id);
+            let channel = if hot_water {
+                Channel::HotWater
+            } else {
+                Channel::Heating
+            };
+            (channel, Some(vec![entity_id]))
+        }
+    }
+}
+
+/// Handle a command the same way the matching API call would
+fn apply_command<T: ClimateEntity + Clone>(state: &AppState<T>, command: Command) {
+    let result = match command {
+        Command::Boost { target, minutes } => {
+            let (channel, entity_ids) = resolve(state, target);
+            start_boost(state, channel, entity_ids.as_deref(), minutes).map(drop)
+        }
+        Command::CancelBoost { target } => {
+            let (channel, entity_ids) = resolve(state, target);
+            cancel_boost(state, channel, entity_ids.as_deref()).map(drop)
+        }
+        Command::Away(away) => {
+            set_away(state, away);
+            Ok(())
+        }
+        Command::Pause { entity_ids, paused } => {
+            set_paused(state, entity_ids.as_deref(), paused);
+            Ok(())
+        }
+    };
+    if let Err((_, message)) = result {
+        eprintln!("MQTT command failed: {}", message);
+    }
+}
+
+/// Keep a connection to the broker open, reconnecting whenever it drops
+pub async fn run_mqtt_bridge<T: ClimateEntity + Clone>(settings: MqttSettings, state: AppState<T>) {
+    let prefix = settings.topic_prefix.as_str();
+    let availability_topic = format!("{}/availability", prefix);
+    let mut options = MqttOptions::new(&settings.client_id, &settings.host, settings.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        &availability_topic,
+        "offline",
+        QoS::AtMostOnce,
+        true,
+    ));
+    if let Some(username) = &settings.username {
+        options.set_credentials(username, settings.password.clone().unwrap_or_default());
+    }
+    let (client, mut event_loop) = AsyncClient::new(options, 64);
+    event_loop
+        .network_options
+        .set_connection_timeout(CONNECT_TIMEOUT.as_secs());
+
+    // The event loop has to keep being polled for the client's requests to go out, so a task
+    // polls it and hands over what comes in
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let (host, port) = (settings.host.clone(), settings.port);
+    tokio::spawn(async move {
+        loop {
+            match event_loop.poll().await {
+                Ok(event) => {
+                    if events_tx.send(Some(event)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!(
+                        "MQTT connection to {}:{} lost: {}, retrying in {}s",
+                        host,
+                        port,
+                        e,
+                        RECONNECT_DELAY.as_secs()
+                    );
+                    if events_tx.send(None).is_err() {
+                        break;
+                    }
+                    sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+
+    let mut connected = false;
+    let mut published: HashMap<String, String> = HashMap::new();
+    let mut publish_timer = interval(settings.publish_interval);
+    loop {
+        tokio::select! {
+            _ = publish_timer.tick(), if connected => {
+                publish_changes(&client, &settings, &state, &mut published).await;
+            }
+            event = events.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                match event {
+                    Some(Event::Incoming(Packet::ConnAck(_))) => {
+                        println!("MQTT connected to {}:{}", settings.host, settings.port);
+                        connected = true;
+                        // A new session starts with nothing published
+                        published.clear();
+                        let filters = [format!("{}/+/set", prefix), format!("{}/+/+/set", prefix)]
+                            .map(|path| SubscribeFilter::new(path, QoS::AtMostOnce));
+                        if let Err(e) = client.subscribe_many(filters).await {
+                            eprintln!("MQTT subscribe failed: {}", e);
+                        }
+                        publish(&client, &availability_topic, "online").await;
+                    }
+                    Some(Event::Incoming(Packet::Publish(message))) => {
+                        match parse_command(prefix, &message.topic, &message.payload) {
+                            Ok(command) => {
+                                println!("MQTT command on {}: {:?}", message.topic, command);
+                                apply_command(&state, command);
+                                publish_changes(&client, &settings, &state, &mut published).await;
+                            }
+                            Err(e) => eprintln!("Ignoring MQTT message: {:#}", e),
+                        }
+                    }
+                    Some(Event::Incoming(Packet::SubAck(ack)))
+                        if ack.return_codes.contains(&SubscribeReasonCode::Failure) =>
+                    {
+                        eprintln!("MQTT broker refused a command subscription");
+                    }
+                    Some(_) => {}
+                    None => connected = false,
+                }
+            }
+        }
+    }
+}
+
+/// Publish every retained message whose payload changed since it was last sent, and clear
+/// those that no longer apply, e.g. for an entity that was removed
+async fn publish_changes<T: ClimateEntity + Clone>(
+    client: &AsyncClient,
+    settings: &MqttSettings,
+    state: &AppState<T>,
+    published: &mut HashMap<String, String>,
+) {
+    let status = current_status(state);
+    let mut messages = state_messages(&settings.topic_prefix, &status);
+    if let Some(discovery) = &settings.discovery {
+        messages.extend(discovery_messages(settings, discovery, &status));
+    }
+
+    let stale: Vec<String> = published
+        .keys()
+        .filter(|topic| !messages.iter().any(|(current, _)| current == *topic))
+        .cloned()
+        .collect();
+    for topic in stale {
+        publish(client, &topic, "").await;
+        published.remove(&topic);
+    }
+
+    for (topic, payload) in messages {
+        if published.get(&topic) == Some(&payload) {
+            continue;
+        }
+        publish(client, &topic, &payload).await;
+        published.insert(topic, payload);
+    }
+}
+
+/// Queue a retained message; the event loop sends it once connected
+async fn publish(client: &AsyncClient, topic: &str, payload: &str) {
+    if let Err(e) = client.publish(topic, QoS::AtMostOnce, true, payload).await {
+        eprintln!("MQTT publish to {} failed: {}", topic, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(topic: &str, payload: &str) -> Result<Command> {
+        parse_command("heating", topic, payload.as_bytes())
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse("heating/boost/set", "").unwrap(),
+            Command::Boost {
+                target: Target::Channel(Channel::Heating),
+                minutes: DEFAULT_BOOST_MINUTES
+            }
+        );
+        assert_eq!(
+            parse("heating/climate.bedroom/boost/set", " 30 ").unwrap(),
+            Command::Boost {
+                target: Target::Entity("climate.bedroom".to_string()),
+                minutes: 30
+            }
+        );
+        assert_eq!(
+            parse("heating/climate.bedroom/cancel_boost/set", "PRESS").unwrap(),
+            Command::CancelBoost {
+                target: Target::Entity("climate.bedroom".to_string())
+            }
+        );
+        assert_eq!(
+            parse("heating/hot_water/boost/set", "60").unwrap(),
+            Command::Boost {
+                target: Target::Channel(Channel::HotWater),
+                minutes: 60
+            }
+        );
+        assert_eq!(
+            parse("heating/away/set", "ON").unwrap(),
+            Command::Away(true)
+        );
+        assert_eq!(
+            parse("heating/pause/set", "off").unwrap(),
+            Command::Pause {
+                entity_ids: None,
+                paused: false
+            }
+        );
+        assert_eq!(
+            parse("heating/climate.bedroom/enabled/set", "OFF").unwrap(),
+            Command::Pause {
+                entity_ids: Some(vec!["climate.bedroom".to_string()]),
+                paused: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_command_rejects_bad_messages() {
+        assert!(parse("heating/boost/set", "0").is_err());
+        assert!(parse("heating/boost/set", "a while").is_err());
+        assert!(parse("heating/away/set", "maybe").is_err());
+        // Away is for the whole house
+        assert!(parse("heating/climate.bedroom/away/set", "ON").is_err());
+        assert!(parse("heating/climate.bedroom/state", "{}").is_err());
+        assert!(parse("other/boost/set", "").is_err());
+        assert!(parse("heating/defrost/set", "ON").is_err());
+        assert!(parse("heating/hot_water/pause/set", "ON").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use crate::api_client::ApiClient;
+use crate::history::EventKind;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub mod persistence;
+
+/// Passes in a row an entity has to be unreachable before `unreachable` notifies
+pub const DEFAULT_UNREACHABLE_TICKS: u32 = 3;
+/// How long the same notification is held back after it was sent
+pub const DEFAULT_MIN_INTERVAL_MINUTES: u32 = 60;
+/// Title of every notification
+const NOTIFICATION_TITLE: &str = "Heating";
+
+/// What a notification is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Fetching the entity failed, or it was unavailable, for several passes in a row
+    Unreachable,
+    /// A command failed or the entity didn't follow it
+    CommandFailed,
+    /// Commands kept failing and the entity was marked degraded
+    Degraded,
+    /// A degraded entity is working again
+    Recovered,
+    FrostProtection,
+    BoostEnded,
+    WindowOpen,
+    Away,
+    Home,
+    WarmWeather,
+    OutdoorReadingStale,
+}
+
+impl Trigger {
+    /// The trigger for a journaled event, if it's one worth telling anyone about. Command
+    /// failures and unreachable entities are reported by the scheduler directly.
+    pub fn for_event(kind: &EventKind) -> Option<Trigger> {
+        match kind {
+            EventKind::Degraded { .. } => Some(Trigger::Degraded),
+            EventKind::Recovered => Some(Trigger::Recovered),
+            EventKind::FrostProtection { .. } => Some(Trigger::FrostProtection),
+            EventKind::BoostEnded => Some(Trigger::BoostEnded),
+            EventKind::WindowOpen { .. } => Some(Trigger::WindowOpen),
+            EventKind::Away { .. } => Some(Trigger::Away),
+            EventKind::Home => Some(Trigger::Home),
+            EventKind::WarmWeather { .. } => Some(Trigger::WarmWeather),
+            EventKind::OutdoorReadingStale { .. } => Some(Trigger::OutdoorReadingStale),
+            _ => None,
+        }
+    }
+}
+
+/// When and how one trigger notifies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRule {
+    /// Calls this `notify.*` service instead of the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// The same notification, for the same entity, is sent at most once in this many minutes
+    #[serde(default = "default_min_interval_minutes")]
+    pub min_interval_minutes: u32,
+    /// For `unreachable`, how many passes in a row before notifying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_ticks: Option<u32>,
+}
+
+fn default_min_interval_minutes() -> u32 {
+    DEFAULT_MIN_INTERVAL_MINUTES
+}
+
+/// Which notifications to send and where, stored in `notifications.json`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    /// `notify.*` service called for every rule without its own, e.g. `notify.mobile_app_phone`
+    pub service: String,
+    /// Only the triggers listed here notify
+    #[serde(default)]
+    pub rules: HashMap<Trigger, NotificationRule>,
+}
+
+impl NotificationSettings {
+    /// Check every service is a `notify` one
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let services = std::iter::once(&self.service)
+            .chain(self.rules.values().filter_map(|rule| rule.service.as_ref()));
+        for service in services {
+            if service_name(service).is_none() {
+                anyhow::bail!("{} is not a notify service", service);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The service part of `notify.<service>`
+pub fn service_name(service: &str) -> Option<&str> {
+    service
+        .strip_prefix("notify.")
+        .filter(|name| !name.is_empty())
+}
+
+/// Something that happened that a rule may want to tell someone about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub entity_id: Option<String>,
+    pub trigger: Trigger,
+    pub message: String,
+}
+
+impl Alert {
+    pub fn new(entity_id: Option<String>, trigger: Trigger, message: impl Into<String>) -> Self {
+        Alert {
+            entity_id,
+            trigger,
+            message: message.into(),
+        }
+    }
+
+    /// An alert for a journaled event, if its kind has a trigger
+    pub fn for_event(entity_id: Option<&str>, kind: &EventKind) -> Option<Self> {
+        let trigger = Trigger::for_event(kind)?;
+        let subject = entity_id.unwrap_or("Heating");
+        let message = match kind {
+            EventKind::Degraded {
+                desired_state,
+                failed_attempts,
+            } => format!(
+                "{} didn't turn {:?} after {} attempts",
+                subject, desired_state, failed_attempts
+            ),
+            EventKind::Recovered => format!("{} is responding to commands again", subject),
+            EventKind::FrostProtection {
+                temperature,
+                threshold,
+            } => format!(
+                "{} is at {:.1}°C, below {:.1}°C; heating is forced on",
+                subject, temperature, threshold
+            ),
+            EventKind::BoostEnded => format!("{} boost finished", subject),
+            EventKind::WindowOpen { .. } => format!("{} window open, heating paused", subject),
+            EventKind::Away { away_mode } => format!("Everyone is out, heating is {:?}", away_mode),
+            EventKind::Home => "Someone is home, the schedule applies again".to_string(),
+            EventKind::WarmWeather {
+                outdoor_temperature,
+                cutoff,
+            } => format!(
+                "It's {:.1}°C outside, at least {:.1}°C; heating periods are skipped",
+                outdoor_temperature, cutoff
+            ),
+            EventKind::OutdoorReadingStale { sensor, read_at } => format!(
+                "{} hasn't been read since {}; weather compensation is off until it is",
+                sensor,
+                read_at.format("%H:%M")
+            ),
+            _ => return None,
+        };
+        Some(Alert::new(entity_id.map(str::to_string), trigger, message))
+    }
+}
+
+/// A notification ready to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    /// `notify.*` service to call
+    pub service: String,
+    pub message: String,
+}
+
+/// What the scheduler remembers to apply rules and rate limits
+#[derive(Debug, Default)]
+pub struct Notifier {
+    /// When each trigger last notified, per entity
+    last_sent: HashMap<(Trigger, Option<String>), DateTime<Local>>,
+    /// Passes in a row each entity has been unreachable
+    unreachable_ticks: HashMap<String, u32>,
+}
+
+impl Notifier {
+    /// Count a pass in which the entity could or couldn't be read. Returns an alert on the
+    /// pass it has been unreachable for as long as the `unreachable` rule allows.
+    pub fn track_reachability(
+        &mut self,
+        settings: &NotificationSettings,
+        entity_id: &str,
+        reachable: bool,
+    ) -> Option<Alert> {
+        if reachable {
+            self.unreachable_ticks.remove(entity_id);
+            return None;
+        }
+        let ticks = self
+            .unreachable_ticks
+            .entry(entity_id.to_string())
+            .or_default();
+        *ticks += 1;
+        let after_ticks = settings
+            .rules
+            .get(&Trigger::Unreachable)?
+            .after_ticks
+            .unwrap_or(DEFAULT_UNREACHABLE_TICKS)
+            .max(1);
+        (*ticks == after_ticks).then(|| {
+            Alert::new(
+                Some(entity_id.to_string()),
+                Trigger::Unreachable,
+                format!("{} has been unreachable for {} passes", entity_id, ticks),
+            )
+        })
+    }
+
+    /// The notifications to send for these alerts, leaving out triggers without a rule and
+    /// anything sent too recently
+    pub fn notifications(
+        &mut self,
+        settings: &NotificationSettings,
+        alerts: Vec<Alert>,
+        now: DateTime<Local>,
+    ) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        for alert in alerts {
+            let Some(rule) = settings.rules.get(&alert.trigger) else {
+                continue;
+            };
+            let key = (alert.trigger, alert.entity_id.clone());
+            let min_interval = Duration::minutes(rule.min_interval_minutes as i64);
+            if self
+                .last_sent
+                .get(&key)
+                .is_some_and(|last_sent| now - *last_sent < min_interval)
+            {
+                continue;
+            }
+            self.last_sent.insert(key, now);
+            notifications.push(Notification {
+                service: rule
+                    .service
+                    .clone()
+                    .unwrap_or_else(|| settings.service.clone()),
+                message: alert.message,
+            });
+        }
+        notifications
+    }
+}
+
+/// Call the notify services through Home Assistant. A notification that fails is logged and
+/// dropped rather than retried.
+pub async fn send(api_client: &ApiClient, notifications: Vec<Notification>) {
+    for notification in notifications {
+        let Some(name) = service_name(&notification.service) else {
+            continue;
+        };
+        let result = api_client
+            .post(&format!("/api/services/notify/{}", name))
+            .json(&serde_json::json!({
+                "title": NOTIFICATION_TITLE,
+                "message": notification.message,
+            }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            eprintln!("  Failed to notify through {}: {}", notification.service, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+
+    fn rule(min_interval_minutes: u32) -> NotificationRule {
+        NotificationRule {
+            service: None,
+            min_interval_minutes,
+            after_ticks: None,
+        }
+    }
+
+    fn settings() -> NotificationSettings {
+        NotificationSettings {
+            service: "notify.mobile_app_phone".to_string(),
+            rules: HashMap::from([
+                (Trigger::BoostEnded, rule(0)),
+                (
+                    Trigger::FrostProtection,
+                    NotificationRule {
+                        service: Some("notify.everyone".to_string()),
+                        ..rule(30)
+                    },
+                ),
+                (
+                    Trigger::Unreachable,
+                    NotificationRule {
+                        after_ticks: Some(2),
+                        ..rule(60)
+                    },
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_rules_and_rate_limits() {
+        let settings = settings();
+        let mut notifier = Notifier::default();
+        let frost = |entity_id: &str| {
+            Alert::for_event(
+                Some(entity_id),
+                &EventKind::FrostProtection {
+                    temperature: 4.5,
+                    threshold: 5.0,
+                },
+            )
+            .unwrap()
+        };
+
+        let sent = notifier.notifications(
+            &settings,
+            vec![
+                frost("climate.bedroom"),
+                // No rule for it
+                Alert::new(None, Trigger::Home, "Someone is home"),
+            ],
+            at(7, 0),
+        );
+        assert_eq!(
+            sent,
+            vec![Notification {
+                service: "notify.everyone".to_string(),
+                message: "climate.bedroom is at 4.5°C, below 5.0°C; heating is forced on"
+                    .to_string(),
+            }]
+        );
+
+        // Held back for the same entity within the interval, not for another entity
+        let sent = notifier.notifications(
+            &settings,
+            vec![frost("climate.bedroom"), frost("climate.kitchen")],
+            at(7, 20),
+        );
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].message.starts_with("climate.kitchen"));
+        let sent = notifier.notifications(&settings, vec![frost("climate.bedroom")], at(7, 30));
+        assert_eq!(sent.len(), 1);
+
+        // Rules without an interval never hold anything back
+        let boost_ended = Alert::for_event(Some("climate.bedroom"), &EventKind::BoostEnded);
+        for _ in 0..2 {
+            let alerts = vec![boost_ended.clone().unwrap()];
+            let sent = notifier.notifications(&settings, alerts, at(8, 0));
+            assert_eq!(sent[0].service, "notify.mobile_app_phone");
+        }
+    }
+
+    #[test]
+    fn test_unreachable_after_ticks() {
+        let settings = settings();
+        let mut notifier = Notifier::default();
+        let mut track =
+            |reachable| notifier.track_reachability(&settings, "climate.bedroom", reachable);
+
+        assert_eq!(track(false), None);
+        let alert = track(false).unwrap();
+        assert_eq!(alert.trigger, Trigger::Unreachable);
+        assert_eq!(
+            alert.message,
+            "climate.bedroom has been unreachable for 2 passes"
+        );
+        // Once per outage
+        assert_eq!(track(false), None);
+        assert_eq!(track(true), None);
+        assert_eq!(track(false), None);
+        assert!(track(false).is_some());
+    }
+
+    #[test]
+    fn test_validate_services() {
+        assert!(settings().validate().is_ok());
+        let mut bad = settings();
+        bad.service = "light.kitchen".to_string();
+        assert!(bad.validate().is_err());
+        assert_eq!(
+            service_name("notify.mobile_app_phone"),
+            Some("mobile_app_phone")
+        );
+        assert_eq!(service_name("notify."), None);
+    }
+}
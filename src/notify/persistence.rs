@@ -0,0 +1,69 @@
+use super::NotificationSettings;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Load notification settings from a JSON file
+pub fn load_notifications<P: AsRef<Path>>(path: P) -> Result<NotificationSettings> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read notifications file: {}", path.display()))?;
+
+    let settings: NotificationSettings = serde_json::from_str(&contents).with_context(|| {
+        format!(
+            "Failed to parse notifications JSON from: {}",
+            path.display()
+        )
+    })?;
+    settings.validate()?;
+
+    Ok(settings)
+}
+
+/// Load notification settings if the file exists; without one nothing is sent
+pub fn load_if_present<P: AsRef<Path>>(path: P) -> Result<Option<NotificationSettings>> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        println!("Loading notifications from: {}", path.display());
+        load_notifications(path).map(Some)
+    } else {
+        println!("No notifications file found at: {}", path.display());
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::Trigger;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_notifications_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("notifications.json");
+        assert_eq!(load_if_present(&file_path).unwrap(), None);
+
+        fs::write(
+            &file_path,
+            r#"{
+                "service": "notify.mobile_app_phone",
+                "rules": {
+                    "frost_protection": {},
+                    "unreachable": {"after_ticks": 4, "min_interval_minutes": 240}
+                }
+            }"#,
+        )
+        .unwrap();
+        let settings = load_if_present(&file_path).unwrap().unwrap();
+        assert_eq!(
+            settings.rules[&Trigger::FrostProtection].min_interval_minutes,
+            60
+        );
+        assert_eq!(settings.rules[&Trigger::Unreachable].after_ticks, Some(4));
+
+        fs::write(&file_path, r#"{"service": "persistent_notification"}"#).unwrap();
+        assert!(load_if_present(&file_path).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Whether anyone is home, as far as the heating is concerned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Occupancy {
+    #[default]
+    Home,
+    Away,
+}
+
+/// How heating behaves while everyone is out
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AwayMode {
+    /// Heating stays off
+    Off,
+    /// The schedule's On periods hold this lower temperature instead of heating outright
+    Eco { temperature: f64 },
+}
+
+/// The `person` or `device_tracker` entities to watch and what to do when they're all out
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresenceSettings {
+    pub trackers: Vec<String>,
+    pub away_mode: AwayMode,
+    /// How long everyone has to be out before the house counts as away
+    pub away_delay: Duration,
+}
+
+/// Where each tracker is and whether the house counts as away
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Presence {
+    pub occupancy: Occupancy,
+    /// When the occupancy last changed
+    pub since: Option<DateTime<Local>>,
+    /// Everyone has been out since this time, still within the away delay if the house is home
+    pub everyone_out_since: Option<DateTime<Local>>,
+    /// Latest state of each tracker, e.g. `home`, `not_home` or a zone name
+    pub trackers: BTreeMap<String, String>,
+    /// What heating does while the house is away
+    pub away_mode: Option<AwayMode>,
+    /// Set by hand; the house is away whatever the trackers say until it's cleared
+    #[serde(default)]
+    pub forced_away: bool,
+}
+
+/// Whether a tracker state means its person is out. Unknown states count as home,
+/// so a broken tracker never turns the heating off.
+pub fn is_out(state: &str) -> bool {
+    !matches!(state, "home" | "unknown" | "unavailable")
+}
+
+impl Presence {
+    /// Take in the latest tracker states. Returns the new occupancy when it changed.
+    pub fn update(
+        &mut self,
+        trackers: BTreeMap<String, String>,
+        settings: &PresenceSettings,
+        now: DateTime<Local>,
+    ) -> Option<Occupancy> {
+        let everyone_out = !trackers.is_empty() && trackers.values().all(|state| is_out(state));
+        self.trackers = trackers;
+        self.away_mode = Some(settings.away_mode);
+
+        let occupancy = if self.forced_away {
+            Occupancy::Away
+        } else if everyone_out {
+            let out_since = *self.everyone_out_since.get_or_insert(now);
+            if now - out_since >= settings.away_delay {
+                Occupancy::Away
+            } else {
+                self.occupancy
+            }
+        } else {
+            self.everyone_out_since = None;
+            Occupancy::Home
+        };
+
+        self.change_occupancy(occupancy, now)
+    }
+
+    /// Declare the house away, or hand it back to the trackers. Without trackers the house is
+    /// home again straight away; with them it follows their latest states on the next update.
+    pub fn force_away(&mut self, away: bool, now: DateTime<Local>) -> Option<Occupancy> {
+        self.forced_away = away;
+        if away {
+            self.away_mode.get_or_insert(AwayMode::Off);
+            self.change_occupancy(Occupancy::Away, now)
+        } else {
+            self.change_occupancy(Occupancy::Home, now)
+        }
+    }
+
+    fn change_occupancy(
+        &mut self,
+        occupancy: Occupancy,
+        now: DateTime<Local>,
+    ) -> Option<Occupancy> {
+        if occupancy == self.occupancy {
+            return None;
+        }
+        self.occupancy = occupancy;
+        self.since = Some(now);
+        Some(occupancy)
+    }
+
+    /// The away behaviour in force, if the house is away
+    pub fn active_away_mode(&self) -> Option<AwayMode> {
+        self.away_mode.filter(|_| self.occupancy == Occupancy::Away)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+
+    fn trackers(states: &[(&str, &str)]) -> BTreeMap<String, String> {
+        states
+            .iter()
+            .map(|(id, state)| (id.to_string(), state.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_is_out() {
+        assert!(is_out("not_home"));
+        assert!(is_out("Work"));
+        assert!(!is_out("home"));
+        assert!(!is_out("unavailable"));
+        assert!(!is_out("unknown"));
+    }
+
+    #[test]
+    fn test_away_after_delay_and_home_on_return() {
+        let settings = PresenceSettings {
+            trackers: vec!["person.alex".to_string(), "person.sam".to_string()],
+            away_mode: AwayMode::Off,
+            away_delay: Duration::minutes(15),
+        };
+        let mut presence = Presence::default();
+
+        // One person still home
+        let some_out = trackers(&[("person.alex", "not_home"), ("person.sam", "home")]);
+        assert_eq!(presence.update(some_out, &settings, at(8, 0)), None);
+        assert_eq!(presence.everyone_out_since, None);
+
+        let all_out = trackers(&[("person.alex", "not_home"), ("person.sam", "Work")]);
+        assert_eq!(presence.update(all_out.clone(), &settings, at(8, 10)), None);
+        assert_eq!(presence.active_away_mode(), None);
+        assert_eq!(
+            presence.update(all_out.clone(), &settings, at(8, 25)),
+            Some(Occupancy::Away)
+        );
+        assert_eq!(presence.since, Some(at(8, 25)));
+        assert_eq!(presence.active_away_mode(), Some(AwayMode::Off));
+        assert_eq!(presence.update(all_out, &settings, at(9, 0)), None);
+
+        // Coming back home resumes straight away
+        let back = trackers(&[("person.alex", "home"), ("person.sam", "Work")]);
+        assert_eq!(
+            presence.update(back, &settings, at(17, 30)),
+            Some(Occupancy::Home)
+        );
+        assert_eq!(presence.everyone_out_since, None);
+        assert_eq!(presence.active_away_mode(), None);
+
+        // Nobody tracked is never away
+        assert_eq!(presence.update(BTreeMap::new(), &settings, at(18, 0)), None);
+    }
+
+    #[test]
+    fn test_forced_away_beats_the_trackers() {
+        let settings = PresenceSettings {
+            trackers: vec!["person.alex".to_string()],
+            away_mode: AwayMode::Eco { temperature: 16.0 },
+            away_delay: Duration::minutes(15),
+        };
+        let home = trackers(&[("person.alex", "home")]);
+        let mut presence = Presence::default();
+        presence.update(home.clone(), &settings, at(8, 0));
+
+        assert_eq!(presence.force_away(true, at(8, 5)), Some(Occupancy::Away));
+        assert_eq!(presence.update(home.clone(), &settings, at(8, 10)), None);
+        assert_eq!(
+            presence.active_away_mode(),
+            Some(AwayMode::Eco { temperature: 16.0 })
+        );
+
+        assert_eq!(presence.force_away(false, at(9, 0)), Some(Occupancy::Home));
+        assert_eq!(presence.update(home, &settings, at(9, 5)), None);
+
+        // Without any trackers configured heating just goes off
+        let mut untracked = Presence::default();
+        assert_eq!(untracked.force_away(true, at(8, 0)), Some(Occupancy::Away));
+        assert_eq!(untracked.active_away_mode(), Some(AwayMode::Off));
+    }
+}
@@ -1,4 +1,6 @@
-use chrono::{NaiveTime, Timelike};
+use crate::tariff::FlexibleHeating;
+use crate::weather::WeatherCompensation;
+use chrono::{DateTime, Days, Local, NaiveTime, Timelike};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use uuid::Uuid;
@@ -186,14 +188,8 @@ impl TimePeriod {
                     end: other.end,
                 };
                 let mut temp = vec![*self];
-                temp = temp
-                    .iter()
-                    .flat_map(|p| p.subtract(&other1))
-                    .collect();
-                temp = temp
-                    .iter()
-                    .flat_map(|p| p.subtract(&other2))
-                    .collect();
+                temp = temp.iter().flat_map(|p| p.subtract(&other1)).collect();
+                temp = temp.iter().flat_map(|p| p.subtract(&other2)).collect();
                 result = temp;
             }
         }
@@ -228,6 +224,12 @@ pub struct ScheduleEntry {
     pub name: String,
     pub time_period: TimePeriod,
     pub heating_state: HeatingState,
+    /// Room temperature to hold during this entry, for entities in thermostat mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_temperature: Option<f64>,
+    /// Heat only for part of this entry, in the cheapest slots of the tariff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flexible: Option<FlexibleHeating>,
 }
 
 impl ScheduleEntry {
@@ -242,8 +244,20 @@ impl ScheduleEntry {
             name: name.into(),
             time_period,
             heating_state,
+            target_temperature: None,
+            flexible: None,
         }
     }
+
+    pub fn with_target_temperature(mut self, target_temperature: Option<f64>) -> Self {
+        self.target_temperature = target_temperature;
+        self
+    }
+
+    pub fn with_flexible(mut self, flexible: Option<FlexibleHeating>) -> Self {
+        self.flexible = flexible;
+        self
+    }
 }
 
 /// Request DTO for creating a new schedule entry (without ID)
@@ -252,11 +266,17 @@ pub struct ScheduleEntryRequest {
     pub name: String,
     pub time_period: TimePeriod,
     pub heating_state: HeatingState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flexible: Option<FlexibleHeating>,
 }
 
 impl From<ScheduleEntryRequest> for ScheduleEntry {
     fn from(request: ScheduleEntryRequest) -> Self {
         ScheduleEntry::new(request.name, request.time_period, request.heating_state)
+            .with_target_temperature(request.target_temperature)
+            .with_flexible(request.flexible)
     }
 }
 
@@ -268,10 +288,21 @@ impl Default for ScheduleEntry {
     }
 }
 
+/// The next point at which the scheduled heating state changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleTransition {
+    pub time: DateTime<Local>,
+    pub heating_state: HeatingState,
+    pub entry: ScheduleEntry,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schedule {
     pub name: String,
     pub entries: Vec<ScheduleEntry>,
+    /// Adjusts the schedule to the outdoor temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weather_compensation: Option<WeatherCompensation>,
 }
 
 impl Schedule {
@@ -279,10 +310,14 @@ impl Schedule {
         Schedule {
             name: name.into(),
             entries: vec![ScheduleEntry::default()],
+            weather_compensation: None,
         }
     }
 
-    pub fn get_active_entry(&self, time: &chrono::DateTime<chrono::Local>) -> Option<&ScheduleEntry> {
+    pub fn get_active_entry(
+        &self,
+        time: &chrono::DateTime<chrono::Local>,
+    ) -> Option<&ScheduleEntry> {
         let naive_time = time.time();
         self.entries
             .iter()
@@ -295,6 +330,31 @@ impl Schedule {
             .unwrap_or(HeatingState::Off)
     }
 
+    /// Find the next time after `time` at which the scheduled state changes
+    ///
+    /// Looks up to a week ahead; a schedule that never changes state has no next transition.
+    pub fn next_transition(&self, time: &DateTime<Local>) -> Option<ScheduleTransition> {
+        let current_state = self.get_current_state(time);
+
+        let mut starts: Vec<NaiveTime> = self.entries.iter().map(|e| e.time_period.start).collect();
+        starts.sort();
+        starts.dedup();
+
+        (0..8)
+            .filter_map(|days| time.date_naive().checked_add_days(Days::new(days)))
+            .flat_map(|date| starts.iter().map(move |start| date.and_time(*start)))
+            .filter_map(|naive| naive.and_local_timezone(Local).earliest())
+            .filter(|candidate| candidate > time)
+            .find_map(|candidate| {
+                let entry = self.get_active_entry(&candidate)?;
+                (entry.heating_state != current_state).then(|| ScheduleTransition {
+                    time: candidate,
+                    heating_state: entry.heating_state.clone(),
+                    entry: entry.clone(),
+                })
+            })
+    }
+
     pub fn add_entry(&mut self, entry: ScheduleEntry) {
         let mut new_entries = Vec::new();
 
@@ -309,9 +369,8 @@ impl Schedule {
                 for period in remaining_periods {
                     new_entries.push(ScheduleEntry {
                         id: Uuid::new_v4(),
-                        name: existing.name.clone(),
                         time_period: period,
-                        heating_state: existing.heating_state.clone(),
+                        ..existing.clone()
                     });
                 }
             } else {
@@ -324,9 +383,7 @@ impl Schedule {
         new_entries.push(entry);
 
         // Sort entries by start time for cleaner organization
-        new_entries.sort_by(|a, b| {
-            a.time_period.start.cmp(&b.time_period.start)
-        });
+        new_entries.sort_by_key(|entry| entry.time_period.start);
 
         self.entries = new_entries;
     }
@@ -344,7 +401,7 @@ impl Schedule {
 
         // Sort entries by start time to find the previous entry
         let mut sorted_entries = self.entries.clone();
-        sorted_entries.sort_by(|a, b| a.time_period.start.cmp(&b.time_period.start));
+        sorted_entries.sort_by_key(|e| e.time_period.start);
 
         // Find the index of the entry to delete
         let delete_idx = sorted_entries
@@ -458,11 +515,17 @@ mod tests {
         assert_eq!(schedule.entries[0].time_period, TimePeriod::new(0, 0, 8, 0));
         assert_eq!(schedule.entries[0].heating_state, HeatingState::Off);
 
-        assert_eq!(schedule.entries[1].time_period, TimePeriod::new(8, 0, 17, 0));
+        assert_eq!(
+            schedule.entries[1].time_period,
+            TimePeriod::new(8, 0, 17, 0)
+        );
         assert_eq!(schedule.entries[1].heating_state, HeatingState::On);
 
         // The after-work period goes from 17:00 to 00:00 (midnight crossing)
-        assert_eq!(schedule.entries[2].time_period, TimePeriod::new(17, 0, 0, 0));
+        assert_eq!(
+            schedule.entries[2].time_period,
+            TimePeriod::new(17, 0, 0, 0)
+        );
         assert_eq!(schedule.entries[2].heating_state, HeatingState::Off);
     }
 
@@ -506,16 +569,18 @@ mod tests {
 
         // Verify no gaps: check that entries are properly ordered
         let mut entries_sorted = schedule.entries.clone();
-        entries_sorted.sort_by(|a, b| a.time_period.start.cmp(&b.time_period.start));
+        entries_sorted.sort_by_key(|e| e.time_period.start);
 
         for i in 0..entries_sorted.len() - 1 {
             let current_end = entries_sorted[i].time_period.end;
             let next_start = entries_sorted[i + 1].time_period.start;
             // End of current should equal start of next (no gaps)
             assert_eq!(
-                current_end, next_start,
+                current_end,
+                next_start,
                 "Gap found between entries {} and {}",
-                i, i + 1
+                i,
+                i + 1
             );
         }
     }
@@ -631,7 +696,11 @@ mod tests {
         assert_eq!(schedule.entries.len(), initial_count - 1);
 
         // The "Morning" entry should now extend to 17:00 (covering the deleted work hours)
-        let morning_entry = schedule.entries.iter().find(|e| e.name == "Morning").unwrap();
+        let morning_entry = schedule
+            .entries
+            .iter()
+            .find(|e| e.name == "Morning")
+            .unwrap();
         assert_eq!(
             morning_entry.time_period.end,
             NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
@@ -646,6 +715,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_next_transition() {
+        use chrono::TimeZone;
+
+        let mut schedule = Schedule::new("Test Schedule");
+        schedule.add_entry(ScheduleEntry::new(
+            "Morning",
+            TimePeriod::new(6, 0, 9, 0),
+            HeatingState::On,
+        ));
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+        let at = |day, hour, minute| {
+            Local
+                .with_ymd_and_hms(2025, 1, day, hour, minute, 0)
+                .unwrap()
+        };
+
+        let next = schedule.next_transition(&at(15, 7, 30)).unwrap();
+        assert_eq!(next.time, at(15, 9, 0));
+        assert_eq!(next.heating_state, HeatingState::Off);
+
+        let next = schedule.next_transition(&at(15, 12, 0)).unwrap();
+        assert_eq!(next.time, at(15, 17, 0));
+        assert_eq!(next.entry.name, "Evening");
+
+        // After the last change of the day it wraps to tomorrow morning
+        let next = schedule.next_transition(&at(15, 23, 0)).unwrap();
+        assert_eq!(next.time, at(16, 6, 0));
+        assert_eq!(next.heating_state, HeatingState::On);
+
+        // A schedule that is always off never changes
+        assert!(
+            Schedule::new("Off")
+                .next_transition(&at(15, 12, 0))
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_delete_entry_wraps_around_midnight() {
         // Test deleting the first entry (should extend the last entry)
@@ -18,8 +18,8 @@ pub fn load_schedule<P: AsRef<Path>>(path: P) -> Result<Schedule> {
 /// Save a schedule to a JSON file
 pub fn save_schedule<P: AsRef<Path>>(schedule: &Schedule, path: P) -> Result<()> {
     let path = path.as_ref();
-    let json = serde_json::to_string_pretty(schedule)
-        .context("Failed to serialize schedule to JSON")?;
+    let json =
+        serde_json::to_string_pretty(schedule).context("Failed to serialize schedule to JSON")?;
 
     fs::write(path, json)
         .with_context(|| format!("Failed to write schedule file: {}", path.display()))?;
@@ -29,6 +29,11 @@ pub fn save_schedule<P: AsRef<Path>>(schedule: &Schedule, path: P) -> Result<()>
 
 /// Load schedule from default location, or create a default one if it doesn't exist
 pub fn load_or_create_default<P: AsRef<Path>>(path: P) -> Result<Schedule> {
+    load_or_create_named(path, "Default Heating Schedule")
+}
+
+/// Load a schedule, or create an empty one with this name if it doesn't exist
+pub fn load_or_create_named<P: AsRef<Path>>(path: P, name: &str) -> Result<Schedule> {
     let path = path.as_ref();
 
     if path.exists() {
@@ -38,11 +43,10 @@ pub fn load_or_create_default<P: AsRef<Path>>(path: P) -> Result<Schedule> {
         println!("No schedule file found at: {}", path.display());
         println!("Creating default schedule...");
 
-        let schedule = Schedule::new("Default Heating Schedule");
+        let schedule = Schedule::new(name);
 
         // Save the default schedule for next time
-        save_schedule(&schedule, path)
-            .context("Failed to save default schedule")?;
+        save_schedule(&schedule, path).context("Failed to save default schedule")?;
 
         println!("Default schedule saved to: {}", path.display());
         Ok(schedule)
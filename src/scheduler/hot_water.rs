@@ -0,0 +1,175 @@
+use super::{
+    HeatingAction, SchedulerMemory, SchedulerState, SentCommand,
+    calculate_desired_heating_state_for_boost, calculate_heating_action_for_schedule,
+    clear_command_failures, detect_manual_change, final_desired_heating_state, override_state,
+    record_command_outcomes, send_command, store_processed, verify_sent_commands,
+};
+use crate::climate::{Availability, BoostInfo, ClimateEntity};
+use crate::history::EventKind;
+use crate::notify::Alert;
+use crate::schedule::HeatingState;
+use crate::timeseries::Sample;
+use chrono::{DateTime, Local};
+use std::collections::{HashMap, HashSet};
+
+/// Switch the hot water channel's entities to what its schedule, their boosts and any manual
+/// overrides ask for.
+///
+/// Room features such as thermostat control, presence, open windows and frost protection are
+/// for space heating. A change made outside the scheduler is honoured as for heating, until the
+/// hot water schedule's next transition or for the override policy's minutes.
+/// Returns the IDs of the entities processed.
+pub(super) async fn update_hot_water<T: ClimateEntity + Clone + 'static>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+    now: DateTime<Local>,
+    events: &mut Vec<(String, EventKind)>,
+    samples: &mut Vec<(String, Sample)>,
+    alerts: &mut Vec<Alert>,
+    unreachable: &mut HashSet<String>,
+) -> Vec<String> {
+    let mut entities = state.hot_water_entities.read().unwrap().clone();
+    if entities.is_empty() {
+        return Vec::new();
+    }
+    let (scheduled_state, active_entry) = {
+        let schedule = state.hot_water_schedule.read().unwrap();
+        (
+            schedule.get_current_state(&now),
+            schedule.get_active_entry(&now).cloned(),
+        )
+    };
+    let boosts_at_start: HashMap<String, Option<BoostInfo>> = entities
+        .iter()
+        .map(|e| {
+            (
+                e.get_entity_id().to_string(),
+                e.get_boosted_status().clone(),
+            )
+        })
+        .collect();
+    let mut sent_commands: Vec<SentCommand> = Vec::new();
+
+    for entity in entities.iter_mut() {
+        let entity_id = entity.get_entity_id().to_string();
+
+        if let Err(e) = entity.fetch_and_update_state(&state.api_client).await {
+            eprintln!("  Error fetching hot water state for {}: {}", entity_id, e);
+            unreachable.insert(entity_id.clone());
+            if memory.unavailable.insert(entity_id.clone()) {
+                events.push((
+                    entity_id,
+                    EventKind::Error {
+                        message: format!("Failed to fetch state: {}", e),
+                    },
+                ));
+            }
+            continue;
+        }
+        let (boosted_state, boost_ended) =
+            calculate_desired_heating_state_for_boost(entity.get_boosted_status(), &now);
+        if boost_ended {
+            entity.set_boost(None);
+            events.push((entity_id.clone(), EventKind::BoostEnded));
+        }
+
+        let info = entity.get_cached_state().clone().unwrap();
+        if info.availability != Availability::Available {
+            memory.expected_states.remove(&entity_id);
+            unreachable.insert(entity_id.clone());
+            if memory.unavailable.insert(entity_id.clone()) {
+                println!("  {} is {:?}, skipping", entity_id, info.availability);
+                events.push((
+                    entity_id,
+                    EventKind::Error {
+                        message: format!("Entity is {:?}", info.availability).to_lowercase(),
+                    },
+                ));
+            }
+            continue;
+        }
+        if memory.unavailable.remove(&entity_id) {
+            events.push((entity_id.clone(), EventKind::Reachable));
+        }
+
+        let heating_state = info.state.clone();
+        // The tank temperature, for water heaters that report one
+        if let Some(temperature) = info.current_temperature {
+            samples.push((
+                entity_id.clone(),
+                Sample {
+                    timestamp: now,
+                    temperature,
+                    setpoint: info.target_temperature,
+                    heating_on: heating_state == HeatingState::On,
+                },
+            ));
+        }
+
+        // A paused entity is left alone, as for heating
+        if state
+            .runtime
+            .read()
+            .unwrap()
+            .get(&entity_id)
+            .is_some_and(|runtime| runtime.paused_since.is_some())
+        {
+            memory.expected_states.remove(&entity_id);
+            continue;
+        }
+
+        events.extend(detect_manual_change(
+            state,
+            memory,
+            &state.hot_water_schedule,
+            &entity_id,
+            &info,
+            now,
+        ));
+        // A boost beats an override, as for heating
+        let desired_state = match override_state(state, &entity_id, now, events) {
+            Some(override_state) if boosted_state != HeatingState::On => override_state,
+            _ => final_desired_heating_state(&scheduled_state, &boosted_state),
+        };
+        let action = calculate_heating_action_for_schedule(&heating_state, &desired_state);
+        if action == HeatingAction::NoChange {
+            memory.dry_run_commands.remove(&entity_id);
+            if clear_command_failures(&state.runtime, &entity_id) {
+                events.push((entity_id, EventKind::Recovered));
+            }
+            continue;
+        }
+
+        println!(
+            "  Hot water {}: {:?} → {:?}",
+            entity_id, heating_state, desired_state
+        );
+        let decision = EventKind::SchedulerDecision {
+            active_entry: active_entry.clone(),
+            previous_state: heating_state,
+            desired_state,
+            boosted: boosted_state == HeatingState::On,
+            dry_run: entity.is_dry_run(),
+        };
+        sent_commands.extend(
+            send_command(state, memory, entity, action, decision, now, events, alerts).await,
+        );
+    }
+
+    let outcomes = verify_sent_commands(state, &mut entities, sent_commands).await;
+    record_command_outcomes(
+        state,
+        memory,
+        &state.hot_water_schedule,
+        outcomes,
+        now,
+        events,
+        alerts,
+    );
+
+    store_processed(&state.hot_water_entities, &entities, &boosts_at_start);
+    entities
+        .iter()
+        .map(|entity| entity.get_entity_id().to_string())
+        .collect()
+}
@@ -1,16 +1,82 @@
-use crate::ScheduleState;
 use crate::api_client::ApiClient;
-use crate::climate::{BoostInfo, ClimateEntity};
-use crate::schedule::HeatingState;
-use chrono::Local;
+use crate::climate::{Availability, BoostInfo, ClimateEntity, ClimateInfo};
+use crate::clock::SharedClock;
+use crate::config::entities_persistence::{ControlMode, DEFAULT_HYSTERESIS, EntitySettings};
+use crate::history::{EventKind, persistence as history_persistence};
+use crate::notify::{Alert, NotificationSettings, Notifier, Trigger};
+use crate::presence::{AwayMode, Occupancy, PresenceSettings};
+use crate::schedule::{HeatingState, ScheduleEntry};
+use crate::tariff::{
+    FlexibleHeating, FlexiblePlan, plan_flexible, slots_from_sensor, window_bounds,
+};
+use crate::timeseries::{Sample, persistence as timeseries_persistence};
+use crate::weather::{OutdoorWeather, WeatherCompensation, outdoor_temperature, warm_weather};
+use crate::{
+    EntityRuntimeState, EntitySettingsState, HistoryState, PresenceState, ScheduleState,
+    TariffState, TemperatureHistoryState, WeatherState,
+};
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::task::JoinSet;
 use tokio::time::interval;
 
+pub mod hot_water;
+pub mod optimum_start;
+pub mod runtime;
+pub mod window;
+
+use hot_water::update_hot_water;
+use optimum_start::{learn_heat_up_rate, update_optimum_start};
+use runtime::{FrostProtection, ManualOverride, OverridePolicy};
+use window::{TemperatureTrend, WindowChange, update_open_window};
+
+/// How often the temperature history is flushed to disk
+const TEMPERATURE_HISTORY_SAVE_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+/// How often the event journal is written to disk when it has new events
+const HISTORY_SAVE_INTERVAL: chrono::Duration = chrono::Duration::minutes(1);
+/// How often heat-up rates are re-learned from the temperature history
+const HEAT_UP_RATE_REFRESH_INTERVAL: chrono::Duration = chrono::Duration::hours(1);
+/// How often prices are re-read from the tariff sensor
+const TARIFF_REFRESH_INTERVAL: chrono::Duration = chrono::Duration::minutes(15);
+
 pub struct SchedulerState<T: ClimateEntity + Clone> {
     pub api_client: ApiClient,
     pub schedule: ScheduleState,
     pub climate_entities: Arc<RwLock<Vec<T>>>,
+    /// Timetable for the hot water channel, separate from the heating schedule
+    pub hot_water_schedule: ScheduleState,
+    /// Entities heating domestic hot water, switched by `hot_water_schedule` and their boosts
+    pub hot_water_entities: Arc<RwLock<Vec<T>>>,
+    pub history: HistoryState,
+    pub history_file_path: String,
+    pub temperature_history: TemperatureHistoryState,
+    pub temperature_history_file_path: String,
+    pub clock: SharedClock,
+    /// Time between scheduler passes
+    pub tick_interval: Duration,
+    /// How long to give the devices commanded in a pass to follow before they're re-read
+    pub verify_delay: Duration,
+    pub runtime: EntityRuntimeState,
+    /// How long manual changes are honoured
+    pub override_policy: OverridePolicy,
+    /// Control mode, targets and minimum on/off times per entity
+    pub entity_settings: EntitySettingsState,
+    /// Heating is forced on below this room temperature unless an entity sets its own
+    pub frost_protection_temperature: Option<f64>,
+    /// Optimum start never begins heating earlier than this before an On entry
+    pub optimum_start_max_lead: chrono::Duration,
+    /// Trackers to watch for an empty house, if presence is configured
+    pub presence_settings: Option<PresenceSettings>,
+    pub presence: PresenceState,
+    /// The latest outdoor reading, when the schedule has weather compensation
+    pub weather: WeatherState,
+    /// Sensor listing upcoming energy prices, if prices don't only come from the tariff file
+    pub tariff_sensor: Option<String>,
+    pub tariff: TariffState,
+    /// Which events are sent to a `notify` service, if any
+    pub notifications: Option<NotificationSettings>,
 }
 
 /// Represents an action to be taken on a climate entity
@@ -38,15 +104,88 @@ pub fn calculate_heating_action_for_schedule(
     }
 }
 
+/// Target and band for an entity the scheduler regulates itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thermostat {
+    pub target: f64,
+    pub hysteresis: f64,
+}
+
+/// Calculate what heating action keeps the room within the thermostat's band
+///
+/// Below the band heating starts, above it heating stops, and inside it the entity keeps
+/// doing what it's doing. Without a temperature reading the entity simply heats.
+#[must_use]
+pub fn calculate_heating_action_for_thermostat(
+    current_state: &HeatingState,
+    current_temperature: Option<f64>,
+    thermostat: &Thermostat,
+) -> HeatingAction {
+    let wanted_state = match current_temperature {
+        None => HeatingState::On,
+        Some(temperature) if temperature < thermostat.target - thermostat.hysteresis => {
+            HeatingState::On
+        }
+        Some(temperature) if temperature > thermostat.target + thermostat.hysteresis => {
+            HeatingState::Off
+        }
+        Some(_) => return HeatingAction::NoChange,
+    };
+    calculate_heating_action_for_schedule(current_state, &wanted_state)
+}
+
+/// How far above the frost threshold a room must warm before frost protection lets go
+pub const FROST_PROTECTION_RELEASE_MARGIN: f64 = 1.0;
+
+/// Whether frost protection should force heating on
+///
+/// It starts below the threshold and holds until the room is clear of it by the release
+/// margin. Without a reading it keeps doing what it did.
+#[must_use]
+pub fn frost_protection_needed(
+    active: bool,
+    current_temperature: Option<f64>,
+    threshold: Option<f64>,
+) -> bool {
+    match (current_temperature, threshold) {
+        (Some(temperature), Some(threshold)) if active => {
+            temperature < threshold + FROST_PROTECTION_RELEASE_MARGIN
+        }
+        (Some(temperature), Some(threshold)) => temperature < threshold,
+        (None, Some(_)) => active,
+        (_, None) => false,
+    }
+}
+
+/// Whether the entity has been on (or off) long enough for `action` to switch it
+#[must_use]
+pub fn minimum_time_elapsed(
+    action: &HeatingAction,
+    switched_at: Option<DateTime<Local>>,
+    settings: &EntitySettings,
+    now: &DateTime<Local>,
+) -> bool {
+    let minimum_minutes = match action {
+        HeatingAction::TurnOff => settings.min_on_minutes,
+        HeatingAction::TurnOn => settings.min_off_minutes,
+        HeatingAction::NoChange => None,
+    };
+    match (minimum_minutes, switched_at) {
+        (Some(minutes), Some(switched_at)) => {
+            *now - switched_at >= chrono::Duration::minutes(minutes as i64)
+        }
+        _ => true,
+    }
+}
+
 // Return a tuple containing the desired heating state and a boolean indicating if the state should be updated
 pub fn calculate_desired_heating_state_for_boost(
     boost_info: &Option<BoostInfo>,
+    now: &DateTime<Local>,
 ) -> (HeatingState, bool) {
     if let Some(boosted) = boost_info {
-        // Validate that current time is inside the boosted time period
-        let now = Local::now().time();
         // Check if current time is within boost period
-        if now >= boosted.boost_start && now <= boosted.boost_end {
+        if *now >= boosted.boost_start && *now <= boosted.boost_end {
             return (HeatingState::On, false);
         }
         (HeatingState::Off, true)
@@ -75,7 +214,7 @@ async fn apply_heating_action(
     entity: &impl ClimateEntity,
     action: HeatingAction,
     api_client: &ApiClient,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), anyhow::Error> {
     match action {
         HeatingAction::TurnOn => {
             entity.turn_on(api_client).await?;
@@ -90,75 +229,1284 @@ async fn apply_heating_action(
     Ok(())
 }
 
-/// Main scheduler loop that runs periodically and applies schedule
-pub async fn run_scheduler<T: ClimateEntity + Clone>(state: SchedulerState<T>) {
-    let mut interval = interval(Duration::from_secs(15));
+/// A command sent in this pass, checked once every entity has been commanded
+struct SentCommand {
+    entity_id: String,
+    action: HeatingAction,
+    state: HeatingState,
+    sent_at: DateTime<Local>,
+    /// Journaled once the entity is seen to follow the command
+    decision: EventKind,
+}
 
-    println!("\n=== Heating Scheduler Started ===");
+/// What became of a command once its entity was re-read
+enum CommandOutcome {
+    Followed,
+    /// Someone changed the entity after the command was sent; this is how they left it
+    Overruled(ClimateInfo),
+    Failed(anyhow::Error),
+}
 
-    loop {
-        interval.tick().await;
+/// Give the entities commanded in this pass `verify_delay` to follow, then re-read them all at
+/// once and check each reached the state it was sent to. An entity changed since its command
+/// was sent has been overruled by hand rather than failed. The re-read states are cached on
+/// `entities`. Returns each command with what became of it.
+async fn verify_sent_commands<T: ClimateEntity + Clone + 'static>(
+    state: &SchedulerState<T>,
+    entities: &mut [T],
+    sent: Vec<SentCommand>,
+) -> Vec<(SentCommand, CommandOutcome)> {
+    if sent.is_empty() {
+        return Vec::new();
+    }
+    if !state.verify_delay.is_zero() {
+        tokio::time::sleep(state.verify_delay).await;
+    }
+
+    let mut reads = JoinSet::new();
+    for (index, command) in sent.iter().enumerate() {
+        let Some(mut entity) = entities
+            .iter()
+            .find(|entity| entity.get_entity_id() == command.entity_id)
+            .cloned()
+        else {
+            continue;
+        };
+        let api_client = state.api_client.clone();
+        reads.spawn(async move {
+            let result = entity.fetch_and_update_state(&api_client).await;
+            (index, entity, result)
+        });
+    }
+    let mut outcomes: HashMap<usize, CommandOutcome> = HashMap::new();
+    while let Some(Ok((index, read, result))) = reads.join_next().await {
+        let command = &sent[index];
+        let outcome = match (result, read.get_cached_state()) {
+            (Err(e), _) => CommandOutcome::Failed(e),
+            (Ok(()), Some(info)) if info.state == command.state => CommandOutcome::Followed,
+            (Ok(()), Some(info))
+                if info
+                    .last_changed
+                    .is_some_and(|last_changed| last_changed > command.sent_at) =>
+            {
+                CommandOutcome::Overruled(info.clone())
+            }
+            (Ok(()), info) => CommandOutcome::Failed(anyhow::anyhow!(
+                "state is {:?} after the command",
+                info.as_ref().map(|info| &info.state)
+            )),
+        };
+        if let Some(entity) = entities
+            .iter_mut()
+            .find(|entity| entity.get_entity_id() == read.get_entity_id())
+        {
+            entity.update_cached_state(read.get_cached_state().clone());
+        }
+        outcomes.insert(index, outcome);
+    }
+
+    sent.into_iter()
+        .enumerate()
+        .map(|(index, command)| {
+            let outcome = outcomes.remove(&index).unwrap_or_else(|| {
+                CommandOutcome::Failed(anyhow::anyhow!("the entity couldn't be re-read"))
+            });
+            (command, outcome)
+        })
+        .collect()
+}
+
+/// Count a failed command against an entity so it's retried with back-off. Returns the events
+/// to journal, including `degraded` once it has failed too often, and the alert to raise.
+fn command_failure<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    entity_id: &str,
+    action: &HeatingAction,
+    commanded_state: HeatingState,
+    error: &anyhow::Error,
+    now: DateTime<Local>,
+) -> (Vec<(String, EventKind)>, Alert) {
+    eprintln!("  ✗ Error applying action: {}", error);
+    let message = format!("Failed to apply {:?}: {}", action, error);
+    let (became_degraded, failed_attempts) = {
+        let mut runtime = state.runtime.write().unwrap();
+        let runtime = runtime.entry(entity_id.to_string()).or_default();
+        (
+            runtime.record_failure(now, message.clone()),
+            runtime.failed_attempts,
+        )
+    };
+    let alert = Alert::new(
+        Some(entity_id.to_string()),
+        Trigger::CommandFailed,
+        format!("{} didn't turn {:?}: {}", entity_id, commanded_state, error),
+    );
+    let mut events = vec![(entity_id.to_string(), EventKind::Error { message })];
+    if became_degraded {
+        eprintln!(
+            "  ✗ {} is degraded after {} failed commands",
+            entity_id, failed_attempts
+        );
+        events.push((
+            entity_id.to_string(),
+            EventKind::Degraded {
+                desired_state: commanded_state,
+                failed_attempts,
+            },
+        ));
+    }
+    (events, alert)
+}
+
+/// Journal a change made by hand to an entity the scheduler expected in `expected_state`, and
+/// honour it as an override for as long as the override policy allows. `schedule` is the one the
+/// entity follows, whose next transition may end the override.
+fn manual_change<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    schedule: &ScheduleState,
+    entity_id: &str,
+    expected_state: HeatingState,
+    observed: &ClimateInfo,
+    now: DateTime<Local>,
+) -> Vec<(String, EventKind)> {
+    println!(
+        "  Manual change detected on {}: {:?} → {:?}",
+        entity_id, expected_state, observed.state
+    );
+    let mut events = vec![(
+        entity_id.to_string(),
+        EventKind::ManualChange {
+            expected_state,
+            observed_state: observed.state.clone(),
+        },
+    )];
+
+    // HA's clock may be ahead of ours; the override can't start in the future
+    let since = observed
+        .last_changed
+        .filter(|last_changed| *last_changed <= now)
+        .unwrap_or(now);
+    let until = state
+        .override_policy
+        .override_until(&schedule.read().unwrap(), &since);
+    if let Some(until) = until {
+        println!(
+            "  Honouring manual change on {} until {:?}",
+            entity_id, until
+        );
+        let manual_override = ManualOverride {
+            state: observed.state.clone(),
+            since,
+            until,
+            user_id: observed.changed_by.clone(),
+        };
+        state
+            .runtime
+            .write()
+            .unwrap()
+            .entry(entity_id.to_string())
+            .or_default()
+            .manual_override = Some(manual_override);
+        events.push((
+            entity_id.to_string(),
+            EventKind::ManualOverride {
+                state: observed.state.clone(),
+                until,
+                user_id: observed.changed_by.clone(),
+            },
+        ));
+    }
+    events
+}
+
+/// Start or end frost protection for an entity from its room temperature, journaling the change.
+/// Returns whether heating is forced on.
+fn update_frost_protection<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    entity_id: &str,
+    entity_threshold: Option<f64>,
+    current_temperature: Option<f64>,
+    now: DateTime<Local>,
+    events: &mut Vec<(String, EventKind)>,
+) -> bool {
+    let frost_threshold = entity_threshold.or(state.frost_protection_temperature);
+    let mut runtime = state.runtime.write().unwrap();
+    let runtime = runtime.entry(entity_id.to_string()).or_default();
+    let was_protected = runtime.frost_protection.is_some();
+    let protected = frost_protection_needed(was_protected, current_temperature, frost_threshold);
+    if protected && !was_protected {
+        let (temperature, threshold) = (
+            current_temperature.unwrap_or_default(),
+            frost_threshold.unwrap_or_default(),
+        );
+        println!(
+            "  Frost protection on {}: {:.1}°C is below {:.1}°C",
+            entity_id, temperature, threshold
+        );
+        runtime.frost_protection = Some(FrostProtection {
+            since: now,
+            threshold,
+        });
+        events.push((
+            entity_id.to_string(),
+            EventKind::FrostProtection {
+                temperature,
+                threshold,
+            },
+        ));
+    } else if !protected && was_protected {
+        runtime.frost_protection = None;
+        events.push((entity_id.to_string(), EventKind::FrostProtectionEnded));
+    }
+    protected
+}
+
+/// Send `action` to an entity, unless it's backing off after failed commands. A failure to send
+/// is journaled straight away, and a dry run journals `decision` once per commanded state.
+/// Returns the command to verify once the pass is done.
+#[allow(clippy::too_many_arguments)]
+async fn send_command<T: ClimateEntity + Clone + 'static>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+    entity: &mut T,
+    action: HeatingAction,
+    decision: EventKind,
+    now: DateTime<Local>,
+    events: &mut Vec<(String, EventKind)>,
+    alerts: &mut Vec<Alert>,
+) -> Option<SentCommand> {
+    let entity_id = entity.get_entity_id().to_string();
+    let commanded_state = match action {
+        HeatingAction::TurnOff => HeatingState::Off,
+        _ => HeatingState::On,
+    };
+
+    if !entity.is_dry_run()
+        && let Some(runtime) = state.runtime.read().unwrap().get(&entity_id)
+        && !runtime.can_retry(&now)
+    {
+        println!("  Backing off {} after a failed command", entity_id);
+        return None;
+    }
+
+    let sent_at = state.clock.now();
+    if let Err(e) = apply_heating_action(entity, action.clone(), &state.api_client).await {
+        let (failure_events, alert) =
+            command_failure(state, &entity_id, &action, commanded_state, &e, now);
+        events.extend(failure_events);
+        alerts.push(alert);
+        return None;
+    }
+    // A dry run leaves the entity as it was, so keep expecting the observed state
+    if entity.is_dry_run() {
+        if memory.dry_run_commands.get(&entity_id) != Some(&commanded_state) {
+            memory
+                .dry_run_commands
+                .insert(entity_id.clone(), commanded_state);
+            events.push((entity_id, decision));
+        }
+        return None;
+    }
+    Some(SentCommand {
+        entity_id,
+        action,
+        state: commanded_state,
+        sent_at,
+        decision,
+    })
+}
+
+/// Compare an entity's state with the one it was left in after the previous pass, and take a
+/// change the scheduler didn't make as a manual change
+fn detect_manual_change<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+    schedule: &ScheduleState,
+    entity_id: &str,
+    info: &ClimateInfo,
+    now: DateTime<Local>,
+) -> Vec<(String, EventKind)> {
+    let Some(expected_state) = memory
+        .expected_states
+        .insert(entity_id.to_string(), info.state.clone())
+        .filter(|expected_state| *expected_state != info.state)
+    else {
+        return Vec::new();
+    };
+    memory.last_switched.insert(
+        entity_id.to_string(),
+        info.last_changed
+            .filter(|last_changed| *last_changed <= now)
+            .unwrap_or(now),
+    );
+
+    // A command the entity didn't follow in time may still take effect later; whoever made any
+    // other change, even as the same Home Assistant user, is taking over
+    let late_command =
+        memory
+            .unverified_commands
+            .remove(entity_id)
+            .is_some_and(|(commanded_state, sent_at)| {
+                commanded_state == info.state
+                    && info
+                        .last_changed
+                        .is_none_or(|last_changed| last_changed >= sent_at)
+            });
+    if late_command {
+        println!("  {} followed an earlier command late", entity_id);
+        return Vec::new();
+    }
+    manual_change(state, schedule, entity_id, expected_state, info, now)
+}
+
+/// The state an active manual override holds an entity in. An override that has run out is
+/// cleared and journaled.
+fn override_state<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    entity_id: &str,
+    now: DateTime<Local>,
+    events: &mut Vec<(String, EventKind)>,
+) -> Option<HeatingState> {
+    match state.runtime.write().unwrap().get_mut(entity_id) {
+        Some(runtime) if runtime.manual_override.is_some() => {
+            let override_state = runtime.active_override(&now).map(|o| o.state.clone());
+            if override_state.is_none() {
+                runtime.manual_override = None;
+                events.push((entity_id.to_string(), EventKind::ManualOverrideEnded));
+            }
+            override_state
+        }
+        _ => None,
+    }
+}
 
-        let now = Local::now();
+/// Journal the commands sent in a pass by how the entities took them, and remember what each
+/// entity should now be in. `schedule` is the one the entities follow.
+fn record_command_outcomes<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+    schedule: &ScheduleState,
+    outcomes: Vec<(SentCommand, CommandOutcome)>,
+    now: DateTime<Local>,
+    events: &mut Vec<(String, EventKind)>,
+    alerts: &mut Vec<Alert>,
+) {
+    for (command, outcome) in outcomes {
+        let entity_id = command.entity_id;
+        match outcome {
+            CommandOutcome::Followed => {}
+            CommandOutcome::Overruled(observed) => {
+                // The command went out, but someone has taken over since
+                memory.unverified_commands.remove(&entity_id);
+                memory
+                    .expected_states
+                    .insert(entity_id.clone(), observed.state.clone());
+                memory.last_switched.insert(
+                    entity_id.clone(),
+                    observed
+                        .last_changed
+                        .filter(|last_changed| *last_changed <= now)
+                        .unwrap_or(now),
+                );
+                events.push((entity_id.clone(), command.decision));
+                events.extend(manual_change(
+                    state,
+                    schedule,
+                    &entity_id,
+                    command.state,
+                    &observed,
+                    now,
+                ));
+                continue;
+            }
+            CommandOutcome::Failed(e) => {
+                memory
+                    .unverified_commands
+                    .insert(entity_id.clone(), (command.state.clone(), command.sent_at));
+                let (failure_events, alert) =
+                    command_failure(state, &entity_id, &command.action, command.state, &e, now);
+                events.extend(failure_events);
+                alerts.push(alert);
+                continue;
+            }
+        }
+        memory.unverified_commands.remove(&entity_id);
+        memory
+            .expected_states
+            .insert(entity_id.clone(), command.state);
+        memory.last_switched.insert(entity_id.clone(), now);
+        if clear_command_failures(&state.runtime, &entity_id) {
+            events.push((entity_id.clone(), EventKind::Recovered));
+        }
+        events.push((entity_id, command.decision));
+    }
+}
 
-        // Get current scheduled state
-        let desired_state = {
-            let schedule = state.schedule.read().unwrap();
-            schedule.get_current_state(&now)
+/// Update the shared entities with the ones processed this pass. The API may have added or
+/// removed entities or changed a boost while the pass was running, and those changes win.
+fn store_processed<T: ClimateEntity + Clone>(
+    shared: &RwLock<Vec<T>>,
+    processed: &[T],
+    boosts_at_start: &HashMap<String, Option<BoostInfo>>,
+) {
+    let Ok(mut entities) = shared.write() else {
+        return;
+    };
+    for entity in entities.iter_mut() {
+        let entity_id = entity.get_entity_id().to_string();
+        let Some(processed) = processed.iter().find(|e| e.get_entity_id() == entity_id) else {
+            continue;
         };
+        let boost_changed_by_api =
+            boosts_at_start.get(&entity_id) != Some(entity.get_boosted_status());
+        let boost = entity.get_boosted_status().clone();
+        *entity = processed.clone();
+        if boost_changed_by_api {
+            entity.set_boost(boost);
+        }
+    }
+}
 
-        // Clone entities to avoid holding lock across await points
-        let mut entities_clone = {
-            let climates = state.climate_entities.read().unwrap();
-            climates.clone()
+/// Forget an entity's failed commands, returning true if it had been degraded
+fn clear_command_failures(runtime: &EntityRuntimeState, entity_id: &str) -> bool {
+    runtime
+        .write()
+        .unwrap()
+        .get_mut(entity_id)
+        .is_some_and(|runtime| runtime.clear_failures())
+}
+
+/// Read every tracker and update who is home. Returns the away behaviour in force, if any.
+async fn update_presence<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    settings: &PresenceSettings,
+    now: DateTime<Local>,
+    house_events: &mut Vec<EventKind>,
+) -> Option<AwayMode> {
+    let mut trackers = BTreeMap::new();
+    for tracker in &settings.trackers {
+        let tracker_state = match state.api_client.fetch_entity_state(tracker).await {
+            Ok(tracker_state) => tracker_state.state,
+            Err(e) => {
+                eprintln!("  Error reading presence tracker {}: {}", tracker, e);
+                "unavailable".to_string()
+            }
         };
+        trackers.insert(tracker.clone(), tracker_state);
+    }
 
-        // Process entities outside the lock
-        for entity in entities_clone.iter_mut() {
-            if let Err(e) = entity
-                .fetch_and_update_state(&state.api_client)
-                .await
-            {
+    let mut presence = state.presence.write().unwrap();
+    match presence.update(trackers, settings, now) {
+        Some(Occupancy::Away) => {
+            println!("  Everyone is out, heating is {:?}", settings.away_mode);
+            house_events.push(EventKind::Away {
+                away_mode: settings.away_mode,
+            });
+        }
+        Some(Occupancy::Home) => {
+            println!("  Someone is home, resuming the schedule");
+            house_events.push(EventKind::Home);
+        }
+        None => {}
+    }
+    presence.active_away_mode()
+}
+
+/// Replace the known price slots with those the tariff sensor currently lists
+async fn refresh_tariff<T: ClimateEntity + Clone>(state: &SchedulerState<T>, sensor: &str) {
+    match state.api_client.fetch_entity_state(sensor).await {
+        Ok(entity) => {
+            let slots = slots_from_sensor(&entity);
+            if slots.is_empty() {
+                eprintln!("  Tariff sensor {} lists no prices", sensor);
+            } else {
+                state.tariff.write().unwrap().tariff.slots = slots;
+            }
+        }
+        Err(e) => eprintln!("  Error reading tariff sensor {}: {}", sensor, e),
+    }
+}
+
+/// Whether a flexible entry heats now. The slots are picked once per occurrence of the entry,
+/// so later price updates don't move heating that has already been done.
+///
+/// A period first seen part-way through, after a restart say, keeps the plan journaled for it
+/// earlier. Without one, the slots are picked from now on: time already gone can't be heated in.
+fn flexible_heating_state<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    entry: &ScheduleEntry,
+    flexible: &FlexibleHeating,
+    now: DateTime<Local>,
+    house_events: &mut Vec<EventKind>,
+) -> HeatingState {
+    let Some((window_start, window_end)) = window_bounds(&entry.time_period, &now) else {
+        return HeatingState::On;
+    };
+
+    let cached = state
+        .tariff
+        .read()
+        .unwrap()
+        .plans
+        .get(&entry.id)
+        .filter(|plan| plan.window_start == window_start)
+        .cloned();
+    let plan = match cached {
+        Some(plan) => plan,
+        None => {
+            let journaled = journaled_plan(state, entry, window_start, window_end);
+            let mut tariff = state.tariff.write().unwrap();
+            let plan = match journaled {
+                Some(plan) => plan,
+                None => {
+                    let plan_start = now.max(window_start);
+                    let mut plan =
+                        plan_flexible(entry.id, flexible, plan_start, window_end, &tariff.tariff);
+                    plan.window_start = window_start;
+                    println!(
+                        "  Flexible heating for {}: {} slot(s), average price {:?}",
+                        entry.name,
+                        plan.slots.len(),
+                        plan.average_price
+                    );
+                    house_events.push(EventKind::FlexibleHeatingPlanned {
+                        entry_name: entry.name.clone(),
+                        slots: plan.slots.clone(),
+                        average_price: plan.average_price,
+                    });
+                    plan
+                }
+            };
+            tariff.plans.insert(entry.id, plan.clone());
+            plan
+        }
+    };
+    if plan.is_heating(&now) {
+        HeatingState::On
+    } else {
+        HeatingState::Off
+    }
+}
+
+/// The plan journaled for this occurrence of a flexible entry, if one was made
+fn journaled_plan<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    entry: &ScheduleEntry,
+    window_start: DateTime<Local>,
+    window_end: DateTime<Local>,
+) -> Option<FlexiblePlan> {
+    let history = state.history.read().unwrap();
+    history
+        .events
+        .iter()
+        .rev()
+        .take_while(|event| event.timestamp >= window_start)
+        .find_map(|event| match &event.kind {
+            EventKind::FlexibleHeatingPlanned {
+                entry_name,
+                slots,
+                average_price,
+            } if *entry_name == entry.name => Some(FlexiblePlan {
+                entry_id: entry.id,
+                window_start,
+                window_end,
+                slots: slots.clone(),
+                average_price: *average_price,
+            }),
+            _ => None,
+        })
+}
+
+/// Read the outdoor temperature and decide whether it's warm enough to skip On periods.
+/// Keeps the previous reading if the sensor can't be read, for up to `MAX_READING_AGE`; after
+/// that there's no reading, so nothing is skipped and targets aren't shifted.
+async fn update_outdoor_weather<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    compensation: &WeatherCompensation,
+    now: DateTime<Local>,
+    house_events: &mut Vec<EventKind>,
+) -> Option<OutdoorWeather> {
+    let temperature = match state
+        .api_client
+        .fetch_entity_state(&compensation.sensor)
+        .await
+    {
+        Ok(entity) => outdoor_temperature(&entity),
+        Err(e) => {
+            eprintln!(
+                "  Error reading outdoor sensor {}: {}",
+                compensation.sensor, e
+            );
+            None
+        }
+    };
+
+    let mut weather = state.weather.write().unwrap();
+    // A different sensor starts afresh
+    let previous = weather
+        .take()
+        .filter(|previous| previous.sensor == compensation.sensor);
+    let Some(temperature) = temperature else {
+        match previous {
+            Some(previous) if previous.is_stale(now) => {
                 eprintln!(
-                    "[{}] Error fetching state for {}: {}",
-                    now.format("%Y-%m-%d %H:%M:%S"),
-                    entity.get_entity_id(),
-                    e
+                    "  No outdoor reading since {}, ignoring the weather",
+                    previous.read_at
                 );
-                continue;
+                house_events.push(EventKind::OutdoorReadingStale {
+                    sensor: previous.sensor,
+                    read_at: previous.read_at,
+                });
+            }
+            previous => *weather = previous,
+        }
+        return weather.clone();
+    };
+
+    let was_warm = previous.is_some_and(|previous| previous.warm_weather);
+    let is_warm = warm_weather(was_warm, temperature, compensation.warm_cutoff);
+    if is_warm && !was_warm {
+        println!("  {:.1}°C outside, skipping On periods", temperature);
+        house_events.push(EventKind::WarmWeather {
+            outdoor_temperature: temperature,
+            cutoff: compensation.warm_cutoff.unwrap_or_default(),
+        });
+    } else if was_warm && !is_warm {
+        house_events.push(EventKind::WarmWeatherEnded);
+    }
+
+    *weather = Some(OutdoorWeather {
+        sensor: compensation.sensor.clone(),
+        temperature,
+        read_at: now,
+        warm_weather: is_warm,
+        setpoint_shift: compensation
+            .heating_curve
+            .map_or(0.0, |curve| curve.setpoint_shift(temperature)),
+    });
+    weather.clone()
+}
+
+/// The entity's heat-up rate, re-learned from the temperature history at most once an hour
+fn heat_up_rate<T: ClimateEntity + Clone>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+    entity_id: &str,
+    now: DateTime<Local>,
+) -> Option<f64> {
+    if let Some((learned_at, rate)) = memory.heat_up_rates.get(entity_id)
+        && now - *learned_at < HEAT_UP_RATE_REFRESH_INTERVAL
+    {
+        return *rate;
+    }
+    let samples = state
+        .temperature_history
+        .read()
+        .unwrap()
+        .samples(entity_id, None, None);
+    let rate = learn_heat_up_rate(&samples);
+    memory
+        .heat_up_rates
+        .insert(entity_id.to_string(), (now, rate));
+    rate
+}
+
+/// What the scheduler remembers between ticks
+#[derive(Debug, Default)]
+pub struct SchedulerMemory {
+    /// The state each entity should be in after the previous tick, used to spot manual changes
+    pub expected_states: HashMap<String, HeatingState>,
+    /// The last command logged per entity in dry-run mode, so it's journaled once rather than every tick
+    pub dry_run_commands: HashMap<String, HeatingState>,
+    /// The last command each entity didn't follow in time and when it was sent, so the entity
+    /// following it later isn't taken for a manual change
+    pub unverified_commands: HashMap<String, (HeatingState, DateTime<Local>)>,
+    /// Recent temperatures per entity, for spotting open windows
+    pub temperature_trends: HashMap<String, TemperatureTrend>,
+    /// When each entity last changed state, for minimum on/off times
+    pub last_switched: HashMap<String, DateTime<Local>>,
+    /// Entities that can't be read or that Home Assistant reports as unavailable or unknown,
+    /// journaled once per outage
+    pub unavailable: HashSet<String>,
+    /// Learned heat-up rate per entity and when it was worked out
+    pub heat_up_rates: HashMap<String, (DateTime<Local>, Option<f64>)>,
+    pub last_tariff_refresh: Option<DateTime<Local>>,
+    pub last_temperature_save: Option<DateTime<Local>>,
+    pub last_history_save: Option<DateTime<Local>>,
+    /// Rate limits and unreachable counts for notifications
+    pub notifier: Notifier,
+}
+
+/// Main scheduler loop that runs periodically and applies schedule
+pub async fn run_scheduler<T: ClimateEntity + Clone + 'static>(state: SchedulerState<T>) {
+    let mut interval = interval(state.tick_interval);
+    let mut memory = SchedulerMemory::default();
+
+    println!("\n=== Heating Scheduler Started ===");
+
+    loop {
+        interval.tick().await;
+        run_scheduler_tick(&state, &mut memory).await;
+    }
+}
+
+/// Run a single scheduler pass at the clock's current time
+pub async fn run_scheduler_tick<T: ClimateEntity + Clone + 'static>(
+    state: &SchedulerState<T>,
+    memory: &mut SchedulerMemory,
+) {
+    let now = state.clock.now();
+    let mut house_events: Vec<EventKind> = Vec::new();
+
+    // Get current scheduled state
+    let (desired_state, active_entry) = {
+        let schedule = state.schedule.read().unwrap();
+        (
+            schedule.get_current_state(&now),
+            schedule.get_active_entry(&now).cloned(),
+        )
+    };
+
+    if let Some(sensor) = &state.tariff_sensor
+        && memory
+            .last_tariff_refresh
+            .is_none_or(|last_refresh| now - last_refresh >= TARIFF_REFRESH_INTERVAL)
+    {
+        memory.last_tariff_refresh = Some(now);
+        refresh_tariff(state, sensor).await;
+    }
+
+    // Flexible entries heat only in the cheapest slots of their period
+    state
+        .tariff
+        .write()
+        .unwrap()
+        .plans
+        .retain(|_, plan| plan.window_end > now);
+    let desired_state = match active_entry.as_ref() {
+        Some(entry) if desired_state == HeatingState::On => match &entry.flexible {
+            Some(flexible) => {
+                flexible_heating_state(state, entry, flexible, now, &mut house_events)
             }
-            let (boosted_state, should_update) =
-                calculate_desired_heating_state_for_boost(entity.get_boosted_status());
-            if should_update {
-                entity.set_boost(None);
+            None => desired_state,
+        },
+        _ => desired_state,
+    };
+
+    // While everyone is out the home schedule is set aside
+    let away_mode = match &state.presence_settings {
+        Some(settings) => update_presence(state, settings, now, &mut house_events).await,
+        None => state.presence.read().unwrap().active_away_mode(),
+    };
+    let desired_state = match away_mode {
+        Some(AwayMode::Off) => HeatingState::Off,
+        _ => desired_state,
+    };
+
+    // Warm days skip the schedule's On periods; the heating curve moves thermostat targets
+    let compensation = state.schedule.read().unwrap().weather_compensation.clone();
+    let outdoor = match &compensation {
+        Some(compensation) => {
+            update_outdoor_weather(state, compensation, now, &mut house_events).await
+        }
+        None => {
+            *state.weather.write().unwrap() = None;
+            None
+        }
+    };
+    let warm = outdoor.as_ref().is_some_and(|outdoor| outdoor.warm_weather);
+    let setpoint_shift = outdoor
+        .as_ref()
+        .map_or(0.0, |outdoor| outdoor.setpoint_shift);
+    let desired_state = if warm {
+        HeatingState::Off
+    } else {
+        desired_state
+    };
+
+    // Clone entities to avoid holding lock across await points
+    let mut entities_clone = {
+        let climates = state.climate_entities.read().unwrap();
+        climates.clone()
+    };
+    let boosts_at_start: HashMap<String, Option<BoostInfo>> = entities_clone
+        .iter()
+        .map(|e| {
+            (
+                e.get_entity_id().to_string(),
+                e.get_boosted_status().clone(),
+            )
+        })
+        .collect();
+
+    let mut events: Vec<(String, EventKind)> = Vec::new();
+    // Commands sent in this pass, checked together once every entity has been seen to
+    let mut sent_commands: Vec<SentCommand> = Vec::new();
+    let mut samples: Vec<(String, Sample)> = Vec::new();
+    // Alerts that aren't journaled events of their own, and entities that couldn't be read
+    let mut alerts: Vec<Alert> = Vec::new();
+    let mut unreachable: HashSet<String> = HashSet::new();
+
+    // Process entities outside the lock
+    for entity in entities_clone.iter_mut() {
+        let entity_id = entity.get_entity_id().to_string();
+        let temperature_sensor = state
+            .entity_settings
+            .read()
+            .unwrap()
+            .get(&entity_id)
+            .and_then(|settings| settings.temperature_sensor.clone());
+        entity.set_temperature_sensor(temperature_sensor);
+
+        if let Err(e) = entity.fetch_and_update_state(&state.api_client).await {
+            eprintln!(
+                "[{}] Error fetching state for {}: {}",
+                now.format("%Y-%m-%d %H:%M:%S"),
+                entity_id,
+                e
+            );
+            unreachable.insert(entity_id.clone());
+            if memory.unavailable.insert(entity_id.clone()) {
+                events.push((
+                    entity_id,
+                    EventKind::Error {
+                        message: format!("Failed to fetch state: {}", e),
+                    },
+                ));
             }
-            let final_desired_state = final_desired_heating_state(&desired_state, &boosted_state);
+            continue;
+        }
+        let (boosted_state, should_update) =
+            calculate_desired_heating_state_for_boost(entity.get_boosted_status(), &now);
+        if should_update {
+            entity.set_boost(None);
+            events.push((entity_id.clone(), EventKind::BoostEnded));
+        }
+        let final_desired_state = final_desired_heating_state(&desired_state, &boosted_state);
 
-            let heating_state = entity.get_cached_state().clone().unwrap().state;
+        let climate_info = entity.get_cached_state().clone().unwrap();
+        if climate_info.availability != Availability::Available {
+            // Commands would fail, and whatever state it comes back in isn't a manual change
+            memory.expected_states.remove(&entity_id);
+            unreachable.insert(entity_id.clone());
+            if memory.unavailable.insert(entity_id.clone()) {
+                println!(
+                    "  {} is {:?}, skipping",
+                    entity_id, climate_info.availability
+                );
+                events.push((
+                    entity_id,
+                    EventKind::Error {
+                        message: format!("Entity is {:?}", climate_info.availability)
+                            .to_lowercase(),
+                    },
+                ));
+            }
+            continue;
+        }
+        if memory.unavailable.remove(&entity_id) {
+            events.push((entity_id.clone(), EventKind::Reachable));
+        }
 
-            let action =
-                calculate_heating_action_for_schedule(&heating_state, &final_desired_state);
+        let heating_state = climate_info.state.clone();
+        if let Some(temperature) = climate_info.current_temperature {
+            samples.push((
+                entity_id.clone(),
+                Sample {
+                    timestamp: now,
+                    temperature,
+                    setpoint: climate_info.target_temperature,
+                    heating_on: heating_state == HeatingState::On,
+                },
+            ));
+        }
 
-            println!("[{}] Action: {:?}", now.format("%Y-%m-%d %H:%M:%S"), action);
+        let settings = state
+            .entity_settings
+            .read()
+            .unwrap()
+            .get(&entity_id)
+            .cloned()
+            .unwrap_or_default();
+        let frost_protected = update_frost_protection(
+            state,
+            &entity_id,
+            settings.frost_protection_temperature,
+            climate_info.current_temperature,
+            now,
+            &mut events,
+        );
 
-            // Only apply changes when action is needed
-            if action != HeatingAction::NoChange {
+        // A paused entity is left alone, and whatever is done to it meanwhile isn't a manual
+        // change. Only the frost floor still applies.
+        if state
+            .runtime
+            .read()
+            .unwrap()
+            .get(&entity_id)
+            .is_some_and(|runtime| runtime.paused_since.is_some())
+        {
+            memory.expected_states.remove(&entity_id);
+            if frost_protected && heating_state == HeatingState::Off {
+                let decision = EventKind::SchedulerDecision {
+                    active_entry: active_entry.clone(),
+                    previous_state: heating_state,
+                    desired_state: HeatingState::On,
+                    boosted: false,
+                    dry_run: entity.is_dry_run(),
+                };
+                sent_commands.extend(
+                    send_command(
+                        state,
+                        memory,
+                        entity,
+                        HeatingAction::TurnOn,
+                        decision,
+                        now,
+                        &mut events,
+                        &mut alerts,
+                    )
+                    .await,
+                );
+            }
+            continue;
+        }
+
+        events.extend(detect_manual_change(
+            state,
+            memory,
+            &state.schedule,
+            &entity_id,
+            &climate_info,
+            now,
+        ));
+        let override_state = override_state(state, &entity_id, now, &mut events);
+        // A boost asked for through the API beats an override
+        let final_desired_state = match &override_state {
+            Some(override_state) if boosted_state != HeatingState::On => override_state.clone(),
+            _ => final_desired_state,
+        };
+
+        // Optimum start heats ahead of the next On entry so the room is warm when it begins
+        let optimum_start = {
+            let next_on = if settings.optimum_start
+                && desired_state == HeatingState::Off
+                && away_mode.is_none()
+                && !warm
+            {
+                state
+                    .schedule
+                    .read()
+                    .unwrap()
+                    .next_transition(&now)
+                    .filter(|transition| {
+                        transition.heating_state == HeatingState::On
+                            && transition.entry.flexible.is_none()
+                    })
+                    .and_then(|transition| {
+                        settings
+                            .target_for(Some(&transition.entry))
+                            .map(|target| (transition.time, target))
+                    })
+            } else {
+                None
+            };
+            let heat_up_rate = if next_on.is_some() {
+                heat_up_rate(state, memory, &entity_id, now)
+            } else {
+                None
+            };
+            let mut runtime = state.runtime.write().unwrap();
+            let runtime = runtime.entry(entity_id.clone()).or_default();
+            let was_preheating = runtime
+                .optimum_start
+                .as_ref()
+                .is_some_and(|plan| plan.preheating);
+            runtime.optimum_start = update_optimum_start(
+                runtime.optimum_start.take(),
+                next_on,
+                climate_info.current_temperature,
+                heat_up_rate,
+                state.optimum_start_max_lead,
+                now,
+            );
+            if let Some(plan) = &runtime.optimum_start
+                && plan.preheating
+                && !was_preheating
+            {
                 println!(
-                    "  Schedule change: {:?} → {:?}",
-                    heating_state, desired_state
+                    "  Pre-heating {} to reach {:.1}°C by {}",
+                    entity_id,
+                    plan.target_temperature,
+                    plan.target_time.format("%H:%M")
                 );
+                events.push((
+                    entity_id.clone(),
+                    EventKind::PreheatStarted {
+                        target_time: plan.target_time,
+                        target_temperature: plan.target_temperature,
+                        heat_up_rate: plan.heat_up_rate,
+                    },
+                ));
+            }
+            runtime.optimum_start.clone().filter(|plan| plan.preheating)
+        };
+        // A manual override still wins over pre-heating
+        let final_desired_state = if optimum_start.is_some() && override_state.is_none() {
+            HeatingState::On
+        } else {
+            final_desired_state
+        };
 
-                if let Err(e) =
-                    apply_heating_action(entity, action.clone(), &state.api_client).await
+        // An open window pauses heating whatever the schedule, boosts or overrides say
+        let window_open = match &settings.open_window {
+            Some(window_settings) => {
+                let sensor_open = match &window_settings.sensor {
+                    Some(sensor) => match state.api_client.fetch_entity_state(sensor).await {
+                        Ok(sensor_state) => sensor_state.state == "on",
+                        Err(e) => {
+                            eprintln!("  Error reading window sensor {}: {}", sensor, e);
+                            false
+                        }
+                    },
+                    None => false,
+                };
+                let trend = memory
+                    .temperature_trends
+                    .entry(entity_id.clone())
+                    .or_default();
+                if let Some(temperature) = climate_info.current_temperature {
+                    trend.record(
+                        now,
+                        temperature,
+                        chrono::Duration::minutes(window_settings.drop_minutes as i64),
+                    );
+                }
+                let mut runtime = state.runtime.write().unwrap();
+                let runtime = runtime.entry(entity_id.clone()).or_default();
+                match update_open_window(
+                    &mut runtime.open_window,
+                    window_settings,
+                    sensor_open,
+                    trend,
+                    now,
+                ) {
+                    Some(WindowChange::Opened(source)) => {
+                        println!(
+                            "  Open window on {} ({:?}), pausing heating",
+                            entity_id, source
+                        );
+                        // Start afresh so the same drop doesn't trigger again after the pause
+                        trend.clear();
+                        events.push((entity_id.clone(), EventKind::WindowOpen { source }));
+                    }
+                    Some(WindowChange::Closed) => {
+                        events.push((entity_id.clone(), EventKind::WindowClosed));
+                    }
+                    None => {}
+                }
+                runtime.open_window.is_some()
+            }
+            None => {
+                // Detection was switched off while a window was open
+                if let Some(runtime) = state.runtime.write().unwrap().get_mut(&entity_id)
+                    && runtime.open_window.take().is_some()
                 {
-                    eprintln!("  ✗ Error applying action: {}", e);
+                    events.push((entity_id.clone(), EventKind::WindowClosed));
                 }
+                false
+            }
+        };
+        let final_desired_state = if window_open {
+            HeatingState::Off
+        } else {
+            final_desired_state
+        };
+
+        // The safety floor beats everything else, including minimum off times
+        let final_desired_state = if frost_protected {
+            HeatingState::On
+        } else {
+            final_desired_state
+        };
+
+        // Only the schedule's On is regulated; boosts, overrides and frost protection
+        // switch the entity outright
+        let regulated = final_desired_state == HeatingState::On
+            && boosted_state != HeatingState::On
+            && override_state.is_none()
+            && !frost_protected;
+        // While pre-heating, aim for the target of the entry being heated for. Away in eco
+        // mode, every entity holds the eco temperature, whatever its control mode.
+        let thermostat_target = match (&optimum_start, away_mode) {
+            (_, Some(AwayMode::Eco { temperature })) => Some(temperature),
+            (Some(plan), _) if settings.control_mode == ControlMode::Thermostat => {
+                Some(plan.target_temperature)
+            }
+            _ => settings.thermostat_target(active_entry.as_ref()),
+        };
+        let thermostat = thermostat_target.map(|target| Thermostat {
+            target: target + setpoint_shift,
+            hysteresis: settings.hysteresis.unwrap_or(DEFAULT_HYSTERESIS),
+        });
+        let action = match thermostat {
+            Some(thermostat) if regulated => calculate_heating_action_for_thermostat(
+                &heating_state,
+                climate_info.current_temperature,
+                &thermostat,
+            ),
+            _ => calculate_heating_action_for_schedule(&heating_state, &final_desired_state),
+        };
+        let commanded_state = match action {
+            HeatingAction::TurnOn => HeatingState::On,
+            HeatingAction::TurnOff => HeatingState::Off,
+            HeatingAction::NoChange => heating_state.clone(),
+        };
+
+        println!("[{}] Action: {:?}", now.format("%Y-%m-%d %H:%M:%S"), action);
+
+        if action == HeatingAction::NoChange {
+            memory.dry_run_commands.remove(&entity_id);
+            if clear_command_failures(&state.runtime, &entity_id) {
+                events.push((entity_id, EventKind::Recovered));
+            }
+            continue;
+        }
+
+        println!(
+            "  Schedule change: {:?} → {:?}",
+            heating_state, desired_state
+        );
+
+        // HA's clock may be ahead of ours, so its last_changed only helps if it's in the past
+        let switched_at = memory
+            .last_switched
+            .get(&entity_id)
+            .copied()
+            .or(climate_info
+                .last_changed
+                .filter(|last_changed| *last_changed <= now));
+        if !frost_protected && !minimum_time_elapsed(&action, switched_at, &settings, &now) {
+            println!("  Holding {} for its minimum on/off time", entity_id);
+            continue;
+        }
+
+        let decision = EventKind::SchedulerDecision {
+            active_entry: active_entry.clone(),
+            previous_state: heating_state,
+            desired_state: commanded_state,
+            boosted: boosted_state == HeatingState::On,
+            dry_run: entity.is_dry_run(),
+        };
+        sent_commands.extend(
+            send_command(
+                state,
+                memory,
+                entity,
+                action,
+                decision,
+                now,
+                &mut events,
+                &mut alerts,
+            )
+            .await,
+        );
+    }
+
+    let outcomes = verify_sent_commands(state, &mut entities_clone, sent_commands).await;
+    record_command_outcomes(
+        state,
+        memory,
+        &state.schedule,
+        outcomes,
+        now,
+        &mut events,
+        &mut alerts,
+    );
+
+    store_processed(&state.climate_entities, &entities_clone, &boosts_at_start);
+    let hot_water_ids = update_hot_water(
+        state,
+        memory,
+        now,
+        &mut events,
+        &mut samples,
+        &mut alerts,
+        &mut unreachable,
+    )
+    .await;
+
+    {
+        let mut temperature_history = state.temperature_history.write().unwrap();
+        for (entity_id, sample) in samples {
+            temperature_history.record(&entity_id, sample);
+        }
+        if memory
+            .last_temperature_save
+            .is_none_or(|last_save| now - last_save >= TEMPERATURE_HISTORY_SAVE_INTERVAL)
+        {
+            memory.last_temperature_save = Some(now);
+            temperature_history.prune(&now);
+            if let Err(e) = timeseries_persistence::save_temperature_history(
+                &temperature_history,
+                &state.temperature_history_file_path,
+            ) {
+                eprintln!("Failed to save temperature history to disk: {}", e);
+            }
+        }
+    }
+
+    {
+        // Events from API calls are recorded straight away and saved here with the scheduler's
+        let mut history = state.history.write().unwrap();
+        for kind in house_events {
+            history.record(now, None, kind);
+        }
+        for (entity_id, kind) in events {
+            history.record(now, Some(entity_id), kind);
+        }
+        // Every journaled event, whoever recorded it, is considered for a notification here
+        let journaled = history.take_unnotified();
+        if let Some(settings) = &state.notifications {
+            alerts.extend(
+                journaled
+                    .iter()
+                    .filter_map(|event| Alert::for_event(event.entity_id.as_deref(), &event.kind)),
+            );
+            let entity_ids = entities_clone
+                .iter()
+                .map(|entity| entity.get_entity_id())
+                .chain(hot_water_ids.iter().map(String::as_str));
+            for entity_id in entity_ids {
+                let reachable = !unreachable.contains(entity_id);
+                alerts.extend(
+                    memory
+                        .notifier
+                        .track_reachability(settings, entity_id, reachable),
+                );
             }
         }
+        if history.unsaved
+            && memory
+                .last_history_save
+                .is_none_or(|last_save| now - last_save >= HISTORY_SAVE_INTERVAL)
+        {
+            memory.last_history_save = Some(now);
+            history.unsaved = false;
+            if let Err(e) = history_persistence::save_history(&history, &state.history_file_path) {
+                eprintln!("Failed to save history to disk: {}", e);
+            }
+        }
+    }
 
-        // Update the shared state with processed entities
-        if let Ok(mut climates) = state.climate_entities.write() {
-            *climates = entities_clone;
+    if let Some(settings) = &state.notifications {
+        let notifications = memory.notifier.notifications(settings, alerts, now);
+        if !notifications.is_empty() {
+            // A slow notify service mustn't hold up the next pass
+            let api_client = state.api_client.clone();
+            tokio::spawn(async move {
+                crate::notify::send(&api_client, notifications).await;
+            });
         }
     }
 }
@@ -166,6 +1514,7 @@ pub async fn run_scheduler<T: ClimateEntity + Clone>(state: SchedulerState<T>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::at;
 
     #[test]
     fn test_calculate_heating_action_no_change() {
@@ -180,6 +1529,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_calculate_desired_heating_state_for_boost() {
+        let boost = Some(BoostInfo {
+            boost_start: at(7, 0),
+            boost_end: at(7, 45),
+        });
+
+        // Inside the boost window heating is on and the boost is kept
+        assert_eq!(
+            calculate_desired_heating_state_for_boost(&boost, &at(7, 30)),
+            (HeatingState::On, false)
+        );
+        // Once the window has passed the boost should be cleared
+        assert_eq!(
+            calculate_desired_heating_state_for_boost(&boost, &at(8, 0)),
+            (HeatingState::Off, true)
+        );
+        // Without a boost nothing changes
+        assert_eq!(
+            calculate_desired_heating_state_for_boost(&None, &at(7, 30)),
+            (HeatingState::Off, false)
+        );
+    }
+
+    #[test]
+    fn test_boost_runs_past_midnight() {
+        // An hour's boost pressed at 23:30 runs until 00:30 the next day
+        let after = |minutes| at(23, 30) + chrono::Duration::minutes(minutes);
+        let boost = Some(BoostInfo {
+            boost_start: after(0),
+            boost_end: after(60),
+        });
+
+        assert_eq!(
+            calculate_desired_heating_state_for_boost(&boost, &after(45)),
+            (HeatingState::On, false)
+        );
+        assert_eq!(
+            calculate_desired_heating_state_for_boost(&boost, &after(61)),
+            (HeatingState::Off, true)
+        );
+    }
+
     #[test]
     fn test_calculate_heating_action_state_change() {
         // When states differ, change to desired
@@ -192,4 +1584,98 @@ mod tests {
             HeatingAction::TurnOff
         );
     }
+
+    #[test]
+    fn test_thermostat_action_uses_hysteresis_band() {
+        let thermostat = Thermostat {
+            target: 20.0,
+            hysteresis: 0.5,
+        };
+        let action = |state, temperature| {
+            calculate_heating_action_for_thermostat(&state, temperature, &thermostat)
+        };
+
+        assert_eq!(action(HeatingState::Off, Some(19.4)), HeatingAction::TurnOn);
+        assert_eq!(
+            action(HeatingState::On, Some(19.4)),
+            HeatingAction::NoChange
+        );
+        // Inside the band nothing changes, whichever way the room is going
+        assert_eq!(
+            action(HeatingState::Off, Some(20.4)),
+            HeatingAction::NoChange
+        );
+        assert_eq!(
+            action(HeatingState::On, Some(19.6)),
+            HeatingAction::NoChange
+        );
+        assert_eq!(action(HeatingState::On, Some(20.6)), HeatingAction::TurnOff);
+        assert_eq!(
+            action(HeatingState::Off, Some(20.6)),
+            HeatingAction::NoChange
+        );
+        // Without a reading it heats like a plain On
+        assert_eq!(action(HeatingState::Off, None), HeatingAction::TurnOn);
+    }
+
+    #[test]
+    fn test_minimum_on_and_off_times() {
+        let settings = EntitySettings {
+            min_on_minutes: Some(20),
+            min_off_minutes: Some(10),
+            ..Default::default()
+        };
+        let switched_at = Some(at(7, 0));
+
+        assert!(!minimum_time_elapsed(
+            &HeatingAction::TurnOff,
+            switched_at,
+            &settings,
+            &at(7, 15)
+        ));
+        assert!(minimum_time_elapsed(
+            &HeatingAction::TurnOff,
+            switched_at,
+            &settings,
+            &at(7, 20)
+        ));
+        assert!(!minimum_time_elapsed(
+            &HeatingAction::TurnOn,
+            switched_at,
+            &settings,
+            &at(7, 5)
+        ));
+        assert!(minimum_time_elapsed(
+            &HeatingAction::TurnOn,
+            switched_at,
+            &settings,
+            &at(7, 10)
+        ));
+        // Unknown switch time or no minimum never holds anything back
+        assert!(minimum_time_elapsed(
+            &HeatingAction::TurnOff,
+            None,
+            &settings,
+            &at(7, 1)
+        ));
+        assert!(minimum_time_elapsed(
+            &HeatingAction::TurnOff,
+            switched_at,
+            &EntitySettings::default(),
+            &at(7, 1)
+        ));
+    }
+
+    #[test]
+    fn test_frost_protection_starts_below_threshold_and_releases_above_margin() {
+        assert!(frost_protection_needed(false, Some(4.9), Some(5.0)));
+        assert!(!frost_protection_needed(false, Some(5.0), Some(5.0)));
+        // Once on it keeps heating until the room is clear of the threshold
+        assert!(frost_protection_needed(true, Some(5.5), Some(5.0)));
+        assert!(!frost_protection_needed(true, Some(6.0), Some(5.0)));
+        // A missing reading keeps the current decision, no threshold means no protection
+        assert!(frost_protection_needed(true, None, Some(5.0)));
+        assert!(!frost_protection_needed(false, None, Some(5.0)));
+        assert!(!frost_protection_needed(true, Some(-3.0), None));
+    }
 }
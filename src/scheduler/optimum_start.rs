@@ -0,0 +1,182 @@
+use crate::scheduler::runtime::OptimumStart;
+use crate::timeseries::Sample;
+use chrono::{DateTime, Duration, Local};
+
+/// Heating runs shorter than this say more about sensor noise than about the room
+const MIN_RUN_MINUTES: i64 = 15;
+/// A gap this long between samples ends a run, e.g. while the scheduler was stopped
+const MAX_SAMPLE_GAP_MINUTES: i64 = 30;
+
+/// How fast an entity's room warms while heating, in °C per hour.
+///
+/// Averages the temperature rise over every run of consecutive heating-on samples,
+/// weighted by run length. None until at least one run shows the room warming.
+pub fn learn_heat_up_rate(samples: &[Sample]) -> Option<f64> {
+    let mut rise = 0.0;
+    let mut hours = 0.0;
+    let mut run: Option<(&Sample, &Sample)> = None;
+
+    let mut finish = |run: Option<(&Sample, &Sample)>| {
+        if let Some((first, last)) = run {
+            let length = last.timestamp - first.timestamp;
+            let run_rise = last.temperature - first.temperature;
+            if length >= Duration::minutes(MIN_RUN_MINUTES) && run_rise > 0.0 {
+                rise += run_rise;
+                hours += length.num_seconds() as f64 / 3600.0;
+            }
+        }
+    };
+
+    for sample in samples {
+        run = match run {
+            Some((first, last))
+                if sample.heating_on
+                    && sample.timestamp - last.timestamp
+                        <= Duration::minutes(MAX_SAMPLE_GAP_MINUTES) =>
+            {
+                Some((first, sample))
+            }
+            _ => {
+                finish(run);
+                sample.heating_on.then_some((sample, sample))
+            }
+        };
+    }
+    finish(run);
+
+    (hours > 0.0).then(|| rise / hours)
+}
+
+/// Work out when heating has to start to reach `target_temperature` at `target_time`.
+///
+/// `next_on` is the time and target of the next On entry. A pre-heat that has already begun
+/// for the same entry is kept as it is, so heating doesn't stop as the remaining lead shrinks.
+pub fn update_optimum_start(
+    previous: Option<OptimumStart>,
+    next_on: Option<(DateTime<Local>, f64)>,
+    current_temperature: Option<f64>,
+    heat_up_rate: Option<f64>,
+    max_lead: Duration,
+    now: DateTime<Local>,
+) -> Option<OptimumStart> {
+    let (target_time, target_temperature) = next_on?;
+    if let Some(previous) = previous
+        && previous.preheating
+        && previous.target_time == target_time
+    {
+        return Some(previous);
+    }
+
+    let heat_up_rate = heat_up_rate.filter(|rate| *rate > 0.0)?;
+    let needed = (target_temperature - current_temperature?).max(0.0);
+    let lead = Duration::seconds((needed / heat_up_rate * 3600.0) as i64).min(max_lead);
+    let start = target_time - lead;
+
+    Some(OptimumStart {
+        start,
+        target_time,
+        target_temperature,
+        heat_up_rate,
+        preheating: start <= now && now < target_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+
+    fn sample(hour: u32, minute: u32, temperature: f64, heating_on: bool) -> Sample {
+        Sample {
+            timestamp: at(hour, minute),
+            temperature,
+            setpoint: None,
+            heating_on,
+        }
+    }
+
+    #[test]
+    fn test_learn_heat_up_rate() {
+        assert_eq!(learn_heat_up_rate(&[]), None);
+
+        let samples = vec![
+            sample(6, 0, 16.0, false),
+            // 2 °C in 30 minutes
+            sample(6, 30, 16.0, true),
+            sample(6, 45, 17.0, true),
+            sample(7, 0, 18.0, true),
+            sample(7, 15, 18.0, false),
+            // Too short to count
+            sample(8, 0, 18.0, true),
+            sample(8, 5, 19.0, true),
+            sample(9, 0, 17.0, false),
+            // 1 °C in 30 minutes
+            sample(17, 0, 17.0, true),
+            sample(17, 30, 18.0, true),
+        ];
+        // 3 °C over an hour of heating
+        assert_eq!(learn_heat_up_rate(&samples), Some(3.0));
+
+        // A gap in the samples splits a run
+        let gappy = vec![
+            sample(6, 0, 16.0, true),
+            sample(6, 5, 16.5, true),
+            sample(7, 0, 19.0, true),
+            sample(7, 5, 19.5, true),
+        ];
+        assert_eq!(learn_heat_up_rate(&gappy), None);
+    }
+
+    #[test]
+    fn test_update_optimum_start() {
+        let max_lead = Duration::hours(2);
+        let next_on = Some((at(7, 0), 21.0));
+
+        // 3 °C to go at 2 °C/h needs 90 minutes
+        let plan =
+            update_optimum_start(None, next_on, Some(18.0), Some(2.0), max_lead, at(5, 0)).unwrap();
+        assert_eq!(plan.start, at(5, 30));
+        assert!(!plan.preheating);
+
+        let plan = update_optimum_start(
+            Some(plan),
+            next_on,
+            Some(18.0),
+            Some(2.0),
+            max_lead,
+            at(5, 30),
+        )
+        .unwrap();
+        assert!(plan.preheating);
+
+        // Once started it holds even though the room is now nearly warm
+        let held = update_optimum_start(
+            Some(plan.clone()),
+            next_on,
+            Some(20.9),
+            Some(2.0),
+            max_lead,
+            at(6, 0),
+        )
+        .unwrap();
+        assert_eq!(held, plan);
+
+        // The lead is capped
+        let cold =
+            update_optimum_start(None, next_on, Some(10.0), Some(2.0), max_lead, at(4, 0)).unwrap();
+        assert_eq!(cold.start, at(5, 0));
+
+        // Already warm enough, or nothing learned yet
+        let warm =
+            update_optimum_start(None, next_on, Some(22.0), Some(2.0), max_lead, at(5, 0)).unwrap();
+        assert_eq!(warm.start, at(7, 0));
+        assert_eq!(
+            update_optimum_start(None, next_on, Some(18.0), None, max_lead, at(5, 0)),
+            None
+        );
+        assert_eq!(
+            update_optimum_start(None, None, Some(18.0), Some(2.0), max_lead, at(5, 0)),
+            None
+        );
+    }
+}
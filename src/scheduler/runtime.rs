@@ -0,0 +1,257 @@
+use crate::schedule::{HeatingState, Schedule};
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Consecutive failed commands after which an entity is marked degraded
+pub const DEGRADED_AFTER_FAILURES: u32 = 3;
+/// Wait before the first retry, doubled after each further failure
+const RETRY_BASE_DELAY_SECS: i64 = 30;
+const RETRY_MAX_DELAY_SECS: i64 = 15 * 60;
+
+/// What the scheduler knows about an entity beyond its Home Assistant state, shared with the API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityRuntime {
+    /// Commands in a row that failed or weren't reflected in the entity's state
+    pub failed_attempts: u32,
+    /// No command is sent before this time after a failure
+    pub next_retry: Option<DateTime<Local>>,
+    pub degraded: bool,
+    pub last_error: Option<String>,
+    /// Someone changed the entity by hand and the scheduler is leaving it alone
+    pub manual_override: Option<ManualOverride>,
+    /// The room got too cold and heating is forced on
+    pub frost_protection: Option<FrostProtection>,
+    /// Heating is paused because a window is open
+    pub open_window: Option<OpenWindow>,
+    /// When heating has to start to reach the next On entry's target on time
+    pub optimum_start: Option<OptimumStart>,
+    /// Set while the scheduler is paused for this entity and sends it no commands at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused_since: Option<DateTime<Local>>,
+}
+
+/// Pre-heat planned ahead of the next On entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptimumStart {
+    /// When heating starts, at most the maximum lead time before `target_time`
+    pub start: DateTime<Local>,
+    /// When the On entry begins and the room should be at its target
+    pub target_time: DateTime<Local>,
+    pub target_temperature: f64,
+    /// Learned from past heating, in °C per hour
+    pub heat_up_rate: f64,
+    /// Heating has started early; it carries on until `target_time`
+    pub preheating: bool,
+}
+
+/// Heating paused for an open window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenWindow {
+    pub source: WindowSource,
+    pub since: DateTime<Local>,
+    /// When a pause after a temperature drop ends; sensor pauses last until the contact closes
+    pub until: Option<DateTime<Local>>,
+}
+
+/// What gave the open window away
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowSource {
+    Sensor,
+    TemperatureDrop,
+}
+
+/// Heating forced on because the room dropped below its frost threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrostProtection {
+    pub since: DateTime<Local>,
+    pub threshold: f64,
+}
+
+/// A state set outside the scheduler that is honoured for a while
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManualOverride {
+    pub state: HeatingState,
+    pub since: DateTime<Local>,
+    /// Open-ended when the schedule has no upcoming transition
+    pub until: Option<DateTime<Local>>,
+    /// The Home Assistant user who made the change, if it wasn't made on the device itself
+    pub user_id: Option<String>,
+}
+
+impl ManualOverride {
+    pub fn is_active(&self, now: &DateTime<Local>) -> bool {
+        self.until.is_none_or(|until| *now < until)
+    }
+}
+
+/// How long a manual change is honoured, set with `MANUAL_OVERRIDE`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverridePolicy {
+    /// Until the schedule next changes state
+    #[default]
+    UntilNextTransition,
+    /// For a fixed number of minutes
+    Minutes(u32),
+    /// Manual changes are reverted on the next pass
+    Disabled,
+}
+
+impl OverridePolicy {
+    /// When an override starting at `since` ends, or None if manual changes aren't honoured
+    pub fn override_until(
+        &self,
+        schedule: &Schedule,
+        since: &DateTime<Local>,
+    ) -> Option<Option<DateTime<Local>>> {
+        match self {
+            OverridePolicy::UntilNextTransition => Some(
+                schedule
+                    .next_transition(since)
+                    .map(|transition| transition.time),
+            ),
+            OverridePolicy::Minutes(minutes) => {
+                Some(Some(*since + Duration::minutes(*minutes as i64)))
+            }
+            OverridePolicy::Disabled => None,
+        }
+    }
+}
+
+impl FromStr for OverridePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "next_transition" => Ok(OverridePolicy::UntilNextTransition),
+            "disabled" | "off" | "0" => Ok(OverridePolicy::Disabled),
+            other => other
+                .parse()
+                .map(OverridePolicy::Minutes)
+                .map_err(|_| anyhow!("Unknown manual override policy: {}", other)),
+        }
+    }
+}
+
+impl EntityRuntime {
+    /// Whether a command may be sent now, or the entity is still backing off
+    pub fn can_retry(&self, now: &DateTime<Local>) -> bool {
+        self.next_retry.is_none_or(|next_retry| *now >= next_retry)
+    }
+
+    /// Record a failed command and schedule the next attempt.
+    /// Returns true when this failure made the entity degraded.
+    pub fn record_failure(&mut self, now: DateTime<Local>, error: String) -> bool {
+        self.failed_attempts += 1;
+        self.next_retry = Some(now + retry_delay(self.failed_attempts));
+        self.last_error = Some(error);
+        let was_degraded = self.degraded;
+        self.degraded = self.failed_attempts >= DEGRADED_AFTER_FAILURES;
+        self.degraded && !was_degraded
+    }
+
+    /// The entity reached the desired state. Returns true if it had been degraded.
+    pub fn clear_failures(&mut self) -> bool {
+        let was_degraded = self.degraded;
+        self.failed_attempts = 0;
+        self.next_retry = None;
+        self.degraded = false;
+        self.last_error = None;
+        was_degraded
+    }
+
+    /// The override in force at `now`, if any
+    pub fn active_override(&self, now: &DateTime<Local>) -> Option<&ManualOverride> {
+        self.manual_override
+            .as_ref()
+            .filter(|manual_override| manual_override.is_active(now))
+    }
+}
+
+/// Back-off before the next attempt after `failed_attempts` failures
+pub fn retry_delay(failed_attempts: u32) -> Duration {
+    let doublings = failed_attempts.saturating_sub(1).min(16);
+    Duration::seconds((RETRY_BASE_DELAY_SECS << doublings).min(RETRY_MAX_DELAY_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_cap() {
+        assert_eq!(retry_delay(1), Duration::seconds(30));
+        assert_eq!(retry_delay(2), Duration::seconds(60));
+        assert_eq!(retry_delay(3), Duration::seconds(120));
+        assert_eq!(retry_delay(10), Duration::minutes(15));
+        assert_eq!(retry_delay(u32::MAX), Duration::minutes(15));
+    }
+
+    #[test]
+    fn test_failures_back_off_and_degrade() {
+        let now = at(7, 0);
+        let mut runtime = EntityRuntime::default();
+        assert!(runtime.can_retry(&now));
+
+        assert!(!runtime.record_failure(now, "still off".to_string()));
+        assert!(!runtime.can_retry(&now));
+        assert!(runtime.can_retry(&(now + Duration::seconds(30))));
+
+        assert!(!runtime.record_failure(now, "still off".to_string()));
+        assert!(runtime.record_failure(now, "still off".to_string()));
+        assert!(runtime.degraded);
+        // Only the failure that crosses the threshold reports it
+        assert!(!runtime.record_failure(now, "still off".to_string()));
+        assert_eq!(runtime.failed_attempts, 4);
+        assert_eq!(runtime.next_retry, Some(now + Duration::minutes(4)));
+
+        assert!(runtime.clear_failures());
+        assert_eq!(runtime, EntityRuntime::default());
+    }
+
+    #[test]
+    fn test_override_policy() {
+        use crate::schedule::{ScheduleEntry, TimePeriod};
+
+        let mut schedule = Schedule::new("Test");
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+
+        assert_eq!(
+            OverridePolicy::UntilNextTransition.override_until(&schedule, &at(12, 0)),
+            Some(Some(at(17, 0)))
+        );
+        assert_eq!(
+            OverridePolicy::Minutes(90).override_until(&schedule, &at(12, 0)),
+            Some(Some(at(13, 30)))
+        );
+        assert_eq!(
+            OverridePolicy::UntilNextTransition.override_until(&Schedule::new("Off"), &at(12, 0)),
+            Some(None)
+        );
+        assert_eq!(
+            OverridePolicy::Disabled.override_until(&schedule, &at(12, 0)),
+            None
+        );
+
+        assert_eq!(
+            "next-transition".parse::<OverridePolicy>().unwrap(),
+            OverridePolicy::UntilNextTransition
+        );
+        assert_eq!(
+            "0".parse::<OverridePolicy>().unwrap(),
+            OverridePolicy::Disabled
+        );
+        assert_eq!(
+            "45".parse::<OverridePolicy>().unwrap(),
+            OverridePolicy::Minutes(45)
+        );
+        assert!("sometimes".parse::<OverridePolicy>().is_err());
+    }
+}
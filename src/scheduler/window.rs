@@ -0,0 +1,165 @@
+use crate::config::entities_persistence::OpenWindowSettings;
+use crate::scheduler::runtime::{OpenWindow, WindowSource};
+use chrono::{DateTime, Duration, Local};
+use std::collections::VecDeque;
+
+/// Recent temperature readings of one entity, kept just long enough to spot a sharp drop
+#[derive(Debug, Clone, Default)]
+pub struct TemperatureTrend {
+    readings: VecDeque<(DateTime<Local>, f64)>,
+}
+
+impl TemperatureTrend {
+    /// Add a reading and forget those older than `span`
+    pub fn record(&mut self, time: DateTime<Local>, temperature: f64, span: Duration) {
+        self.readings.push_back((time, temperature));
+        while let Some((oldest, _)) = self.readings.front()
+            && time - *oldest > span
+        {
+            self.readings.pop_front();
+        }
+    }
+
+    /// How far the latest reading is below the warmest one still remembered
+    pub fn drop(&self) -> f64 {
+        let Some((_, latest)) = self.readings.back() else {
+            return 0.0;
+        };
+        let warmest = self
+            .readings
+            .iter()
+            .map(|(_, temperature)| *temperature)
+            .fold(f64::MIN, f64::max);
+        warmest - latest
+    }
+
+    pub fn clear(&mut self) {
+        self.readings.clear();
+    }
+}
+
+/// How an entity's open-window pause changed on this pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowChange {
+    Opened(WindowSource),
+    Closed,
+}
+
+/// Start or end an open-window pause from the sensor and the temperature trend
+pub fn update_open_window(
+    current: &mut Option<OpenWindow>,
+    settings: &OpenWindowSettings,
+    sensor_open: bool,
+    trend: &TemperatureTrend,
+    now: DateTime<Local>,
+) -> Option<WindowChange> {
+    if let Some(window) = current {
+        let closed = match window.source {
+            WindowSource::Sensor => !sensor_open,
+            WindowSource::TemperatureDrop => window.until.is_some_and(|until| now >= until),
+        };
+        if closed {
+            *current = None;
+            return Some(WindowChange::Closed);
+        }
+        return None;
+    }
+
+    let (source, until) = if sensor_open {
+        (WindowSource::Sensor, None)
+    } else if settings
+        .drop_celsius
+        .is_some_and(|drop_celsius| trend.drop() >= drop_celsius)
+    {
+        (
+            WindowSource::TemperatureDrop,
+            Some(now + Duration::minutes(settings.pause_minutes as i64)),
+        )
+    } else {
+        return None;
+    };
+    *current = Some(OpenWindow {
+        source,
+        since: now,
+        until,
+    });
+    Some(WindowChange::Opened(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+
+    fn settings() -> OpenWindowSettings {
+        OpenWindowSettings {
+            sensor: None,
+            drop_celsius: Some(1.0),
+            drop_minutes: 5,
+            pause_minutes: 30,
+        }
+    }
+
+    #[test]
+    fn test_trend_only_remembers_the_span() {
+        let mut trend = TemperatureTrend::default();
+        let span = Duration::minutes(5);
+        trend.record(at(7, 0), 21.0, span);
+        trend.record(at(7, 3), 20.5, span);
+        assert_eq!(trend.drop(), 0.5);
+
+        // The 21.0 reading is now too old to count
+        trend.record(at(7, 6), 20.0, span);
+        assert_eq!(trend.drop(), 0.5);
+
+        trend.clear();
+        assert_eq!(trend.drop(), 0.0);
+    }
+
+    #[test]
+    fn test_sharp_drop_pauses_for_a_while() {
+        let mut trend = TemperatureTrend::default();
+        let span = Duration::minutes(5);
+        trend.record(at(7, 0), 21.0, span);
+        trend.record(at(7, 2), 19.8, span);
+
+        let mut window = None;
+        assert_eq!(
+            update_open_window(&mut window, &settings(), false, &trend, at(7, 2)),
+            Some(WindowChange::Opened(WindowSource::TemperatureDrop))
+        );
+        assert_eq!(window.as_ref().unwrap().until, Some(at(7, 32)));
+        assert_eq!(
+            update_open_window(&mut window, &settings(), false, &trend, at(7, 31)),
+            None
+        );
+        assert_eq!(
+            update_open_window(&mut window, &settings(), false, &trend, at(7, 32)),
+            Some(WindowChange::Closed)
+        );
+        assert!(window.is_none());
+    }
+
+    #[test]
+    fn test_sensor_pauses_until_closed() {
+        let trend = TemperatureTrend::default();
+        let mut window = None;
+
+        assert_eq!(
+            update_open_window(&mut window, &settings(), false, &trend, at(7, 0)),
+            None
+        );
+        assert_eq!(
+            update_open_window(&mut window, &settings(), true, &trend, at(7, 1)),
+            Some(WindowChange::Opened(WindowSource::Sensor))
+        );
+        assert_eq!(
+            update_open_window(&mut window, &settings(), true, &trend, at(9, 0)),
+            None
+        );
+        assert_eq!(
+            update_open_window(&mut window, &settings(), false, &trend, at(9, 1)),
+            Some(WindowChange::Closed)
+        );
+    }
+}
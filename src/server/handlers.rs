@@ -1,21 +1,28 @@
-use crate::climate::{BoostInfo, ClimateEntity};
+use crate::climate::climate_state_api::ApiHeatingState;
+use crate::climate::{Availability, BoostInfo, ClimateEntity, EntityDomain};
+use crate::config::entities_persistence::EntitySettings;
+use crate::history::{EventKind, HistoryEvent, HistoryQuery};
+use crate::presence::{AwayMode, Occupancy};
 use crate::schedule::persistence;
-use crate::schedule::{Schedule, ScheduleEntry, ScheduleEntryRequest};
-use crate::server::AppState;
+use crate::schedule::{HeatingState, Schedule, ScheduleEntry, ScheduleEntryRequest};
+use crate::scheduler::runtime::ManualOverride;
+use crate::server::{AppState, Channel};
+use crate::simulation::{SimulationResult, simulate};
+use crate::stats::{MAX_STATS_DAYS, StatsFormat, StatsQuery, compute_stats, to_csv};
+use crate::status::{HotWaterStatus, SchedulerStatus, build_hot_water_status, build_status};
+use crate::tariff::{TariffPlans, period_minutes};
+use crate::timeseries::{SeriesPoint, SeriesQuery};
+use crate::weather::WeatherCompensation;
 use axum::Json;
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use chrono::{Duration, Local};
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Local};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::climate::ClimateEntityWrapper;
-#[cfg(debug_assertions)]
-use crate::climate::MockClimate;
-#[cfg(not(debug_assertions))]
-use crate::climate::DefaultClimate;
-#[cfg(debug_assertions)]
-use crate::schedule::HeatingState;
 
 pub async fn get_schedule<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
@@ -28,18 +35,88 @@ pub async fn add_schedule_entry<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
     Json(payload): Json<ScheduleEntryRequest>,
 ) -> Result<Json<Schedule>, (StatusCode, String)> {
+    add_entry(&state, Channel::Heating, payload)
+}
+
+/// Add an entry to a channel's schedule and save it
+fn add_entry<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    payload: ScheduleEntryRequest,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    if let Some(flexible) = &payload.flexible {
+        if payload.heating_state != HeatingState::On {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Only On entries can be flexible".to_string(),
+            ));
+        }
+        let window = period_minutes(&payload.time_period);
+        if flexible.duration_minutes == 0 || flexible.duration_minutes as i64 > window {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Flexible duration must be between 1 and {} minutes for this period",
+                    window
+                ),
+            ));
+        }
+    }
+
     // Convert request to ScheduleEntry (generates UUID automatically)
     let entry: ScheduleEntry = payload.into();
 
     // Add entry to the in-memory schedule
     let updated_schedule = {
-        let mut schedule = state.schedule.write().unwrap();
+        let mut schedule = state.schedule_of(channel).0.write().unwrap();
         schedule.add_entry(entry);
         schedule.clone()
     };
 
-    // Persist the updated schedule to disk
-    if let Err(e) = persistence::save_schedule(&updated_schedule, &state.schedule_file_path) {
+    persist_schedule(state, channel, updated_schedule)
+}
+
+/// Turn on or replace weather compensation for the schedule
+pub async fn set_weather_compensation<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(payload): Json<WeatherCompensation>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    if !payload.sensor.starts_with("weather.") && !payload.sensor.starts_with("sensor.") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Outdoor sensor must be a weather or sensor entity: {}",
+                payload.sensor
+            ),
+        ));
+    }
+    let updated_schedule = {
+        let mut schedule = state.schedule.write().unwrap();
+        schedule.weather_compensation = Some(payload);
+        schedule.clone()
+    };
+    persist_schedule(&state, Channel::Heating, updated_schedule)
+}
+
+/// Turn weather compensation off so the schedule applies whatever the weather
+pub async fn clear_weather_compensation<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    let updated_schedule = {
+        let mut schedule = state.schedule.write().unwrap();
+        schedule.weather_compensation = None;
+        schedule.clone()
+    };
+    persist_schedule(&state, Channel::Heating, updated_schedule)
+}
+
+/// Save a channel's schedule after a change and return it
+fn persist_schedule<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    schedule: Schedule,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    if let Err(e) = persistence::save_schedule(&schedule, state.schedule_of(channel).1) {
         eprintln!("Failed to save schedule to disk: {}", e);
         return Err((
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -48,16 +125,25 @@ pub async fn add_schedule_entry<T: ClimateEntity + Clone>(
     }
 
     println!("Schedule updated and saved");
-    Ok(Json(updated_schedule))
+    Ok(Json(schedule))
 }
 
 pub async fn delete_schedule_entry<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
     Path(entry_id): Path<Uuid>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    delete_entry(&state, Channel::Heating, entry_id)
+}
+
+/// Remove an entry from a channel's schedule and save it
+fn delete_entry<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    entry_id: Uuid,
 ) -> Result<Json<Schedule>, (StatusCode, String)> {
     // Delete entry from the in-memory schedule
     let updated_schedule = {
-        let mut schedule = state.schedule.write().unwrap();
+        let mut schedule = state.schedule_of(channel).0.write().unwrap();
 
         // Attempt to delete the entry
         if let Err(e) = schedule.delete_entry(entry_id) {
@@ -70,36 +156,304 @@ pub async fn delete_schedule_entry<T: ClimateEntity + Clone>(
         schedule.clone()
     };
 
-    // Persist the updated schedule to disk
-    if let Err(e) = persistence::save_schedule(&updated_schedule, &state.schedule_file_path) {
-        eprintln!("Failed to save schedule to disk: {}", e);
+    persist_schedule(state, channel, updated_schedule)
+}
+
+/// Active schedule entry, next transition, boosts and the effective state of every entity
+pub async fn get_status<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Json<SchedulerStatus> {
+    Json(current_status(&state))
+}
+
+pub(crate) fn current_status<T: ClimateEntity + Clone>(state: &AppState<T>) -> SchedulerStatus {
+    let schedule = state.schedule.read().unwrap().clone();
+    let climates = state.climate_entities.read().unwrap().clone();
+    let runtime = state.runtime.read().unwrap();
+    let presence = state.presence.read().unwrap();
+    let weather = state.weather.read().unwrap();
+    let tariff = state.tariff.read().unwrap();
+    let mut status = build_status(
+        &schedule,
+        &climates,
+        &runtime,
+        &presence,
+        weather.as_ref(),
+        &tariff,
+        state.clock.now(),
+    );
+    if !state.hot_water_entities.read().unwrap().is_empty() {
+        status.hot_water = Some(hot_water_status(state));
+    }
+    status
+}
+
+pub(crate) fn hot_water_status<T: ClimateEntity + Clone>(state: &AppState<T>) -> HotWaterStatus {
+    let schedule = state.hot_water_schedule.read().unwrap().clone();
+    let entities = state.hot_water_entities.read().unwrap().clone();
+    let runtime = state.runtime.read().unwrap().clone();
+    build_hot_water_status(&schedule, &entities, &runtime, state.clock.now())
+}
+
+/// Known energy prices and the slots picked for flexible entries
+pub async fn get_tariff<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Json<TariffPlans> {
+    Json(state.tariff.read().unwrap().clone())
+}
+
+/// Longest range a simulation may cover
+const MAX_SIMULATION_DAYS: i64 = 31;
+
+fn default_include_boosts() -> bool {
+    true
+}
+
+/// Request body for previewing a schedule over a date range
+#[derive(Serialize, Deserialize)]
+pub struct SimulationRequest {
+    pub from: DateTime<Local>,
+    pub to: DateTime<Local>,
+    /// Schedule to preview; the current schedule is used when omitted
+    pub schedule: Option<Schedule>,
+    /// Proposed entry to add before simulating
+    pub add_entry: Option<ScheduleEntryRequest>,
+    /// Proposed entry to delete before simulating
+    pub delete_entry_id: Option<Uuid>,
+    /// Whether the entities' active boosts are taken into account
+    #[serde(default = "default_include_boosts")]
+    pub include_boosts: bool,
+}
+
+/// Preview the desired state of every entity over a date range without saving anything
+pub async fn simulate_schedule<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(request): Json<SimulationRequest>,
+) -> Result<Json<SimulationResult>, (StatusCode, String)> {
+    if request.from >= request.to {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "'from' must be before 'to'".to_string(),
+        ));
+    }
+    if request.to - request.from > Duration::days(MAX_SIMULATION_DAYS) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Simulations are limited to {} days", MAX_SIMULATION_DAYS),
+        ));
+    }
+
+    let mut schedule = match request.schedule {
+        Some(schedule) => schedule,
+        None => state.schedule.read().unwrap().clone(),
+    };
+    if let Some(entry_id) = request.delete_entry_id
+        && let Err(e) = schedule.delete_entry(entry_id)
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Failed to delete entry: {}", e),
+        ));
+    }
+    if let Some(entry) = request.add_entry {
+        schedule.add_entry(entry.into());
+    }
+
+    let entities: Vec<_> = {
+        let climates = state.climate_entities.read().unwrap();
+        climates
+            .iter()
+            .map(|entity| {
+                let boost = if request.include_boosts {
+                    entity.get_boosted_status().clone()
+                } else {
+                    None
+                };
+                (entity.get_entity_id().to_string(), boost)
+            })
+            .collect()
+    };
+
+    Ok(Json(simulate(
+        &schedule,
+        &entities,
+        request.from,
+        request.to,
+    )))
+}
+
+/// Record boost events in the history journal and persist it
+fn record_boosts<T: ClimateEntity + Clone>(state: &AppState<T>, boosts: Vec<(String, BoostInfo)>) {
+    let events = boosts
+        .into_iter()
+        .map(|(entity_id, boost)| {
+            (
+                entity_id,
+                EventKind::Boost {
+                    boost_start: boost.boost_start.time(),
+                    boost_end: boost.boost_end.time(),
+                },
+            )
+        })
+        .collect();
+    record_events(state, events);
+}
+
+/// Record events caused by API calls in the history journal; the scheduler saves it and
+/// sends any notifications they call for on its next pass
+fn record_events<T: ClimateEntity + Clone>(state: &AppState<T>, events: Vec<(String, EventKind)>) {
+    let mut history = state.history.write().unwrap();
+    let now = state.clock.now();
+    for (entity_id, kind) in events {
+        history.record(now, Some(entity_id), kind);
+    }
+}
+
+/// Record an event about the whole house in the history journal, saved and notified like
+/// `record_events`
+fn record_house_event<T: ClimateEntity + Clone>(state: &AppState<T>, kind: EventKind) {
+    let mut history = state.history.write().unwrap();
+    history.record(state.clock.now(), None, kind);
+}
+
+/// How long `boost_all` and boosts without a length last
+pub const DEFAULT_BOOST_MINUTES: i64 = 45;
+
+/// Whether a command naming `entity_ids` applies to an entity; naming none means all of them
+fn is_selected(entity_ids: Option<&[String]>, entity_id: &str) -> bool {
+    entity_ids.is_none_or(|entity_ids| entity_ids.iter().any(|id| id == entity_id))
+}
+
+/// Boost the named entities, or every entity, of a channel and journal it. Returns the entities
+/// boosted.
+pub(crate) fn start_boost<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    entity_ids: Option<&[String]>,
+    minutes: i64,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let Ok(mut climates) = state.entities_of(channel).write() else {
         return Err((
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to persist schedule: {}", e),
+            "Error Locking".to_string(),
         ));
+    };
+    let mut boosts = Vec::new();
+    for entity in climates.iter_mut() {
+        if !is_selected(entity_ids, entity.get_entity_id()) {
+            continue;
+        }
+        let now = state.clock.now();
+        let boost_info = BoostInfo {
+            boost_start: now,
+            boost_end: now + Duration::minutes(minutes),
+        };
+        boosts.push((entity.get_entity_id().to_string(), boost_info.clone()));
+        entity.set_boost(Some(boost_info));
+    }
+    drop(climates);
+    let boosted = boosts
+        .iter()
+        .map(|(entity_id, _)| entity_id.clone())
+        .collect();
+    record_boosts(state, boosts);
+    Ok(boosted)
+}
+
+/// End the boosts of the named entities, or every entity, of a channel and journal it. Returns
+/// the entities that were boosted.
+pub(crate) fn cancel_boost<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    entity_ids: Option<&[String]>,
+) -> Result<Vec<String>, (StatusCode, String)> {
+    let Ok(mut climates) = state.entities_of(channel).write() else {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Error Locking".to_string(),
+        ));
+    };
+    let mut cancelled = Vec::new();
+    for entity in climates.iter_mut() {
+        if is_selected(entity_ids, entity.get_entity_id()) && entity.get_boosted_status().is_some()
+        {
+            entity.set_boost(None);
+            cancelled.push(entity.get_entity_id().to_string());
+        }
+    }
+    drop(climates);
+    let events = cancelled
+        .iter()
+        .map(|entity_id| (entity_id.clone(), EventKind::BoostEnded))
+        .collect();
+    record_events(state, events);
+    Ok(cancelled)
+}
+
+/// Pause or resume the scheduler for the named entities, or every entity of both channels, and
+/// journal it. Returns the entities whose pause changed.
+pub(crate) fn set_paused<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    entity_ids: Option<&[String]>,
+    paused: bool,
+) -> Vec<String> {
+    let now = state.clock.now();
+    let managed: Vec<String> = [Channel::Heating, Channel::HotWater]
+        .into_iter()
+        .flat_map(|channel| channel_entity_ids(state, channel))
+        .filter(|entity_id| is_selected(entity_ids, entity_id))
+        .collect();
+    let mut changed = Vec::new();
+    {
+        let mut runtime = state.runtime.write().unwrap();
+        for entity_id in managed {
+            let runtime = runtime.entry(entity_id.clone()).or_default();
+            if runtime.paused_since.is_some() != paused {
+                runtime.paused_since = paused.then_some(now);
+                changed.push(entity_id);
+            }
+        }
     }
+    let kind = if paused {
+        EventKind::Paused
+    } else {
+        EventKind::Resumed
+    };
+    let events = changed
+        .iter()
+        .map(|entity_id| (entity_id.clone(), kind.clone()))
+        .collect();
+    record_events(state, events);
+    changed
+}
 
-    println!("Schedule entry deleted and saved");
-    Ok(Json(updated_schedule))
+/// Declare the house away, or hand it back to the presence trackers, and journal any change
+pub(crate) fn set_away<T: ClimateEntity + Clone>(state: &AppState<T>, away: bool) -> Occupancy {
+    let (changed, presence) = {
+        let mut presence = state.presence.write().unwrap();
+        (
+            presence.force_away(away, state.clock.now()),
+            presence.clone(),
+        )
+    };
+    match changed {
+        Some(Occupancy::Away) => record_house_event(
+            state,
+            EventKind::Away {
+                away_mode: presence.away_mode.unwrap_or(AwayMode::Off),
+            },
+        ),
+        Some(Occupancy::Home) => record_house_event(state, EventKind::Home),
+        None => {}
+    }
+    presence.occupancy
 }
 
 pub async fn boost_all<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    if let Ok(mut climates) = state.climate_entities.write() {
-        for entity in climates.iter_mut() {
-            let now = Local::now().time();
-            entity.set_boost(Some(BoostInfo {
-                boost_start: now.clone(),
-                boost_end: now + Duration::minutes(45),
-            }));
-        }
-        return Ok(StatusCode::OK);
-    }
-    Err((
-        StatusCode::INTERNAL_SERVER_ERROR,
-        "Error Locking".to_string(),
-    ))
+    start_boost(&state, Channel::Heating, None, DEFAULT_BOOST_MINUTES)?;
+    Ok(StatusCode::OK)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -111,55 +465,148 @@ pub async fn boost<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
     Json(boost_climates): Json<BoostInput>,
 ) -> Result<StatusCode, (StatusCode, String)> {
-    if let Ok(mut climates) = state.climate_entities.write() {
-        for entity in climates.iter_mut() {
-            // Only boost climates whose entity_id matches one in the climate_names list
-            if boost_climates
-                .climate_names
-                .contains(&entity.get_entity_id().to_string())
-            {
-                let now = Local::now().time();
-                entity.set_boost(Some(BoostInfo {
-                    boost_start: now,
-                    boost_end: now + Duration::minutes(boost_climates.time_length as i64),
-                }));
-            }
-        }
-        return Ok(StatusCode::OK);
-    }
-    Err((
-        StatusCode::INTERNAL_SERVER_ERROR,
-        "Error Locking".to_string(),
+    // Only boost climates whose entity_id matches one in the climate_names list
+    start_boost(
+        &state,
+        Channel::Heating,
+        Some(&boost_climates.climate_names),
+        boost_climates.time_length as i64,
+    )?;
+    Ok(StatusCode::OK)
+}
+
+/// Entities to apply a command to; every managed entity when omitted
+#[derive(Serialize, Deserialize)]
+pub struct EntitySelection {
+    #[serde(default)]
+    pub entity_ids: Option<Vec<String>>,
+}
+
+/// End boosts early
+pub async fn cancel_boosts<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(selection): Json<EntitySelection>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    Ok(Json(cancel_boost(
+        &state,
+        Channel::Heating,
+        selection.entity_ids.as_deref(),
+    )?))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PauseInput {
+    #[serde(default)]
+    pub entity_ids: Option<Vec<String>>,
+    pub paused: bool,
+}
+
+/// Stop the scheduler sending commands to entities, or let it manage them again
+pub async fn pause<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(input): Json<PauseInput>,
+) -> Json<Vec<String>> {
+    Json(set_paused(
+        &state,
+        input.entity_ids.as_deref(),
+        input.paused,
     ))
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct AwayInput {
+    pub away: bool,
+}
+
+/// Declare the house away whatever the presence trackers say, or clear it
+pub async fn away<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(input): Json<AwayInput>,
+) -> Json<Occupancy> {
+    Json(set_away(&state, input.away))
+}
+
+/// Query the heating event journal, optionally filtered by entity and time range
+pub async fn get_history<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEvent>>, (StatusCode, String)> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "'from' must not be after 'to'".to_string(),
+        ));
+    }
+
+    let history = state.history.read().unwrap();
+    Ok(Json(history.query(&query)))
+}
+
+/// Temperature, setpoint and heating state per entity at the requested resolution
+pub async fn get_temperature_history<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Query(query): Query<SeriesQuery>,
+) -> Result<Json<HashMap<String, Vec<SeriesPoint>>>, (StatusCode, String)> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "'from' must not be after 'to'".to_string(),
+        ));
+    }
+
+    let temperature_history = state.temperature_history.read().unwrap();
+    Ok(Json(temperature_history.query(&query)))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ClimateEntityInfo {
     pub entity_id: String,
     pub current_temperature: Option<f64>,
     pub state: Option<String>,
+    /// Raw hvac mode reported by Home Assistant, e.g. "heat", "auto" or "unavailable"
+    pub hvac_mode: Option<ApiHeatingState>,
+    pub availability: Option<Availability>,
+    /// Commands repeatedly failed to bring the entity to the desired state
+    pub degraded: bool,
+    pub last_command_error: Option<String>,
+    /// Manual change the scheduler is currently honouring, if any
+    pub manual_override: Option<ManualOverride>,
     pub boost_active: bool,
-    pub boost_start: Option<String>,
-    pub boost_end: Option<String>,
+    pub boost_start: Option<DateTime<Local>>,
+    pub boost_end: Option<DateTime<Local>>,
 }
 
 pub async fn get_entities<T: ClimateEntity + Clone>(
     State(state): State<AppState<T>>,
 ) -> Result<Json<Vec<ClimateEntityInfo>>, (StatusCode, String)> {
     if let Ok(climates) = state.climate_entities.read() {
+        let runtime = state.runtime.read().unwrap();
+        let now = state.clock.now();
         let entities: Vec<ClimateEntityInfo> = climates
             .iter()
             .map(|entity| {
                 let cached_state = entity.get_cached_state();
                 let boost_info = entity.get_boosted_status();
+                let entity_runtime = runtime.get(entity.get_entity_id());
 
                 ClimateEntityInfo {
                     entity_id: entity.get_entity_id().to_string(),
-                    current_temperature: cached_state.as_ref().map(|s| s.current_temperature),
+                    current_temperature: cached_state.as_ref().and_then(|s| s.current_temperature),
                     state: cached_state.as_ref().map(|s| format!("{:?}", s.state)),
+                    hvac_mode: cached_state.as_ref().map(|s| s.hvac_mode.clone()),
+                    availability: cached_state.as_ref().map(|s| s.availability),
+                    degraded: entity_runtime.is_some_and(|r| r.degraded),
+                    last_command_error: entity_runtime.and_then(|r| r.last_error.clone()),
+                    manual_override: entity_runtime
+                        .and_then(|r| r.active_override(&now))
+                        .cloned(),
                     boost_active: boost_info.is_some(),
-                    boost_start: boost_info.as_ref().map(|b| b.boost_start.to_string()),
-                    boost_end: boost_info.as_ref().map(|b| b.boost_end.to_string()),
+                    boost_start: boost_info.as_ref().map(|b| b.boost_start),
+                    boost_end: boost_info.as_ref().map(|b| b.boost_end),
                 }
             })
             .collect();
@@ -190,16 +637,37 @@ pub async fn add_entities(
     State(state): State<AppState<ClimateEntityWrapper>>,
     Json(payload): Json<AddEntitiesRequest>,
 ) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    use crate::config::entities_persistence::{save_entities, EntitiesConfig};
+    add_channel_entities(&state, Channel::Heating, payload.entity_ids)
+}
 
-    // Get current entity IDs
-    let current_ids: Vec<String> = {
-        let climates = state.climate_entities.read().unwrap();
-        climates.iter().map(|e| e.get_entity_id().to_string()).collect()
+/// Add entities to a channel, creating each for its domain, and save the entities file
+fn add_channel_entities(
+    state: &AppState<ClimateEntityWrapper>,
+    channel: Channel,
+    entity_ids: Vec<String>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    for entity_id in &entity_ids {
+        EntityDomain::parse(entity_id).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    // An entity belongs to one channel only
+    let other_channel = match channel {
+        Channel::Heating => Channel::HotWater,
+        Channel::HotWater => Channel::Heating,
     };
+    let other_ids = channel_entity_ids(state, other_channel);
+    if let Some(taken) = entity_ids.iter().find(|id| other_ids.contains(id)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is already on the {} channel", taken, other_channel),
+        ));
+    }
+
+    // Get current entity IDs
+    let current_ids = channel_entity_ids(state, channel);
 
     // Filter out entities that already exist
-    let new_entity_ids: Vec<String> = payload.entity_ids
+    let new_entity_ids: Vec<String> = entity_ids
         .into_iter()
         .filter(|id| !current_ids.contains(id))
         .collect();
@@ -208,34 +676,68 @@ pub async fn add_entities(
         return Ok(Json(current_ids));
     }
 
-    // Add new entities to the climate_entities list
-    #[cfg(debug_assertions)]
+    // Add new entities to the channel's list
     {
-        let mut climates = state.climate_entities.write().unwrap();
-        for entity_id in &new_entity_ids {
-            climates.push(ClimateEntityWrapper::Mock(MockClimate::new(
-                entity_id.clone(),
-                HeatingState::Off,
-            )));
-        }
+        let mut climates = state.entities_of(channel).write().unwrap();
+        climates.extend(state.entity_factory.create_all(new_entity_ids.clone()));
     }
 
-    #[cfg(not(debug_assertions))]
+    save_entities_file(state)?;
+
+    println!("Added {} new entities", new_entity_ids.len());
+    Ok(Json(channel_entity_ids(state, channel)))
+}
+
+/// Remove a climate entity
+pub async fn remove_entity(
+    State(state): State<AppState<ClimateEntityWrapper>>,
+    Json(payload): Json<RemoveEntityRequest>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    remove_channel_entity(&state, Channel::Heating, &payload.entity_id)
+}
+
+/// Remove an entity from a channel, forget what was kept about it and save the entities file
+fn remove_channel_entity<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+    entity_id: &str,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    // Remove entity from the list
     {
-        let mut climates = state.climate_entities.write().unwrap();
-        for entity_id in &new_entity_ids {
-            climates.push(ClimateEntityWrapper::Real(DefaultClimate::new(entity_id.clone())));
-        }
+        let mut climates = state.entities_of(channel).write().unwrap();
+        climates.retain(|e| e.get_entity_id() != entity_id);
     }
+    state.entity_settings.write().unwrap().remove(entity_id);
+    state.runtime.write().unwrap().remove(entity_id);
 
-    // Get updated list
-    let all_entity_ids: Vec<String> = {
-        let climates = state.climate_entities.read().unwrap();
-        climates.iter().map(|e| e.get_entity_id().to_string()).collect()
-    };
+    save_entities_file(state)?;
 
-    // Persist to disk
-    let entities_config = EntitiesConfig::new(all_entity_ids.clone());
+    println!("Removed entity: {}", entity_id);
+    Ok(Json(channel_entity_ids(state, channel)))
+}
+
+/// The IDs of a channel's entities
+fn channel_entity_ids<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+    channel: Channel,
+) -> Vec<String> {
+    let climates = state.entities_of(channel).read().unwrap();
+    climates
+        .iter()
+        .map(|e| e.get_entity_id().to_string())
+        .collect()
+}
+
+/// Write both channels' entities and every entity's settings to the entities file
+fn save_entities_file<T: ClimateEntity + Clone>(
+    state: &AppState<T>,
+) -> Result<(), (StatusCode, String)> {
+    use crate::config::entities_persistence::{EntitiesConfig, save_entities};
+
+    let settings = state.entity_settings.read().unwrap().clone();
+    let entities_config = EntitiesConfig::new(channel_entity_ids(state, Channel::Heating))
+        .with_hot_water(channel_entity_ids(state, Channel::HotWater))
+        .with_settings(settings);
     if let Err(e) = save_entities(&entities_config, &state.entities_file_path) {
         eprintln!("Failed to save entities to disk: {}", e);
         return Err((
@@ -243,40 +745,202 @@ pub async fn add_entities(
             format!("Failed to persist entities: {}", e),
         ));
     }
+    Ok(())
+}
 
-    println!("Added {} new entities", new_entity_ids.len());
-    Ok(Json(all_entity_ids))
+/// Drop a manual override so the schedule applies again on the next pass
+pub async fn clear_manual_override<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Path(entity_id): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let cleared = state
+        .runtime
+        .write()
+        .unwrap()
+        .get_mut(&entity_id)
+        .and_then(|runtime| runtime.manual_override.take());
+    if cleared.is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No manual override on {}", entity_id),
+        ));
+    }
+
+    record_events(&state, vec![(entity_id, EventKind::ManualOverrideEnded)]);
+    Ok(StatusCode::OK)
 }
 
-/// Remove a climate entity
-pub async fn remove_entity(
-    State(state): State<AppState<ClimateEntityWrapper>>,
-    Json(payload): Json<RemoveEntityRequest>,
-) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    use crate::config::entities_persistence::{save_entities, EntitiesConfig};
+/// Replace the settings of a managed entity
+pub async fn update_entity_settings<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Path(entity_id): Path<String>,
+    Json(settings): Json<EntitySettings>,
+) -> Result<Json<EntitySettings>, (StatusCode, String)> {
+    if !channel_entity_ids(&state, Channel::Heating).contains(&entity_id) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Entity {} is not managed by the scheduler", entity_id),
+        ));
+    }
+    if let Some(sensor) = &settings.temperature_sensor
+        && !sensor.starts_with("sensor.")
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a sensor entity", sensor),
+        ));
+    }
 
-    // Remove entity from the list
+    state
+        .entity_settings
+        .write()
+        .unwrap()
+        .insert(entity_id.clone(), settings.clone());
+
+    save_entities_file(&state)?;
+
+    println!("Updated settings for entity: {}", entity_id);
+    Ok(Json(settings))
+}
+
+/// Heating runtime and energy statistics per entity and for the whole house, as JSON or CSV
+pub async fn get_stats<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
     {
-        let mut climates = state.climate_entities.write().unwrap();
-        climates.retain(|e| e.get_entity_id() != payload.entity_id);
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "'from' must not be after 'to'".to_string(),
+        ));
+    }
+    if query.exceeds_max_days(state.clock.now().date_naive()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Statistics are limited to {} days", MAX_STATS_DAYS),
+        ));
     }
 
-    // Get updated list
-    let all_entity_ids: Vec<String> = {
+    let entity_ids: Vec<String> = {
         let climates = state.climate_entities.read().unwrap();
-        climates.iter().map(|e| e.get_entity_id().to_string()).collect()
+        climates
+            .iter()
+            .map(|e| e.get_entity_id().to_string())
+            .collect()
+    };
+    let settings = state.entity_settings.read().unwrap().clone();
+
+    let report = {
+        let history = state.history.read().unwrap();
+        compute_stats(
+            &history.events,
+            &entity_ids,
+            &settings,
+            &state.energy,
+            &query,
+            state.clock.now(),
+        )
     };
 
-    // Persist to disk
-    let entities_config = EntitiesConfig::new(all_entity_ids.clone());
-    if let Err(e) = save_entities(&entities_config, &state.entities_file_path) {
-        eprintln!("Failed to save entities to disk: {}", e);
+    match query.format {
+        StatsFormat::Json => Ok(Json(report).into_response()),
+        StatsFormat::Csv => Ok((
+            [
+                (header::CONTENT_TYPE, "text/csv"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"heating_stats.csv\"",
+                ),
+            ],
+            to_csv(&report),
+        )
+            .into_response()),
+    }
+}
+
+/// How long a hot water boost lasts when no length is given
+pub const DEFAULT_HOT_WATER_BOOST_MINUTES: i64 = 60;
+
+/// The hot water channel's active entry, next transition and entities
+pub async fn get_hot_water<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Json<HotWaterStatus> {
+    Json(hot_water_status(&state))
+}
+
+pub async fn get_hot_water_schedule<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Json<Schedule> {
+    let schedule = state.hot_water_schedule.read().unwrap().clone();
+    Json(schedule)
+}
+
+pub async fn add_hot_water_schedule_entry<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(payload): Json<ScheduleEntryRequest>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    if payload.flexible.is_some() {
         return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to persist entities: {}", e),
+            StatusCode::BAD_REQUEST,
+            "Hot water entries can't be flexible".to_string(),
         ));
     }
+    add_entry(&state, Channel::HotWater, payload)
+}
+
+pub async fn delete_hot_water_schedule_entry<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Path(entry_id): Path<Uuid>,
+) -> Result<Json<Schedule>, (StatusCode, String)> {
+    delete_entry(&state, Channel::HotWater, entry_id)
+}
 
-    println!("Removed entity: {}", payload.entity_id);
-    Ok(Json(all_entity_ids))
+#[derive(Serialize, Deserialize)]
+pub struct HotWaterBoostInput {
+    /// Length in minutes, an hour when omitted
+    #[serde(default)]
+    pub minutes: Option<u8>,
+}
+
+/// Heat hot water now, whatever its schedule says. Returns the entities boosted.
+pub async fn boost_hot_water<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+    Json(input): Json<HotWaterBoostInput>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    let minutes = match input.minutes {
+        Some(0) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Boost length must be at least a minute".to_string(),
+            ));
+        }
+        Some(minutes) => minutes as i64,
+        None => DEFAULT_HOT_WATER_BOOST_MINUTES,
+    };
+    Ok(Json(start_boost(&state, Channel::HotWater, None, minutes)?))
+}
+
+/// End a hot water boost early
+pub async fn cancel_hot_water_boost<T: ClimateEntity + Clone>(
+    State(state): State<AppState<T>>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    Ok(Json(cancel_boost(&state, Channel::HotWater, None)?))
+}
+
+/// Add entities to the hot water channel
+pub async fn add_hot_water_entities(
+    State(state): State<AppState<ClimateEntityWrapper>>,
+    Json(payload): Json<AddEntitiesRequest>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    add_channel_entities(&state, Channel::HotWater, payload.entity_ids)
+}
+
+/// Remove an entity from the hot water channel
+pub async fn remove_hot_water_entity(
+    State(state): State<AppState<ClimateEntityWrapper>>,
+    Json(payload): Json<RemoveEntityRequest>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    remove_channel_entity(&state, Channel::HotWater, &payload.entity_id)
 }
@@ -1,15 +1,25 @@
-use crate::ScheduleState;
-use crate::climate::{ClimateEntity, ClimateEntityWrapper};
+use crate::climate::{ClimateEntity, ClimateEntityFactory, ClimateEntityWrapper};
+use crate::clock::SharedClock;
 use crate::server::handlers::{
-    add_entities, add_schedule_entry, boost, boost_all, delete_schedule_entry,
-    get_entities, get_schedule, remove_entity,
+    add_entities, add_hot_water_entities, add_hot_water_schedule_entry, add_schedule_entry, away,
+    boost, boost_all, boost_hot_water, cancel_boosts, cancel_hot_water_boost,
+    clear_manual_override, clear_weather_compensation, delete_hot_water_schedule_entry,
+    delete_schedule_entry, get_entities, get_history, get_hot_water, get_hot_water_schedule,
+    get_schedule, get_stats, get_status, get_tariff, get_temperature_history, pause, remove_entity,
+    remove_hot_water_entity, set_weather_compensation, simulate_schedule, update_entity_settings,
 };
-use axum::routing::{delete, post};
+use crate::stats::EnergySettings;
+use crate::{
+    EntityRuntimeState, EntitySettingsState, HistoryState, PresenceState, ScheduleState,
+    TariffState, TemperatureHistoryState, WeatherState,
+};
+use axum::routing::{delete, post, put};
 use axum::{Router, routing::get};
+use std::fmt;
 use std::sync::{Arc, RwLock};
 use tower_http::cors::CorsLayer;
 
-mod handlers;
+pub(crate) mod handlers;
 
 #[derive(Clone, Debug)]
 pub struct AppState<T: ClimateEntity + Clone> {
@@ -17,34 +27,134 @@ pub struct AppState<T: ClimateEntity + Clone> {
     pub schedule_file_path: String,
     pub climate_entities: Arc<RwLock<Vec<T>>>,
     pub entities_file_path: String,
+    pub hot_water_schedule: ScheduleState,
+    pub hot_water_schedule_file_path: String,
+    pub hot_water_entities: Arc<RwLock<Vec<T>>>,
+    pub history: HistoryState,
+    pub temperature_history: TemperatureHistoryState,
+    pub entity_settings: EntitySettingsState,
+    pub energy: EnergySettings,
+    pub entity_factory: ClimateEntityFactory,
+    pub clock: SharedClock,
+    /// Command failures and degraded flags kept by the scheduler
+    pub runtime: EntityRuntimeState,
+    /// Who is home, kept up to date by the scheduler
+    pub presence: PresenceState,
+    /// The latest outdoor reading, kept up to date by the scheduler
+    pub weather: WeatherState,
+    /// Energy prices and the slots picked for flexible entries
+    pub tariff: TariffState,
+}
+
+/// Space heating or domestic hot water; each has its own schedule, entities and boosts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Heating,
+    HotWater,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Heating => write!(f, "heating"),
+            Channel::HotWater => write!(f, "hot water"),
+        }
+    }
+}
+
+impl<T: ClimateEntity + Clone> AppState<T> {
+    /// A channel's schedule and the file it's saved to
+    pub(crate) fn schedule_of(&self, channel: Channel) -> (&ScheduleState, &str) {
+        match channel {
+            Channel::Heating => (&self.schedule, &self.schedule_file_path),
+            Channel::HotWater => (&self.hot_water_schedule, &self.hot_water_schedule_file_path),
+        }
+    }
+
+    pub(crate) fn entities_of(&self, channel: Channel) -> &Arc<RwLock<Vec<T>>> {
+        match channel {
+            Channel::Heating => &self.climate_entities,
+            Channel::HotWater => &self.hot_water_entities,
+        }
+    }
 }
 
-pub async fn start_server(
-    schedule: ScheduleState,
-    schedule_file_path: String,
-    climate_entities: Arc<RwLock<Vec<ClimateEntityWrapper>>>,
-    entities_file_path: String,
-) {
-    let app_state = AppState {
-        schedule,
-        schedule_file_path,
-        climate_entities,
-        entities_file_path,
-    };
+pub async fn start_server(app_state: AppState<ClimateEntityWrapper>, bind_address: String) {
+    let app = build_router(app_state);
+
+    let listener = tokio::net::TcpListener::bind(&bind_address).await.unwrap();
+    println!("API listening on {}", bind_address);
+    axum::serve(listener, app).await.unwrap();
+}
+
+/// All API routes with their shared state
+pub fn build_router(app_state: AppState<ClimateEntityWrapper>) -> Router {
     let cors_layer = CorsLayer::permissive();
-    let app = Router::new()
+    Router::new()
         .route("/schedule", get(get_schedule::<ClimateEntityWrapper>))
-        .route("/schedule", post(add_schedule_entry::<ClimateEntityWrapper>))
-        .route("/schedule/{id}", delete(delete_schedule_entry::<ClimateEntityWrapper>))
+        .route(
+            "/schedule",
+            post(add_schedule_entry::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/schedule/{id}",
+            delete(delete_schedule_entry::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/schedule/weather_compensation",
+            put(set_weather_compensation::<ClimateEntityWrapper>)
+                .delete(clear_weather_compensation::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/schedule/simulate",
+            post(simulate_schedule::<ClimateEntityWrapper>),
+        )
+        .route("/status", get(get_status::<ClimateEntityWrapper>))
+        .route("/tariff", get(get_tariff::<ClimateEntityWrapper>))
         .route("/entities", get(get_entities::<ClimateEntityWrapper>))
         .route("/entities", post(add_entities))
         .route("/entities", delete(remove_entity))
+        .route(
+            "/entities/{entity_id}/settings",
+            put(update_entity_settings::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/entities/{entity_id}/override",
+            delete(clear_manual_override::<ClimateEntityWrapper>),
+        )
         .route("/boost_all", post(boost_all::<ClimateEntityWrapper>))
         .route("/boost", post(boost::<ClimateEntityWrapper>))
+        .route("/cancel_boost", post(cancel_boosts::<ClimateEntityWrapper>))
+        .route("/pause", put(pause::<ClimateEntityWrapper>))
+        .route("/away", put(away::<ClimateEntityWrapper>))
+        .route("/hot_water", get(get_hot_water::<ClimateEntityWrapper>))
+        .route(
+            "/hot_water/schedule",
+            get(get_hot_water_schedule::<ClimateEntityWrapper>)
+                .post(add_hot_water_schedule_entry::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/hot_water/schedule/{id}",
+            delete(delete_hot_water_schedule_entry::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/hot_water/entities",
+            post(add_hot_water_entities).delete(remove_hot_water_entity),
+        )
+        .route(
+            "/hot_water/boost",
+            post(boost_hot_water::<ClimateEntityWrapper>),
+        )
+        .route(
+            "/hot_water/cancel_boost",
+            post(cancel_hot_water_boost::<ClimateEntityWrapper>),
+        )
+        .route("/history", get(get_history::<ClimateEntityWrapper>))
+        .route(
+            "/temperature_history",
+            get(get_temperature_history::<ClimateEntityWrapper>),
+        )
+        .route("/stats", get(get_stats::<ClimateEntityWrapper>))
         .layer(cors_layer)
-        .with_state(app_state);
-
-    // run our app with hyper, listening globally on port 3000
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+        .with_state(app_state)
 }
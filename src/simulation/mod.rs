@@ -0,0 +1,264 @@
+use crate::climate::BoostInfo;
+use crate::schedule::{HeatingState, Schedule};
+use crate::scheduler::{calculate_desired_heating_state_for_boost, final_desired_heating_state};
+use chrono::{DateTime, Days, Duration, Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A stretch of time during which an entity's desired state doesn't change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSpan {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub state: HeatingState,
+    /// Name of the schedule entry active at the start of the span
+    pub entry_name: Option<String>,
+    pub boosted: bool,
+}
+
+/// A change of desired state for one entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub time: DateTime<Local>,
+    pub entity_id: String,
+    pub from: HeatingState,
+    pub to: HeatingState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationResult {
+    pub from: DateTime<Local>,
+    pub to: DateTime<Local>,
+    pub timelines: HashMap<String, Vec<StateSpan>>,
+    /// Every transition across all entities, in time order
+    pub transitions: Vec<Transition>,
+}
+
+/// Times in `[from, to)` at which the desired state may change, in order
+fn candidate_times(
+    schedule: &Schedule,
+    entities: &[(String, Option<BoostInfo>)],
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> Vec<DateTime<Local>> {
+    let boundaries: Vec<NaiveTime> = schedule
+        .entries
+        .iter()
+        .flat_map(|e| [e.time_period.start, e.time_period.end])
+        .collect();
+    let mut times = vec![from];
+    for boost in entities.iter().filter_map(|(_, boost)| boost.as_ref()) {
+        // Boosts include their end time, so they stop just after it
+        for time in [boost.boost_start, boost.boost_end + Duration::seconds(1)] {
+            if time > from && time < to {
+                times.push(time);
+            }
+        }
+    }
+    let mut date = from.date_naive();
+    while date <= to.date_naive() {
+        for boundary in &boundaries {
+            // Times skipped by a DST change don't exist locally, so there is nothing to evaluate
+            if let Some(time) = date
+                .and_time(*boundary)
+                .and_local_timezone(Local)
+                .earliest()
+                && time > from
+                && time < to
+            {
+                times.push(time);
+            }
+        }
+        date = date + Days::new(1);
+    }
+
+    times.sort();
+    times.dedup();
+    times
+}
+
+/// Replay the scheduler's decisions over `[from, to)` without touching any entity
+///
+/// Each entity is evaluated with the same schedule and boost logic the scheduler uses, so a
+/// proposed schedule can be previewed before it is saved. Boosts are cleared once they have run
+/// out, just like the scheduler does.
+pub fn simulate(
+    schedule: &Schedule,
+    entities: &[(String, Option<BoostInfo>)],
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> SimulationResult {
+    let times = candidate_times(schedule, entities, from, to);
+
+    let mut timelines: HashMap<String, Vec<StateSpan>> = HashMap::new();
+    let mut transitions = Vec::new();
+
+    for (entity_id, boost) in entities {
+        let mut boost = boost.clone();
+        let mut spans: Vec<StateSpan> = Vec::new();
+
+        for (i, time) in times.iter().enumerate() {
+            let end = times.get(i + 1).copied().unwrap_or(to);
+
+            let scheduled_state = schedule.get_current_state(time);
+            let (boosted_state, should_update) =
+                calculate_desired_heating_state_for_boost(&boost, time);
+            if should_update {
+                boost = None;
+            }
+            let state = final_desired_heating_state(&scheduled_state, &boosted_state);
+            let boosted = boosted_state == HeatingState::On;
+            let entry_name = schedule.get_active_entry(time).map(|e| e.name.clone());
+
+            match spans.last_mut() {
+                Some(last)
+                    if last.state == state
+                        && last.boosted == boosted
+                        && last.entry_name == entry_name =>
+                {
+                    last.end = end;
+                }
+                last => {
+                    if let Some(last) = last
+                        && last.state != state
+                    {
+                        transitions.push(Transition {
+                            time: *time,
+                            entity_id: entity_id.clone(),
+                            from: last.state.clone(),
+                            to: state.clone(),
+                        });
+                    }
+                    spans.push(StateSpan {
+                        start: *time,
+                        end,
+                        state,
+                        entry_name,
+                        boosted,
+                    });
+                }
+            }
+        }
+
+        timelines.insert(entity_id.clone(), spans);
+    }
+
+    transitions.sort_by(|a, b| a.time.cmp(&b.time).then(a.entity_id.cmp(&b.entity_id)));
+
+    SimulationResult {
+        from,
+        to,
+        timelines,
+        transitions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::{ScheduleEntry, TimePeriod};
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn work_day_schedule() -> Schedule {
+        let mut schedule = Schedule::new("Test Schedule");
+        schedule.add_entry(ScheduleEntry::new(
+            "Morning",
+            TimePeriod::new(6, 0, 9, 0),
+            HeatingState::On,
+        ));
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+        schedule
+    }
+
+    #[test]
+    fn test_simulate_schedule_over_a_day() {
+        let schedule = work_day_schedule();
+        let entities = vec![("climate.bedroom".to_string(), None)];
+
+        let result = simulate(&schedule, &entities, at(15, 0, 0), at(16, 0, 0));
+
+        let times: Vec<_> = result
+            .transitions
+            .iter()
+            .map(|t| (t.time, t.to.clone()))
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                (at(15, 6, 0), HeatingState::On),
+                (at(15, 9, 0), HeatingState::Off),
+                (at(15, 17, 0), HeatingState::On),
+                (at(15, 22, 0), HeatingState::Off),
+            ]
+        );
+
+        let spans = &result.timelines["climate.bedroom"];
+        assert_eq!(spans.first().unwrap().start, at(15, 0, 0));
+        assert_eq!(spans.last().unwrap().end, at(16, 0, 0));
+        assert_eq!(spans[1].entry_name.as_deref(), Some("Morning"));
+    }
+
+    #[test]
+    fn test_simulate_boost_applies_once() {
+        let schedule = Schedule::new("All Off");
+        let entities = vec![(
+            "climate.bedroom".to_string(),
+            Some(BoostInfo {
+                boost_start: at(15, 12, 0),
+                boost_end: at(15, 12, 45),
+            }),
+        )];
+
+        // Two days: the boost must not come back on the second day
+        let result = simulate(&schedule, &entities, at(15, 12, 10), at(17, 0, 0));
+
+        assert_eq!(result.transitions.len(), 1);
+        assert_eq!(
+            result.transitions[0].time,
+            at(15, 12, 45) + Duration::seconds(1)
+        );
+        assert_eq!(result.transitions[0].to, HeatingState::Off);
+
+        let spans = &result.timelines["climate.bedroom"];
+        assert_eq!(spans.len(), 2);
+        assert!(spans[0].boosted);
+        assert_eq!(spans[0].state, HeatingState::On);
+    }
+
+    #[test]
+    fn test_simulate_entities_share_schedule_but_not_boosts() {
+        let schedule = work_day_schedule();
+        let entities = vec![
+            ("climate.bedroom".to_string(), None),
+            (
+                "climate.living_room".to_string(),
+                Some(BoostInfo {
+                    boost_start: at(15, 10, 0),
+                    boost_end: at(15, 10, 30),
+                }),
+            ),
+        ];
+
+        let result = simulate(&schedule, &entities, at(15, 10, 0), at(16, 10, 0));
+
+        let count = |entity_id: &str| {
+            result
+                .transitions
+                .iter()
+                .filter(|t| t.entity_id == entity_id)
+                .count()
+        };
+        assert_eq!(count("climate.bedroom"), 4);
+        assert_eq!(count("climate.living_room"), 5);
+    }
+}
@@ -0,0 +1,575 @@
+use crate::config::entities_persistence::EntitySettings;
+use crate::history::{EventKind, HistoryEvent};
+use crate::schedule::HeatingState;
+use chrono::{DateTime, Datelike, Days, Duration, Local, Months, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Power and price used to turn heating minutes into an energy and cost estimate
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergySettings {
+    /// Used for entities without their own `power_kw` setting, e.g. a boiler rating
+    pub default_power_kw: Option<f64>,
+    pub price_per_kwh: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Period {
+    /// First day of the period containing `date` (weeks start on Monday)
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Daily => date,
+            Period::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Period::Monthly => date.with_day(1).unwrap(),
+        }
+    }
+
+    /// First day of the following period
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Daily => start + Days::new(1),
+            Period::Weekly => start + Days::new(7),
+            Period::Monthly => start + Months::new(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Filters for the statistics report
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsQuery {
+    #[serde(default)]
+    pub period: Period,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub entity_id: Option<String>,
+    #[serde(default)]
+    pub format: StatsFormat,
+}
+
+impl StatsQuery {
+    /// Whether an explicit `from` lies more than `MAX_STATS_DAYS` before `to`, which defaults to
+    /// `today`
+    pub fn exceeds_max_days(&self, today: NaiveDate) -> bool {
+        self.from.is_some_and(|from| {
+            self.to.unwrap_or(today) - from > Duration::days(MAX_STATS_DAYS as i64)
+        })
+    }
+}
+
+/// Heating runtime for one entity (or the whole house when `entity_id` is `None`) in one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeriodStats {
+    pub period_start: NaiveDate,
+    pub entity_id: Option<String>,
+    pub heating_minutes: f64,
+    pub energy_kwh: Option<f64>,
+    pub cost: Option<f64>,
+    /// The period reaches before `from` or past `to`, or hasn't ended yet, so only the covered
+    /// days are counted
+    pub partial: bool,
+}
+
+/// Longest range a report covers, counted back from `to`
+pub const MAX_STATS_DAYS: u64 = 31;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsReport {
+    pub period: Period,
+    /// First day actually covered. A requested `from` is moved up to the oldest event still in
+    /// the journal, which keeps only recent events. Without `from` the report starts at the
+    /// oldest event, but at most `MAX_STATS_DAYS` before `to`.
+    pub from: NaiveDate,
+    pub entities: Vec<PeriodStats>,
+    /// Minutes during which at least one entity was heating, with summed energy
+    pub house: Vec<PeriodStats>,
+}
+
+type Interval = (DateTime<Local>, DateTime<Local>);
+
+/// Start of `date` in local time, or the first instant after midnight where a DST change skips it
+pub(crate) fn local_midnight(date: NaiveDate) -> DateTime<Local> {
+    midnight_in(date, &Local)
+}
+
+/// Start of `date` in `tz`, as for `local_midnight`
+pub(crate) fn midnight_in<Tz: TimeZone>(date: NaiveDate, tz: &Tz) -> DateTime<Tz> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..=12)
+        .find_map(|quarter| {
+            tz.from_local_datetime(&(midnight + Duration::minutes(15 * quarter)))
+                .earliest()
+        })
+        .unwrap_or_else(|| midnight.and_utc().with_timezone(tz))
+}
+
+/// Heating state after a journal event, if it records an on/off transition
+fn transition(event: &HistoryEvent) -> Option<HeatingState> {
+    match &event.kind {
+        EventKind::SchedulerDecision {
+            desired_state,
+            dry_run: false,
+            ..
+        } => Some(desired_state.clone()),
+        EventKind::ManualChange { observed_state, .. } => Some(observed_state.clone()),
+        _ => None,
+    }
+}
+
+/// Periods an entity spent heating, derived from the on/off transitions in the journal
+///
+/// Time before the entity's first recorded transition is not counted, since we don't know when
+/// that state began.
+pub fn heating_intervals(
+    events: &[HistoryEvent],
+    entity_id: &str,
+    now: DateTime<Local>,
+) -> Vec<Interval> {
+    let transitions = events
+        .iter()
+        .filter(|e| e.entity_id.as_deref() == Some(entity_id))
+        .filter_map(|e| transition(e).map(|after| (e.timestamp, after)));
+
+    let mut intervals = Vec::new();
+    let mut on_since = None;
+
+    for (timestamp, after) in transitions {
+        match (after, on_since) {
+            (HeatingState::On, None) => on_since = Some(timestamp),
+            (HeatingState::Off, Some(start)) => {
+                intervals.push((start, timestamp));
+                on_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(start) = on_since
+        && start < now
+    {
+        intervals.push((start, now));
+    }
+
+    intervals
+}
+
+/// Merge overlapping intervals, e.g. to count time when any zone was heating
+fn union(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort_by_key(|(start, _)| *start);
+    let mut merged: Vec<Interval> = Vec::new();
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => {
+                if end > *last_end {
+                    *last_end = end;
+                }
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn minutes_within(intervals: &[Interval], start: DateTime<Local>, end: DateTime<Local>) -> f64 {
+    intervals
+        .iter()
+        .map(|(s, e)| {
+            let overlap_start = (*s).max(start);
+            let overlap_end = (*e).min(end);
+            if overlap_end > overlap_start {
+                (overlap_end - overlap_start).num_seconds() as f64 / 60.0
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+/// Build per-entity and whole-house heating statistics for each period in the requested range
+pub fn compute_stats(
+    events: &[HistoryEvent],
+    entity_ids: &[String],
+    settings: &HashMap<String, EntitySettings>,
+    energy: &EnergySettings,
+    query: &StatsQuery,
+    now: DateTime<Local>,
+) -> StatsReport {
+    let today = now.date_naive();
+    let to = query.to.unwrap_or(today).min(today);
+    let oldest = events
+        .first()
+        .map(|e| e.timestamp.date_naive())
+        .unwrap_or(today);
+    let from = query
+        .from
+        .unwrap_or(to - Days::new(MAX_STATS_DAYS))
+        .max(oldest);
+
+    let entity_ids: Vec<&String> = entity_ids
+        .iter()
+        .filter(|id| query.entity_id.as_ref().is_none_or(|wanted| *id == wanted))
+        .collect();
+
+    let intervals: Vec<(&String, Vec<Interval>)> = entity_ids
+        .iter()
+        .map(|id| (*id, heating_intervals(events, id, now)))
+        .collect();
+    let house_intervals = union(
+        intervals
+            .iter()
+            .flat_map(|(_, entity_intervals)| entity_intervals.clone())
+            .collect(),
+    );
+
+    let price = |kwh: Option<f64>| kwh.zip(energy.price_per_kwh).map(|(kwh, p)| kwh * p);
+
+    let mut report = StatsReport {
+        period: query.period,
+        from,
+        ..Default::default()
+    };
+
+    let mut period_start = query.period.start_of(from);
+    while period_start <= to {
+        let period_end = query.period.next(period_start);
+        let start = local_midnight(period_start.max(from));
+        let end = local_midnight(period_end.min(to + Days::new(1))).min(now);
+        let partial = period_start < from || end < local_midnight(period_end);
+
+        let mut house_energy: Option<f64> = None;
+        for (entity_id, entity_intervals) in &intervals {
+            let heating_minutes = minutes_within(entity_intervals, start, end);
+            let power_kw = settings
+                .get(*entity_id)
+                .and_then(|s| s.power_kw)
+                .or(energy.default_power_kw);
+            let energy_kwh = power_kw.map(|kw| kw * heating_minutes / 60.0);
+            if let Some(kwh) = energy_kwh {
+                house_energy = Some(house_energy.unwrap_or(0.0) + kwh);
+            }
+
+            report.entities.push(PeriodStats {
+                period_start,
+                entity_id: Some((*entity_id).clone()),
+                heating_minutes,
+                energy_kwh,
+                cost: price(energy_kwh),
+                partial,
+            });
+        }
+
+        report.house.push(PeriodStats {
+            period_start,
+            entity_id: None,
+            heating_minutes: minutes_within(&house_intervals, start, end),
+            energy_kwh: house_energy,
+            cost: price(house_energy),
+            partial,
+        });
+
+        period_start = period_end;
+    }
+
+    report
+}
+
+/// Render the report as CSV, one row per entity and period followed by the whole-house rows
+pub fn to_csv(report: &StatsReport) -> String {
+    let optional = |value: Option<f64>| value.map(|v| format!("{:.3}", v)).unwrap_or_default();
+
+    let mut csv = String::from("period_start,entity_id,heating_minutes,energy_kwh,cost,partial\n");
+    for row in report.entities.iter().chain(report.house.iter()) {
+        csv.push_str(&format!(
+            "{},{},{:.1},{},{},{}\n",
+            row.period_start,
+            row.entity_id.as_deref().unwrap_or("house"),
+            row.heating_minutes,
+            optional(row.energy_kwh),
+            optional(row.cost),
+            row.partial,
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 1, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn decision(timestamp: DateTime<Local>, entity_id: &str, on: bool) -> HistoryEvent {
+        let (previous_state, desired_state) = if on {
+            (HeatingState::Off, HeatingState::On)
+        } else {
+            (HeatingState::On, HeatingState::Off)
+        };
+        HistoryEvent::new(
+            timestamp,
+            Some(entity_id.to_string()),
+            EventKind::SchedulerDecision {
+                active_entry: None,
+                previous_state,
+                desired_state,
+                boosted: false,
+                dry_run: false,
+            },
+        )
+    }
+
+    #[test]
+    fn test_heating_intervals_from_transitions() {
+        let events = vec![
+            decision(at(15, 6, 0), "climate.bedroom", true),
+            decision(at(15, 8, 0), "climate.bedroom", false),
+            decision(at(15, 17, 0), "climate.bedroom", true),
+        ];
+
+        let intervals = heating_intervals(&events, "climate.bedroom", at(15, 18, 30));
+
+        assert_eq!(
+            intervals,
+            vec![
+                (at(15, 6, 0), at(15, 8, 0)),
+                (at(15, 17, 0), at(15, 18, 30))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heating_intervals_ignores_time_before_first_transition() {
+        let events = vec![decision(at(15, 8, 0), "climate.bedroom", false)];
+
+        let intervals = heating_intervals(&events, "climate.bedroom", at(15, 12, 0));
+
+        assert!(intervals.is_empty());
+    }
+
+    #[test]
+    fn test_daily_stats_split_across_midnight() {
+        let events = vec![
+            decision(at(15, 23, 0), "climate.bedroom", true),
+            decision(at(16, 1, 0), "climate.bedroom", false),
+        ];
+        let mut settings = HashMap::new();
+        settings.insert(
+            "climate.bedroom".to_string(),
+            EntitySettings {
+                power_kw: Some(2.0),
+                ..Default::default()
+            },
+        );
+        let energy = EnergySettings {
+            default_power_kw: None,
+            price_per_kwh: Some(0.25),
+        };
+
+        let report = compute_stats(
+            &events,
+            &["climate.bedroom".to_string()],
+            &settings,
+            &energy,
+            &StatsQuery::default(),
+            at(16, 12, 0),
+        );
+
+        assert_eq!(report.entities.len(), 2);
+        assert_eq!(report.entities[0].heating_minutes, 60.0);
+        assert_eq!(report.entities[1].heating_minutes, 60.0);
+        assert_eq!(report.entities[0].energy_kwh, Some(2.0));
+        assert_eq!(report.entities[0].cost, Some(0.5));
+    }
+
+    #[test]
+    fn test_house_stats_count_overlap_once() {
+        let events = vec![
+            decision(at(15, 6, 0), "climate.bedroom", true),
+            decision(at(15, 6, 30), "climate.living_room", true),
+            decision(at(15, 7, 0), "climate.bedroom", false),
+            decision(at(15, 8, 0), "climate.living_room", false),
+        ];
+        let energy = EnergySettings {
+            default_power_kw: Some(1.0),
+            price_per_kwh: None,
+        };
+
+        let report = compute_stats(
+            &events,
+            &[
+                "climate.bedroom".to_string(),
+                "climate.living_room".to_string(),
+            ],
+            &HashMap::new(),
+            &energy,
+            &StatsQuery::default(),
+            at(15, 12, 0),
+        );
+
+        assert_eq!(report.house.len(), 1);
+        assert_eq!(report.house[0].heating_minutes, 120.0);
+        assert_eq!(report.house[0].energy_kwh, Some(2.5));
+        assert_eq!(report.house[0].cost, None);
+    }
+
+    #[test]
+    fn test_range_starts_at_the_oldest_retained_event() {
+        let events = vec![decision(at(15, 6, 0), "climate.bedroom", true)];
+        let query = |from| StatsQuery {
+            from: Some(from),
+            ..Default::default()
+        };
+        let stats = |query: &StatsQuery, now| {
+            compute_stats(
+                &events,
+                &["climate.bedroom".to_string()],
+                &HashMap::new(),
+                &EnergySettings::default(),
+                query,
+                now,
+            )
+        };
+
+        let report = stats(&query(NaiveDate::MIN), at(16, 12, 0));
+        assert_eq!(report.from, at(15, 0, 0).date_naive());
+        assert_eq!(report.house.len(), 2);
+
+        // However old the journal, a report without `from` covers at most MAX_STATS_DAYS
+        let much_later = at(15, 12, 0) + Days::new(400);
+        let report = stats(&StatsQuery::default(), much_later);
+        assert_eq!(
+            report.from,
+            much_later.date_naive() - Days::new(MAX_STATS_DAYS)
+        );
+        assert_eq!(report.house.len(), MAX_STATS_DAYS as usize + 1);
+    }
+
+    #[test]
+    fn test_range_longer_than_the_cap_is_rejected() {
+        let today = at(31, 12, 0).date_naive();
+        let query = |from: NaiveDate, to| StatsQuery {
+            from: Some(from),
+            to,
+            ..Default::default()
+        };
+        let cap = Days::new(MAX_STATS_DAYS);
+        let to = today - Days::new(100);
+
+        assert!(!query(today - cap, None).exceeds_max_days(today));
+        assert!(query(today - cap - Days::new(1), None).exceeds_max_days(today));
+        assert!(!query(to - cap, Some(to)).exceeds_max_days(today));
+        assert!(query(to - cap - Days::new(1), Some(to)).exceeds_max_days(today));
+        assert!(!StatsQuery::default().exceeds_max_days(today));
+    }
+
+    #[test]
+    fn test_periods_cut_by_the_range_are_partial() {
+        let events = vec![
+            decision(at(2, 6, 0), "climate.bedroom", true),
+            decision(at(2, 8, 0), "climate.bedroom", false),
+            decision(at(20, 6, 0), "climate.bedroom", true),
+            decision(at(20, 7, 0), "climate.bedroom", false),
+        ];
+        let query = StatsQuery {
+            period: Period::Monthly,
+            from: Some(at(15, 0, 0).date_naive()),
+            to: Some(at(25, 0, 0).date_naive()),
+            ..Default::default()
+        };
+
+        let report = compute_stats(
+            &events,
+            &["climate.bedroom".to_string()],
+            &HashMap::new(),
+            &EnergySettings::default(),
+            &query,
+            Local.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap(),
+        );
+
+        // January is cut on both sides, and heating before `from` isn't counted in it
+        assert_eq!(report.house.len(), 1);
+        assert_eq!(report.house[0].period_start, at(1, 0, 0).date_naive());
+        assert_eq!(report.house[0].heating_minutes, 60.0);
+        assert!(report.house[0].partial);
+
+        let report = compute_stats(
+            &events,
+            &["climate.bedroom".to_string()],
+            &HashMap::new(),
+            &EnergySettings::default(),
+            &StatsQuery::default(),
+            at(3, 12, 0),
+        );
+        assert_eq!(report.house.len(), 2);
+        assert!(!report.house[0].partial);
+        assert!(report.house[1].partial, "today hasn't ended yet");
+    }
+
+    #[test]
+    fn test_weekly_and_monthly_period_starts() {
+        // 2025-01-15 is a Wednesday
+        let date = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        assert_eq!(
+            Period::Weekly.start_of(date),
+            NaiveDate::from_ymd_opt(2025, 1, 13).unwrap()
+        );
+        assert_eq!(
+            Period::Monthly.start_of(date),
+            NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()
+        );
+        assert_eq!(
+            Period::Monthly.next(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()),
+            NaiveDate::from_ymd_opt(2025, 2, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let report = StatsReport {
+            period: Period::Daily,
+            from: NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(),
+            entities: vec![PeriodStats {
+                period_start: NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(),
+                entity_id: Some("climate.bedroom".to_string()),
+                heating_minutes: 90.0,
+                energy_kwh: Some(1.5),
+                cost: None,
+                partial: false,
+            }],
+            house: vec![PeriodStats {
+                period_start: NaiveDate::from_ymd_opt(2025, 1, 15).unwrap(),
+                entity_id: None,
+                heating_minutes: 90.0,
+                energy_kwh: None,
+                cost: None,
+                partial: true,
+            }],
+        };
+
+        assert_eq!(
+            to_csv(&report),
+            "period_start,entity_id,heating_minutes,energy_kwh,cost,partial\n\
+             2025-01-15,climate.bedroom,90.0,1.500,,false\n\
+             2025-01-15,house,90.0,,,true\n"
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use crate::climate::ClimateEntity;
+use crate::presence::{AwayMode, Presence};
+use crate::schedule::{HeatingState, Schedule, ScheduleEntry, ScheduleTransition};
+use crate::scheduler::runtime::{
+    EntityRuntime, FrostProtection, ManualOverride, OpenWindow, OptimumStart,
+};
+use crate::scheduler::{calculate_desired_heating_state_for_boost, final_desired_heating_state};
+use crate::tariff::{FlexiblePlan, TariffPlans};
+use crate::weather::OutdoorWeather;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoostStatus {
+    pub boost_start: DateTime<Local>,
+    pub boost_end: DateTime<Local>,
+}
+
+/// What one entity is doing and what the scheduler wants it to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityStatus {
+    pub entity_id: String,
+    pub current_state: Option<HeatingState>,
+    pub current_temperature: Option<f64>,
+    /// Active boost, if any
+    pub boost: Option<BoostStatus>,
+    /// Manual change the scheduler is currently honouring, if any
+    pub manual_override: Option<ManualOverride>,
+    /// Set while heating is paused for an open window
+    pub open_window: Option<OpenWindow>,
+    /// Set while the room is below its frost threshold and heating is forced on
+    pub frost_protection: Option<FrostProtection>,
+    /// When heating starts ahead of the next On entry, for entities using optimum start
+    pub optimum_start: Option<OptimumStart>,
+    /// Set while the scheduler is paused for this entity
+    pub paused_since: Option<DateTime<Local>>,
+    /// The state the scheduler will drive the entity to, after boosts, overrides, open windows
+    /// and frost protection; a paused entity stays as it is unless frost protection is on
+    pub effective_state: HeatingState,
+}
+
+/// Snapshot of the scheduler: the active entry, what changes next and every entity's state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerStatus {
+    pub now: DateTime<Local>,
+    pub active_entry: Option<ScheduleEntry>,
+    pub scheduled_state: HeatingState,
+    pub next_transition: Option<ScheduleTransition>,
+    /// Who is home, when presence trackers are configured or the house was declared away
+    pub presence: Option<Presence>,
+    /// Outdoor temperature and its effect, when the schedule has weather compensation
+    pub weather: Option<OutdoorWeather>,
+    /// Slots picked for the active entry, if it's flexible
+    pub flexible_plan: Option<FlexiblePlan>,
+    pub entities: Vec<EntityStatus>,
+    /// The hot water channel, when it has any entities
+    pub hot_water: Option<HotWaterStatus>,
+}
+
+impl SchedulerStatus {
+    /// Heating entities followed by hot water ones
+    pub fn all_entities(&self) -> impl Iterator<Item = &EntityStatus> {
+        self.entities.iter().chain(
+            self.hot_water
+                .iter()
+                .flat_map(|hot_water| &hot_water.entities),
+        )
+    }
+}
+
+/// The hot water channel: its own schedule's active entry and next transition, and its entities
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HotWaterStatus {
+    pub active_entry: Option<ScheduleEntry>,
+    pub scheduled_state: HeatingState,
+    pub next_transition: Option<ScheduleTransition>,
+    pub entities: Vec<EntityStatus>,
+}
+
+/// Work out the current status the same way the scheduler decides what to do
+pub fn build_status<T: ClimateEntity>(
+    schedule: &Schedule,
+    entities: &[T],
+    runtime: &HashMap<String, EntityRuntime>,
+    presence: &Presence,
+    weather: Option<&OutdoorWeather>,
+    tariff: &TariffPlans,
+    now: DateTime<Local>,
+) -> SchedulerStatus {
+    let scheduled_state = schedule.get_current_state(&now);
+    let active_entry = schedule.get_active_entry(&now).cloned();
+    let flexible_plan = active_entry
+        .as_ref()
+        .filter(|entry| entry.flexible.is_some())
+        .and_then(|entry| tariff.plans.get(&entry.id))
+        .cloned();
+    // Away in eco mode still follows the schedule, just at a lower temperature
+    let warm = weather.is_some_and(|weather| weather.warm_weather);
+    let desired_state = match presence.active_away_mode() {
+        Some(AwayMode::Off) => HeatingState::Off,
+        _ if warm => HeatingState::Off,
+        _ => match &flexible_plan {
+            Some(plan) if !plan.is_heating(&now) => HeatingState::Off,
+            _ => scheduled_state.clone(),
+        },
+    };
+
+    let entities = entities
+        .iter()
+        .map(|entity| {
+            let cached_state = entity.get_cached_state();
+            let boost_info = entity.get_boosted_status();
+            let (boosted_state, _) = calculate_desired_heating_state_for_boost(boost_info, &now);
+            let entity_runtime = runtime.get(entity.get_entity_id());
+            let manual_override = entity_runtime
+                .and_then(|runtime| runtime.active_override(&now))
+                .cloned();
+            let open_window = entity_runtime.and_then(|runtime| runtime.open_window.clone());
+            let frost_protection =
+                entity_runtime.and_then(|runtime| runtime.frost_protection.clone());
+            let optimum_start = entity_runtime.and_then(|runtime| runtime.optimum_start.clone());
+            let preheating = optimum_start.as_ref().is_some_and(|plan| plan.preheating);
+            let paused_since = entity_runtime.and_then(|runtime| runtime.paused_since);
+            let current_state = cached_state.as_ref().map(|s| s.state.clone());
+            let effective_state = match &manual_override {
+                _ if frost_protection.is_some() => HeatingState::On,
+                _ if paused_since.is_some() => current_state.clone().unwrap_or(HeatingState::Off),
+                _ if open_window.is_some() => HeatingState::Off,
+                Some(manual_override) if boosted_state != HeatingState::On => {
+                    manual_override.state.clone()
+                }
+                _ if preheating => HeatingState::On,
+                _ => final_desired_heating_state(&desired_state, &boosted_state),
+            };
+
+            EntityStatus {
+                entity_id: entity.get_entity_id().to_string(),
+                current_state,
+                current_temperature: cached_state.as_ref().and_then(|s| s.current_temperature),
+                // A boost that has run out but hasn't been cleared yet isn't active
+                boost: boost_info
+                    .as_ref()
+                    .filter(|_| boosted_state == HeatingState::On)
+                    .map(|b| BoostStatus {
+                        boost_start: b.boost_start,
+                        boost_end: b.boost_end,
+                    }),
+                manual_override,
+                open_window,
+                frost_protection,
+                optimum_start,
+                paused_since,
+                effective_state,
+            }
+        })
+        .collect();
+
+    SchedulerStatus {
+        now,
+        active_entry,
+        scheduled_state,
+        next_transition: schedule.next_transition(&now),
+        presence: Some(presence.clone())
+            .filter(|presence| !presence.trackers.is_empty() || presence.forced_away),
+        weather: weather.cloned(),
+        flexible_plan,
+        entities,
+        hot_water: None,
+    }
+}
+
+/// Work out the hot water channel's status. Its schedule, boosts, manual overrides and pauses
+/// apply, but none of the room features.
+pub fn build_hot_water_status<T: ClimateEntity>(
+    schedule: &Schedule,
+    entities: &[T],
+    runtime: &HashMap<String, EntityRuntime>,
+    now: DateTime<Local>,
+) -> HotWaterStatus {
+    let scheduled_state = schedule.get_current_state(&now);
+    let entities = entities
+        .iter()
+        .map(|entity| {
+            let cached_state = entity.get_cached_state();
+            let boost_info = entity.get_boosted_status();
+            let (boosted_state, _) = calculate_desired_heating_state_for_boost(boost_info, &now);
+            let entity_runtime = runtime.get(entity.get_entity_id());
+            let manual_override = entity_runtime
+                .and_then(|runtime| runtime.active_override(&now))
+                .cloned();
+            let paused_since = entity_runtime.and_then(|runtime| runtime.paused_since);
+            let current_state = cached_state.as_ref().map(|s| s.state.clone());
+            let effective_state = match &manual_override {
+                _ if paused_since.is_some() => current_state.clone().unwrap_or(HeatingState::Off),
+                Some(manual_override) if boosted_state != HeatingState::On => {
+                    manual_override.state.clone()
+                }
+                _ => final_desired_heating_state(&scheduled_state, &boosted_state),
+            };
+            EntityStatus {
+                entity_id: entity.get_entity_id().to_string(),
+                current_state,
+                current_temperature: cached_state.as_ref().and_then(|s| s.current_temperature),
+                boost: boost_info
+                    .as_ref()
+                    .filter(|_| boosted_state == HeatingState::On)
+                    .map(|b| BoostStatus {
+                        boost_start: b.boost_start,
+                        boost_end: b.boost_end,
+                    }),
+                manual_override,
+                open_window: None,
+                frost_protection: None,
+                optimum_start: None,
+                paused_since,
+                effective_state,
+            }
+        })
+        .collect();
+
+    HotWaterStatus {
+        active_entry: schedule.get_active_entry(&now).cloned(),
+        scheduled_state,
+        next_transition: schedule.next_transition(&now),
+        entities,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::climate::{BoostInfo, MockClimate};
+    use crate::clock::at;
+    use crate::schedule::TimePeriod;
+
+    #[test]
+    fn test_build_status_with_boost() {
+        let mut schedule = Schedule::new("Test Schedule");
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+
+        let mut boosted = MockClimate::new("climate.bedroom".to_string(), HeatingState::Off);
+        boosted.set_boost(Some(BoostInfo {
+            boost_start: at(12, 0),
+            boost_end: at(12, 45),
+        }));
+        let idle = MockClimate::new("climate.living_room".to_string(), HeatingState::Off);
+
+        let now = at(12, 30);
+        let status = build_status(
+            &schedule,
+            &[boosted, idle],
+            &HashMap::new(),
+            &Presence::default(),
+            None,
+            &TariffPlans::default(),
+            now,
+        );
+
+        assert_eq!(status.scheduled_state, HeatingState::Off);
+        assert_eq!(status.next_transition.unwrap().time, at(17, 0));
+
+        assert_eq!(status.entities[0].effective_state, HeatingState::On);
+        assert!(status.entities[0].boost.is_some());
+        assert_eq!(status.entities[1].effective_state, HeatingState::Off);
+        assert!(status.entities[1].boost.is_none());
+    }
+
+    #[test]
+    fn test_build_status_with_manual_override() {
+        let mut schedule = Schedule::new("Test Schedule");
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+        let runtime = HashMap::from([(
+            "climate.bedroom".to_string(),
+            EntityRuntime {
+                manual_override: Some(ManualOverride {
+                    state: HeatingState::Off,
+                    since: at(17, 30),
+                    until: Some(at(22, 0)),
+                    user_id: Some("someone".to_string()),
+                }),
+                ..Default::default()
+            },
+        )]);
+        let entities = [MockClimate::new(
+            "climate.bedroom".to_string(),
+            HeatingState::Off,
+        )];
+
+        let status = build_status(
+            &schedule,
+            &entities,
+            &runtime,
+            &Presence::default(),
+            None,
+            &TariffPlans::default(),
+            at(18, 0),
+        );
+        assert_eq!(status.scheduled_state, HeatingState::On);
+        assert_eq!(status.entities[0].effective_state, HeatingState::Off);
+        assert!(status.entities[0].manual_override.is_some());
+
+        // Once it has run out the schedule applies again
+        let status = build_status(
+            &schedule,
+            &entities,
+            &runtime,
+            &Presence::default(),
+            None,
+            &TariffPlans::default(),
+            at(22, 0),
+        );
+        assert!(status.entities[0].manual_override.is_none());
+        assert_eq!(status.entities[0].effective_state, HeatingState::Off);
+        let status = build_status(
+            &schedule,
+            &entities,
+            &runtime,
+            &Presence::default(),
+            None,
+            &TariffPlans::default(),
+            at(17, 0) + chrono::Days::new(1),
+        );
+        assert_eq!(status.entities[0].effective_state, HeatingState::On);
+    }
+
+    #[test]
+    fn test_frost_protection_beats_the_schedule() {
+        let schedule = Schedule::new("Away");
+        let now = at(3, 0);
+        let runtime = HashMap::from([(
+            "climate.bedroom".to_string(),
+            EntityRuntime {
+                frost_protection: Some(FrostProtection {
+                    since: now,
+                    threshold: 5.0,
+                }),
+                ..Default::default()
+            },
+        )]);
+        let entities = [MockClimate::new(
+            "climate.bedroom".to_string(),
+            HeatingState::Off,
+        )];
+
+        let status = build_status(
+            &schedule,
+            &entities,
+            &runtime,
+            &Presence::default(),
+            None,
+            &TariffPlans::default(),
+            now,
+        );
+        assert_eq!(status.scheduled_state, HeatingState::Off);
+        assert_eq!(status.entities[0].effective_state, HeatingState::On);
+        assert_eq!(
+            status.entities[0]
+                .frost_protection
+                .as_ref()
+                .unwrap()
+                .threshold,
+            5.0
+        );
+    }
+
+    #[test]
+    fn test_away_turns_the_schedule_off() {
+        use crate::presence::Occupancy;
+
+        let mut schedule = Schedule::new("Test Schedule");
+        schedule.add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 22, 0),
+            HeatingState::On,
+        ));
+        let now = at(18, 0);
+        let entities = [MockClimate::new(
+            "climate.bedroom".to_string(),
+            HeatingState::On,
+        )];
+        let mut presence = Presence {
+            occupancy: Occupancy::Away,
+            since: Some(now),
+            trackers: [("person.alex".to_string(), "not_home".to_string())].into(),
+            away_mode: Some(AwayMode::Off),
+            ..Default::default()
+        };
+
+        let status = build_status(
+            &schedule,
+            &entities,
+            &HashMap::new(),
+            &presence,
+            None,
+            &TariffPlans::default(),
+            now,
+        );
+        assert_eq!(status.scheduled_state, HeatingState::On);
+        assert_eq!(status.presence.as_ref().unwrap().occupancy, Occupancy::Away);
+        assert_eq!(status.entities[0].effective_state, HeatingState::Off);
+
+        // Eco mode keeps heating through the schedule's On periods, at a lower target
+        presence.away_mode = Some(AwayMode::Eco { temperature: 16.0 });
+        let status = build_status(
+            &schedule,
+            &entities,
+            &HashMap::new(),
+            &presence,
+            None,
+            &TariffPlans::default(),
+            now,
+        );
+        assert_eq!(status.entities[0].effective_state, HeatingState::On);
+    }
+
+    #[test]
+    fn test_hot_water_status_follows_its_own_schedule() {
+        let mut schedule = Schedule::new("Hot Water");
+        schedule.add_entry(ScheduleEntry::new(
+            "Morning",
+            TimePeriod::new(6, 0, 7, 30),
+            HeatingState::On,
+        ));
+        let mut boosted = MockClimate::new("water_heater.tank".to_string(), HeatingState::Off);
+        let idle = MockClimate::new("switch.immersion".to_string(), HeatingState::Off);
+
+        let mut runtime = HashMap::new();
+        let status = build_hot_water_status(
+            &schedule,
+            &[boosted.clone(), idle.clone()],
+            &runtime,
+            at(6, 30),
+        );
+        assert_eq!(status.active_entry.unwrap().name, "Morning");
+        assert_eq!(status.next_transition.unwrap().time, at(7, 30));
+        assert_eq!(status.entities[1].effective_state, HeatingState::On);
+
+        boosted.set_boost(Some(BoostInfo {
+            boost_start: at(12, 0),
+            boost_end: at(13, 0),
+        }));
+        let entities = [boosted.clone(), idle.clone()];
+        let status = build_hot_water_status(&schedule, &entities, &runtime, at(12, 15));
+        assert_eq!(status.scheduled_state, HeatingState::Off);
+        assert_eq!(status.entities[0].effective_state, HeatingState::On);
+        assert!(status.entities[0].boost.is_some());
+        assert_eq!(status.entities[1].effective_state, HeatingState::Off);
+
+        // Paused, the tank is left as it is whatever its boost says
+        runtime.insert(
+            "water_heater.tank".to_string(),
+            EntityRuntime {
+                paused_since: Some(at(12, 0)),
+                ..Default::default()
+            },
+        );
+        // Switched on by hand, the immersion is kept on until the override ends
+        runtime.insert(
+            "switch.immersion".to_string(),
+            EntityRuntime {
+                manual_override: Some(ManualOverride {
+                    state: HeatingState::On,
+                    since: at(12, 0),
+                    until: Some(at(13, 0)),
+                    user_id: None,
+                }),
+                ..Default::default()
+            },
+        );
+        let status = build_hot_water_status(&schedule, &[boosted, idle], &runtime, at(12, 15));
+        assert_eq!(status.entities[0].paused_since, Some(at(12, 0)));
+        assert_eq!(status.entities[0].effective_state, HeatingState::Off);
+        assert!(status.entities[1].manual_override.is_some());
+        assert_eq!(status.entities[1].effective_state, HeatingState::On);
+    }
+}
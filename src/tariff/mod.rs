@@ -0,0 +1,393 @@
+use crate::api_client::EntityState;
+use crate::schedule::TimePeriod;
+use chrono::{DateTime, Days, Duration, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub mod persistence;
+
+/// Flexible entries are planned in steps of this length
+pub const PLAN_STEP_MINUTES: i64 = 5;
+
+/// Sensor attributes that commonly hold a list of upcoming prices
+const SENSOR_PRICE_ATTRIBUTES: [&str; 4] = ["rates", "prices", "raw_today", "raw_tomorrow"];
+/// Keys a price can be stored under within each sensor slot
+const SENSOR_PRICE_KEYS: [&str; 3] = ["value_inc_vat", "price", "value"];
+
+/// The price of energy between two times
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceSlot {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub price: f64,
+}
+
+/// A price that repeats every day, e.g. an overnight off-peak rate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyPrice {
+    pub time_period: TimePeriod,
+    pub price: f64,
+}
+
+/// Known energy prices, as stored in the tariff file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tariff {
+    /// Prices that repeat every day
+    #[serde(default)]
+    pub daily: Vec<DailyPrice>,
+    /// Prices for specific times, e.g. from a Home Assistant sensor; these win over daily prices
+    #[serde(default)]
+    pub slots: Vec<PriceSlot>,
+}
+
+impl Tariff {
+    /// The price at `time`, if one is known
+    pub fn price_at(&self, time: &DateTime<Local>) -> Option<f64> {
+        self.slots
+            .iter()
+            .find(|slot| slot.start <= *time && *time < slot.end)
+            .map(|slot| slot.price)
+            .or_else(|| {
+                self.daily
+                    .iter()
+                    .find(|daily| daily.time_period.contains(time.time()))
+                    .map(|daily| daily.price)
+            })
+    }
+}
+
+/// Marks an On entry as flexible: heat for `duration_minutes` somewhere within its period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlexibleHeating {
+    pub duration_minutes: u32,
+    /// Heat in one block rather than in the cheapest slots wherever they fall
+    #[serde(default)]
+    pub contiguous: bool,
+}
+
+/// A stretch of time a flexible entry heats for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeatingSlot {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+/// The slots picked for one occurrence of a flexible entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlexiblePlan {
+    pub entry_id: Uuid,
+    pub window_start: DateTime<Local>,
+    pub window_end: DateTime<Local>,
+    pub slots: Vec<HeatingSlot>,
+    /// Average price over the chosen slots, when every one of them had a known price
+    pub average_price: Option<f64>,
+}
+
+impl FlexiblePlan {
+    pub fn is_heating(&self, now: &DateTime<Local>) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.start <= *now && *now < slot.end)
+    }
+}
+
+/// Prices and the plans made from them, shared between the scheduler and the API
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TariffPlans {
+    pub tariff: Tariff,
+    /// Plans for the current occurrence of each flexible entry, by entry id
+    pub plans: HashMap<Uuid, FlexiblePlan>,
+}
+
+/// Length of a schedule period in minutes; one starting and ending at the same time is a whole day
+pub fn period_minutes(period: &TimePeriod) -> i64 {
+    let minutes = (period.end - period.start)
+        .num_minutes()
+        .rem_euclid(24 * 60);
+    if minutes == 0 { 24 * 60 } else { minutes }
+}
+
+/// Start and end of the occurrence of `period` that contains `now`
+pub fn window_bounds(
+    period: &TimePeriod,
+    now: &DateTime<Local>,
+) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let today = now.date_naive();
+    // A period crossing midnight may have started yesterday
+    let start_date = if now.time() < period.start {
+        today.checked_sub_days(Days::new(1))?
+    } else {
+        today
+    };
+    let start = local(start_date.and_time(period.start))?;
+    Some((start, start + Duration::minutes(period_minutes(period))))
+}
+
+fn local(naive: chrono::NaiveDateTime) -> Option<DateTime<Local>> {
+    naive.and_local_timezone(Local).earliest()
+}
+
+/// Pick the cheapest `duration_minutes` of heating between `window_start` and `window_end`.
+///
+/// Times without a known price count as dearer than any known price. Ties go to the earliest
+/// slot, so without any prices heating simply starts at the beginning of the window.
+pub fn plan_flexible(
+    entry_id: Uuid,
+    flexible: &FlexibleHeating,
+    window_start: DateTime<Local>,
+    window_end: DateTime<Local>,
+    tariff: &Tariff,
+) -> FlexiblePlan {
+    let step = Duration::minutes(PLAN_STEP_MINUTES);
+    let mut steps: Vec<(DateTime<Local>, Option<f64>)> = Vec::new();
+    let mut time = window_start;
+    while time < window_end {
+        steps.push((time, tariff.price_at(&time)));
+        time += step;
+    }
+    let needed =
+        (flexible.duration_minutes.div_ceil(PLAN_STEP_MINUTES as u32) as usize).min(steps.len());
+
+    let chosen: Vec<usize> = if flexible.contiguous {
+        let start = (0..=steps.len() - needed)
+            .min_by(|a, b| {
+                cost(&steps[*a..*a + needed])
+                    .partial_cmp(&cost(&steps[*b..*b + needed]))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+        (start..start + needed).collect()
+    } else {
+        let mut by_price: Vec<usize> = (0..steps.len()).collect();
+        // Stable, so equal prices keep their order in time
+        by_price.sort_by(|a, b| {
+            cost(&steps[*a..=*a])
+                .partial_cmp(&cost(&steps[*b..=*b]))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut chosen: Vec<usize> = by_price.into_iter().take(needed).collect();
+        chosen.sort();
+        chosen
+    };
+
+    let mut slots: Vec<HeatingSlot> = Vec::new();
+    for index in &chosen {
+        let start = steps[*index].0;
+        let end = (start + step).min(window_end);
+        match slots.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => slots.push(HeatingSlot { start, end }),
+        }
+    }
+    let prices: Option<Vec<f64>> = chosen.iter().map(|index| steps[*index].1).collect();
+    let average_price = prices
+        .filter(|prices| !prices.is_empty())
+        .map(|prices| prices.iter().sum::<f64>() / prices.len() as f64);
+
+    FlexiblePlan {
+        entry_id,
+        window_start,
+        window_end,
+        slots,
+        average_price,
+    }
+}
+
+/// How dear a run of steps is: how many have no known price, then the total of the rest
+fn cost(steps: &[(DateTime<Local>, Option<f64>)]) -> (usize, f64) {
+    let unknown = steps.iter().filter(|(_, price)| price.is_none()).count();
+    let total = steps.iter().filter_map(|(_, price)| *price).sum();
+    (unknown, total)
+}
+
+/// Price slots listed in a Home Assistant sensor's attributes by common tariff integrations
+pub fn slots_from_sensor(entity: &EntityState) -> Vec<PriceSlot> {
+    let mut slots: Vec<PriceSlot> = SENSOR_PRICE_ATTRIBUTES
+        .iter()
+        .filter_map(|attribute| entity.attributes.get(attribute)?.as_array())
+        .flatten()
+        .filter_map(slot_from_value)
+        .collect();
+    slots.sort_by_key(|slot| slot.start);
+    slots
+}
+
+fn slot_from_value(value: &Value) -> Option<PriceSlot> {
+    let time = |key: &str| {
+        DateTime::parse_from_rfc3339(value.get(key)?.as_str()?)
+            .ok()
+            .map(|time| time.with_timezone(&Local))
+    };
+    let price = SENSOR_PRICE_KEYS
+        .iter()
+        .find_map(|key| value.get(key)?.as_f64())?;
+    Some(PriceSlot {
+        start: time("start")?,
+        end: time("end")?,
+        price,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::at;
+    use serde_json::json;
+
+    fn slot(start: DateTime<Local>, end: DateTime<Local>) -> HeatingSlot {
+        HeatingSlot { start, end }
+    }
+
+    /// 14:00-20:00 with a cheap hour at 15:00, a cheaper half hour at 18:00 and a peak from 16:00
+    fn afternoon_tariff() -> Tariff {
+        Tariff {
+            daily: vec![DailyPrice {
+                time_period: TimePeriod::new(0, 0, 0, 0),
+                price: 0.25,
+            }],
+            slots: vec![
+                PriceSlot {
+                    start: at(15, 0),
+                    end: at(16, 0),
+                    price: 0.10,
+                },
+                PriceSlot {
+                    start: at(16, 0),
+                    end: at(18, 0),
+                    price: 0.40,
+                },
+                PriceSlot {
+                    start: at(18, 0),
+                    end: at(18, 30),
+                    price: 0.05,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_price_at_prefers_dated_slots() {
+        let tariff = afternoon_tariff();
+        assert_eq!(tariff.price_at(&at(15, 30)), Some(0.10));
+        assert_eq!(tariff.price_at(&at(16, 0)), Some(0.40));
+        assert_eq!(tariff.price_at(&at(9, 0)), Some(0.25));
+        assert_eq!(Tariff::default().price_at(&at(9, 0)), None);
+    }
+
+    #[test]
+    fn test_split_plan_picks_the_cheapest_slots() {
+        let flexible = FlexibleHeating {
+            duration_minutes: 90,
+            contiguous: false,
+        };
+        let plan = plan_flexible(
+            Uuid::nil(),
+            &flexible,
+            at(14, 0),
+            at(20, 0),
+            &afternoon_tariff(),
+        );
+        assert_eq!(
+            plan.slots,
+            vec![slot(at(15, 0), at(16, 0)), slot(at(18, 0), at(18, 30))]
+        );
+        assert!((plan.average_price.unwrap() - 0.25 / 3.0).abs() < 1e-9);
+        assert!(plan.is_heating(&at(18, 10)));
+        assert!(!plan.is_heating(&at(17, 0)));
+    }
+
+    #[test]
+    fn test_contiguous_plan_picks_the_cheapest_block() {
+        let flexible = FlexibleHeating {
+            duration_minutes: 120,
+            contiguous: true,
+        };
+        let plan = plan_flexible(
+            Uuid::nil(),
+            &flexible,
+            at(14, 0),
+            at(20, 0),
+            &afternoon_tariff(),
+        );
+        // 14:00-16:00 averages 0.175; anything later crosses the peak or the 0.25 evening
+        assert_eq!(plan.slots, vec![slot(at(14, 0), at(16, 0))]);
+    }
+
+    #[test]
+    fn test_plan_without_prices_starts_at_the_window() {
+        let flexible = FlexibleHeating {
+            duration_minutes: 45,
+            contiguous: false,
+        };
+        let plan = plan_flexible(
+            Uuid::nil(),
+            &flexible,
+            at(14, 0),
+            at(20, 0),
+            &Tariff::default(),
+        );
+        assert_eq!(plan.slots, vec![slot(at(14, 0), at(14, 45))]);
+        assert_eq!(plan.average_price, None);
+
+        // Asking for more than the window heats throughout
+        let flexible = FlexibleHeating {
+            duration_minutes: 600,
+            contiguous: true,
+        };
+        let plan = plan_flexible(
+            Uuid::nil(),
+            &flexible,
+            at(14, 0),
+            at(20, 0),
+            &Tariff::default(),
+        );
+        assert_eq!(plan.slots, vec![slot(at(14, 0), at(20, 0))]);
+    }
+
+    #[test]
+    fn test_window_bounds() {
+        let afternoon = TimePeriod::new(14, 0, 20, 0);
+        assert_eq!(
+            window_bounds(&afternoon, &at(15, 0)),
+            Some((at(14, 0), at(20, 0)))
+        );
+        assert_eq!(period_minutes(&afternoon), 360);
+
+        let overnight = TimePeriod::new(22, 0, 6, 0);
+        let (start, end) = window_bounds(&overnight, &at(2, 0)).unwrap();
+        assert_eq!(start, at(22, 0) - Duration::days(1));
+        assert_eq!(end, at(6, 0));
+        assert_eq!(period_minutes(&TimePeriod::new(0, 0, 0, 0)), 24 * 60);
+    }
+
+    #[test]
+    fn test_slots_from_sensor() {
+        let entity = EntityState {
+            entity_id: "sensor.electricity_rates".to_string(),
+            state: "0.21".to_string(),
+            attributes: json!({
+                "rates": [
+                    {
+                        "start": at(15, 30).to_rfc3339(),
+                        "end": at(16, 0).to_rfc3339(),
+                        "value_inc_vat": 0.12
+                    },
+                    {
+                        "start": at(15, 0).to_rfc3339(),
+                        "end": at(15, 30).to_rfc3339(),
+                        "value_inc_vat": 0.18
+                    },
+                    { "start": "soon", "end": "later", "value_inc_vat": 0.1 }
+                ]
+            }),
+            ..Default::default()
+        };
+        let slots = slots_from_sensor(&entity);
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].start, at(15, 0));
+        assert_eq!(slots[0].price, 0.18);
+        assert_eq!(slots[1].price, 0.12);
+    }
+}
@@ -0,0 +1,57 @@
+use super::Tariff;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Load a tariff from a JSON file
+pub fn load_tariff<P: AsRef<Path>>(path: P) -> Result<Tariff> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tariff file: {}", path.display()))?;
+
+    let tariff: Tariff = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse tariff JSON from: {}", path.display()))?;
+
+    Ok(tariff)
+}
+
+/// Load the tariff if the file exists; without one no prices are known
+pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<Tariff> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        println!("Loading tariff from: {}", path.display());
+        load_tariff(path)
+    } else {
+        println!("No tariff file found at: {}", path.display());
+        Ok(Tariff::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_tariff_file() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("tariff.json");
+        assert_eq!(load_or_default(&file_path).unwrap(), Tariff::default());
+
+        fs::write(
+            &file_path,
+            r#"{
+                "daily": [
+                    {"time_period": {"start": "00:30:00", "end": "07:30:00"}, "price": 0.09},
+                    {"time_period": {"start": "07:30:00", "end": "00:30:00"}, "price": 0.28}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let tariff = load_or_default(&file_path).unwrap();
+        assert_eq!(tariff.daily.len(), 2);
+        assert!(tariff.slots.is_empty());
+        assert_eq!(tariff.daily[0].price, 0.09);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::stats::midnight_in;
+use chrono::{DateTime, Duration, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub mod persistence;
+
+/// How densely samples are stored and how long they are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SamplingPolicy {
+    /// Minimum spacing between stored samples; newer readings within a slot replace the older one
+    pub sample_interval_secs: i64,
+    pub max_age_days: i64,
+}
+
+impl Default for SamplingPolicy {
+    fn default() -> Self {
+        SamplingPolicy {
+            sample_interval_secs: 60,
+            max_age_days: 7,
+        }
+    }
+}
+
+/// A single reading for one entity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: DateTime<Local>,
+    pub temperature: f64,
+    pub setpoint: Option<f64>,
+    pub heating_on: bool,
+}
+
+/// Resolution the series can be returned at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Resolution {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[default]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Resolution {
+    pub fn as_secs(&self) -> i64 {
+        match self {
+            Resolution::OneMinute => 60,
+            Resolution::FiveMinutes => 5 * 60,
+            Resolution::FifteenMinutes => 15 * 60,
+            Resolution::OneHour => 60 * 60,
+            Resolution::OneDay => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Samples averaged over one bucket of the requested resolution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeriesPoint {
+    /// Start of the bucket
+    pub timestamp: DateTime<Local>,
+    pub temperature: f64,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    pub setpoint: Option<f64>,
+    /// Fraction of samples in the bucket with heating on (0.0 - 1.0)
+    pub heating_on_ratio: f64,
+}
+
+/// Filters for querying the series
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeriesQuery {
+    pub entity_id: Option<String>,
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub resolution: Resolution,
+}
+
+/// Per-entity temperature, setpoint and heating state over time, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemperatureHistory {
+    #[serde(skip)]
+    pub policy: SamplingPolicy,
+    pub series: HashMap<String, Vec<Sample>>,
+}
+
+impl TemperatureHistory {
+    pub fn new(policy: SamplingPolicy) -> Self {
+        TemperatureHistory {
+            policy,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Store a reading, replacing the previous one if it falls in the same sample slot. Old
+    /// samples are left for `prune`, which the scheduler runs before each save.
+    pub fn record(&mut self, entity_id: &str, sample: Sample) {
+        let slot = sample.timestamp.timestamp() / self.policy.sample_interval_secs;
+        let samples = self.series.entry(entity_id.to_string()).or_default();
+
+        match samples.last_mut() {
+            Some(last) if last.timestamp.timestamp() / self.policy.sample_interval_secs == slot => {
+                *last = sample;
+            }
+            _ => samples.push(sample),
+        }
+    }
+
+    /// Drop samples older than the retention window and entities left without samples
+    pub fn prune(&mut self, now: &DateTime<Local>) {
+        let cutoff = *now - Duration::days(self.policy.max_age_days);
+        for samples in self.series.values_mut() {
+            samples.retain(|s| s.timestamp >= cutoff);
+        }
+        self.series.retain(|_, samples| !samples.is_empty());
+    }
+
+    /// Raw samples for an entity within an optional time range
+    pub fn samples(
+        &self,
+        entity_id: &str,
+        from: Option<DateTime<Local>>,
+        to: Option<DateTime<Local>>,
+    ) -> Vec<Sample> {
+        self.series
+            .get(entity_id)
+            .map(|samples| {
+                samples
+                    .iter()
+                    .filter(|s| from.is_none_or(|from| s.timestamp >= from))
+                    .filter(|s| to.is_none_or(|to| s.timestamp <= to))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Return the series for each matching entity, downsampled to the requested resolution
+    pub fn query(&self, query: &SeriesQuery) -> HashMap<String, Vec<SeriesPoint>> {
+        self.series
+            .keys()
+            .filter(|entity_id| match &query.entity_id {
+                Some(wanted) => *entity_id == wanted,
+                None => true,
+            })
+            .map(|entity_id| {
+                let samples = self.samples(entity_id, query.from, query.to);
+                (entity_id.clone(), downsample(&samples, query.resolution))
+            })
+            .collect()
+    }
+}
+
+/// Average samples into buckets of local time: days start at local midnight and shorter buckets
+/// are counted from it, so hours follow the local clock in half-hour offset zones too
+pub fn downsample(samples: &[Sample], resolution: Resolution) -> Vec<SeriesPoint> {
+    downsample_in(samples, resolution, &Local)
+}
+
+fn downsample_in<Tz: TimeZone>(
+    samples: &[Sample],
+    resolution: Resolution,
+    tz: &Tz,
+) -> Vec<SeriesPoint> {
+    let bucket_secs = resolution.as_secs();
+    let bucket_start = |timestamp: &DateTime<Local>| {
+        let timestamp = timestamp.with_timezone(tz);
+        let midnight = midnight_in(timestamp.date_naive(), tz);
+        let bucket = match resolution {
+            Resolution::OneDay => midnight,
+            _ => {
+                let into_day = (timestamp - midnight.clone()).num_seconds();
+                midnight + Duration::seconds(into_day.div_euclid(bucket_secs) * bucket_secs)
+            }
+        };
+        bucket.with_timezone(&Local)
+    };
+    let mut points: Vec<SeriesPoint> = Vec::new();
+
+    for chunk in samples.chunk_by(|a, b| bucket_start(&a.timestamp) == bucket_start(&b.timestamp)) {
+        let count = chunk.len() as f64;
+        let temperatures = chunk.iter().map(|s| s.temperature);
+        let setpoints: Vec<f64> = chunk.iter().filter_map(|s| s.setpoint).collect();
+
+        points.push(SeriesPoint {
+            timestamp: bucket_start(&chunk[0].timestamp),
+            temperature: temperatures.clone().sum::<f64>() / count,
+            min_temperature: temperatures.clone().fold(f64::INFINITY, f64::min),
+            max_temperature: temperatures.fold(f64::NEG_INFINITY, f64::max),
+            setpoint: if setpoints.is_empty() {
+                None
+            } else {
+                Some(setpoints.iter().sum::<f64>() / setpoints.len() as f64)
+            },
+            heating_on_ratio: chunk.iter().filter(|s| s.heating_on).count() as f64 / count,
+        });
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    fn sample(minute: u32, second: u32, temperature: f64, heating_on: bool) -> Sample {
+        Sample {
+            timestamp: Local
+                .with_ymd_and_hms(2025, 1, 15, 7, minute, second)
+                .unwrap(),
+            temperature,
+            setpoint: Some(21.0),
+            heating_on,
+        }
+    }
+
+    #[test]
+    fn test_record_keeps_one_sample_per_slot() {
+        let mut history = TemperatureHistory::new(SamplingPolicy::default());
+
+        history.record("climate.bedroom", sample(0, 0, 18.0, false));
+        history.record("climate.bedroom", sample(0, 15, 18.5, true));
+        history.record("climate.bedroom", sample(1, 0, 19.0, true));
+
+        let samples = history.samples("climate.bedroom", None, None);
+        assert_eq!(samples.len(), 2);
+        // The later reading in the first minute replaces the earlier one
+        assert_eq!(samples[0].temperature, 18.5);
+        assert!(samples[0].heating_on);
+    }
+
+    #[test]
+    fn test_prune_drops_old_samples_and_empty_entities() {
+        let mut history = TemperatureHistory::new(SamplingPolicy {
+            sample_interval_secs: 60,
+            max_age_days: 1,
+        });
+
+        let old = sample(0, 0, 18.0, false);
+        history.record("climate.bedroom", old.clone());
+        history.prune(&(old.timestamp + Duration::days(2)));
+
+        assert!(history.series.is_empty());
+    }
+
+    #[test]
+    fn test_downsample_averages_buckets() {
+        let samples = vec![
+            sample(0, 0, 18.0, false),
+            sample(1, 0, 19.0, true),
+            sample(4, 0, 20.0, true),
+            sample(5, 0, 21.0, true),
+        ];
+
+        let points = downsample(&samples, Resolution::FiveMinutes);
+
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].timestamp, samples[0].timestamp);
+        assert_eq!(points[0].temperature, 19.0);
+        assert_eq!(points[0].min_temperature, 18.0);
+        assert_eq!(points[0].max_temperature, 20.0);
+        assert!((points[0].heating_on_ratio - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(points[1].temperature, 21.0);
+        assert_eq!(points[1].setpoint, Some(21.0));
+    }
+
+    #[test]
+    fn test_downsample_follows_local_days_and_hours() {
+        // 23:30 and 00:15 in India, at +05:30, fall in the same UTC day and hour
+        let india = FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap();
+        let utc = |hour, minute| {
+            Utc.with_ymd_and_hms(2025, 1, 15, hour, minute, 0)
+                .unwrap()
+                .with_timezone(&Local)
+        };
+        let samples = vec![
+            Sample {
+                timestamp: utc(18, 0),
+                ..sample(0, 0, 18.0, false)
+            },
+            Sample {
+                timestamp: utc(18, 45),
+                ..sample(0, 0, 20.0, false)
+            },
+        ];
+        let local = |day, hour| india.with_ymd_and_hms(2025, 1, day, hour, 0, 0).unwrap();
+
+        let days = downsample_in(&samples, Resolution::OneDay, &india);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].timestamp, local(15, 0));
+        assert_eq!(days[1].timestamp, local(16, 0));
+
+        let hours = downsample_in(&samples, Resolution::OneHour, &india);
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].timestamp, local(15, 23));
+        assert_eq!(hours[1].timestamp, local(16, 0));
+    }
+
+    #[test]
+    fn test_query_filters_entity() {
+        let mut history = TemperatureHistory::new(SamplingPolicy::default());
+        history.record("climate.bedroom", sample(0, 0, 18.0, false));
+        history.record("climate.living_room", sample(0, 0, 20.0, false));
+
+        let result = history.query(&SeriesQuery {
+            entity_id: Some("climate.bedroom".to_string()),
+            ..Default::default()
+        });
+
+        assert_eq!(result.len(), 1);
+        assert!(result.contains_key("climate.bedroom"));
+    }
+}
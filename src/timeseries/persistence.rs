@@ -0,0 +1,109 @@
+use super::{SamplingPolicy, TemperatureHistory};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Load the temperature history from a JSON file
+pub fn load_temperature_history<P: AsRef<Path>>(path: P) -> Result<TemperatureHistory> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!(
+            "Failed to read temperature history file: {}",
+            path.display()
+        )
+    })?;
+
+    let history: TemperatureHistory = serde_json::from_str(&contents).with_context(|| {
+        format!(
+            "Failed to parse temperature history JSON from: {}",
+            path.display()
+        )
+    })?;
+
+    Ok(history)
+}
+
+/// Save the temperature history to a JSON file
+pub fn save_temperature_history<P: AsRef<Path>>(
+    history: &TemperatureHistory,
+    path: P,
+) -> Result<()> {
+    let path = path.as_ref();
+    let json = serde_json::to_string(history)
+        .context("Failed to serialize temperature history to JSON")?;
+
+    fs::write(path, json).with_context(|| {
+        format!(
+            "Failed to write temperature history file: {}",
+            path.display()
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Load the temperature history from file, or start an empty one if it doesn't exist
+pub fn load_or_create_default<P: AsRef<Path>>(
+    path: P,
+    policy: SamplingPolicy,
+) -> Result<TemperatureHistory> {
+    let path = path.as_ref();
+
+    if path.exists() {
+        println!("Loading temperature history from: {}", path.display());
+        let mut history = load_temperature_history(path)?;
+        history.policy = policy;
+        history.prune(&chrono::Local::now());
+        Ok(history)
+    } else {
+        println!("No temperature history file found at: {}", path.display());
+
+        let history = TemperatureHistory::new(policy);
+        save_temperature_history(&history, path)
+            .context("Failed to save empty temperature history")?;
+
+        println!("Empty temperature history saved to: {}", path.display());
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::Sample;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_load_temperature_history() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("temperature_history.json");
+
+        let mut history = TemperatureHistory::new(SamplingPolicy::default());
+        history.record(
+            "climate.bedroom",
+            Sample {
+                timestamp: chrono::Local::now(),
+                temperature: 19.5,
+                setpoint: Some(21.0),
+                heating_on: true,
+            },
+        );
+
+        save_temperature_history(&history, &file_path).unwrap();
+        let loaded = load_temperature_history(&file_path).unwrap();
+
+        assert_eq!(loaded.series, history.series);
+    }
+
+    #[test]
+    fn test_load_or_create_default() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("temperature_history.json");
+
+        let history1 = load_or_create_default(&file_path, SamplingPolicy::default()).unwrap();
+        assert!(file_path.exists());
+
+        let history2 = load_or_create_default(&file_path, SamplingPolicy::default()).unwrap();
+        assert_eq!(history1.series, history2.series);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::api_client::EntityState;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+/// Warm weather skipping ends once it's this far below the cut-off, so it doesn't flap
+pub const WARM_WEATHER_RELEASE_MARGIN: f64 = 1.0;
+/// Largest change the heating curve makes to a target, either way
+pub const MAX_SETPOINT_SHIFT: f64 = 3.0;
+/// How long the last reading stands in for a sensor that can't be read
+pub const MAX_READING_AGE: Duration = Duration::hours(1);
+
+/// Adjusts a schedule to the outdoor temperature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeatherCompensation {
+    /// `weather.*` entity, read from its `temperature` attribute, or a `sensor.*` reading °C
+    pub sensor: String,
+    /// The schedule's On periods are skipped while it's at least this warm outside
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warm_cutoff: Option<f64>,
+    /// Shifts thermostat targets up when it's cold outside and down when it's mild
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heating_curve: Option<HeatingCurve>,
+}
+
+/// Moves targets by `slope` °C per °C the outdoor temperature is below `reference_temperature`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatingCurve {
+    pub slope: f64,
+    pub reference_temperature: f64,
+}
+
+impl HeatingCurve {
+    /// How far to move a target at this outdoor temperature, capped at `MAX_SETPOINT_SHIFT`
+    pub fn setpoint_shift(&self, outdoor_temperature: f64) -> f64 {
+        (self.slope * (self.reference_temperature - outdoor_temperature))
+            .clamp(-MAX_SETPOINT_SHIFT, MAX_SETPOINT_SHIFT)
+    }
+}
+
+/// The latest outdoor reading and what the scheduler made of it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutdoorWeather {
+    pub sensor: String,
+    pub temperature: f64,
+    pub read_at: DateTime<Local>,
+    /// On periods are being skipped because it's warm outside
+    pub warm_weather: bool,
+    /// Added to thermostat targets by the heating curve
+    pub setpoint_shift: f64,
+}
+
+impl OutdoorWeather {
+    /// Whether the reading is too old to act on
+    pub fn is_stale(&self, now: DateTime<Local>) -> bool {
+        now - self.read_at > MAX_READING_AGE
+    }
+}
+
+/// The outdoor temperature a `weather` or `sensor` entity reports, if it has one
+pub fn outdoor_temperature(entity: &EntityState) -> Option<f64> {
+    if entity.entity_id.starts_with("weather.") {
+        entity.attributes.get("temperature")?.as_f64()
+    } else {
+        entity.state.parse().ok()
+    }
+}
+
+/// Whether On periods should be skipped, given whether they already are
+pub fn warm_weather(active: bool, outdoor_temperature: f64, cutoff: Option<f64>) -> bool {
+    let Some(cutoff) = cutoff else {
+        return false;
+    };
+    if active {
+        outdoor_temperature >= cutoff - WARM_WEATHER_RELEASE_MARGIN
+    } else {
+        outdoor_temperature >= cutoff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entity(entity_id: &str, state: &str, attributes: serde_json::Value) -> EntityState {
+        EntityState {
+            entity_id: entity_id.to_string(),
+            state: state.to_string(),
+            attributes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_outdoor_temperature() {
+        let weather = entity("weather.home", "sunny", json!({"temperature": 12.5}));
+        assert_eq!(outdoor_temperature(&weather), Some(12.5));
+        let sensor = entity("sensor.outdoor", "-3.2", json!({}));
+        assert_eq!(outdoor_temperature(&sensor), Some(-3.2));
+        assert_eq!(
+            outdoor_temperature(&entity("sensor.outdoor", "unavailable", json!({}))),
+            None
+        );
+        assert_eq!(
+            outdoor_temperature(&entity("weather.home", "sunny", json!({}))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_warm_weather_has_a_release_margin() {
+        assert!(!warm_weather(false, 20.0, None));
+        assert!(!warm_weather(false, 15.9, Some(16.0)));
+        assert!(warm_weather(false, 16.0, Some(16.0)));
+        assert!(warm_weather(true, 15.5, Some(16.0)));
+        assert!(!warm_weather(true, 14.9, Some(16.0)));
+    }
+
+    #[test]
+    fn test_heating_curve_shift() {
+        let curve = HeatingCurve {
+            slope: 0.2,
+            reference_temperature: 10.0,
+        };
+        assert_eq!(curve.setpoint_shift(10.0), 0.0);
+        assert!((curve.setpoint_shift(0.0) - 2.0).abs() < 1e-9);
+        assert!((curve.setpoint_shift(15.0) + 1.0).abs() < 1e-9);
+        assert_eq!(curve.setpoint_shift(-30.0), MAX_SETPOINT_SHIFT);
+    }
+}
@@ -0,0 +1,167 @@
+//! In-process stand-in for an MQTT broker, answering the bridge with rumqttc's own codec
+
+use bytes::BytesMut;
+use ha_heating_scheduler::mqtt::MqttSettings;
+use rumqttc::mqttbytes::v4::{
+    ConnAck, Connect, ConnectReturnCode, Packet, Publish, SubAck, SubscribeReasonCode,
+};
+use rumqttc::mqttbytes::{Error, QoS};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Default)]
+struct BrokerState {
+    connects: Vec<Connect>,
+    subscriptions: Vec<String>,
+    /// Latest retained payload per topic; an empty retained payload clears it
+    retained: HashMap<String, String>,
+    /// Every message published by a client, in order
+    published: Vec<(String, String)>,
+    clients: Vec<mpsc::UnboundedSender<Packet>>,
+}
+
+/// Handle to a running fake broker; clones share the same state
+#[derive(Debug, Clone)]
+pub struct FakeBroker {
+    pub port: u16,
+    state: Arc<Mutex<BrokerState>>,
+}
+
+impl FakeBroker {
+    /// Start listening on a random local port
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(BrokerState::default()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let accept_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_client(stream, Arc::clone(&accept_state)));
+            }
+        });
+        FakeBroker { port, state }
+    }
+
+    /// Bridge settings pointing at this broker, publishing quickly
+    pub fn settings(&self, topic_prefix: &str) -> MqttSettings {
+        MqttSettings {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            client_id: "test-bridge".to_string(),
+            username: Some("user".to_string()),
+            password: Some("secret".to_string()),
+            topic_prefix: topic_prefix.to_string(),
+            publish_interval: std::time::Duration::from_millis(50),
+            discovery: None,
+        }
+    }
+
+    /// Send a message to every connected client, as if another client had published it
+    pub fn publish(&self, topic: &str, payload: &str) {
+        let packet = Packet::Publish(Publish::new(topic, QoS::AtMostOnce, payload));
+        for client in &self.state.lock().unwrap().clients {
+            let _ = client.send(packet.clone());
+        }
+    }
+
+    pub fn retained(&self, topic: &str) -> Option<String> {
+        self.state.lock().unwrap().retained.get(topic).cloned()
+    }
+
+    pub fn published(&self) -> Vec<(String, String)> {
+        self.state.lock().unwrap().published.clone()
+    }
+
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.state.lock().unwrap().subscriptions.clone()
+    }
+
+    pub fn connects(&self) -> Vec<Connect> {
+        self.state.lock().unwrap().connects.clone()
+    }
+}
+
+async fn serve_client(stream: tokio::net::TcpStream, state: Arc<Mutex<BrokerState>>) {
+    let (mut reader, mut writer) = stream.into_split();
+    let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Packet>();
+    tokio::spawn(async move {
+        while let Some(packet) = outgoing_rx.recv().await {
+            let mut bytes = BytesMut::new();
+            if packet.write(&mut bytes, MAX_PACKET_SIZE).is_err()
+                || writer.write_all(&bytes).await.is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut last_will = None;
+    let mut buffer = BytesMut::new();
+    loop {
+        let packet = match Packet::read(&mut buffer, MAX_PACKET_SIZE) {
+            Ok(packet) => packet,
+            Err(Error::InsufficientBytes(_)) => match reader.read_buf(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => continue,
+            },
+            Err(_) => break,
+        };
+        let reply = {
+            let mut state = state.lock().unwrap();
+            match packet {
+                Packet::Connect(connect) => {
+                    last_will = connect.last_will.clone();
+                    state.connects.push(connect);
+                    state.clients.push(outgoing.clone());
+                    Some(Packet::ConnAck(ConnAck::new(
+                        ConnectReturnCode::Success,
+                        false,
+                    )))
+                }
+                Packet::Subscribe(subscribe) => {
+                    let return_codes = subscribe
+                        .filters
+                        .iter()
+                        .map(|filter| SubscribeReasonCode::Success(filter.qos))
+                        .collect();
+                    let paths = subscribe.filters.into_iter().map(|filter| filter.path);
+                    state.subscriptions.extend(paths);
+                    Some(Packet::SubAck(SubAck::new(subscribe.pkid, return_codes)))
+                }
+                Packet::Publish(publish) => {
+                    let payload = String::from_utf8_lossy(&publish.payload).to_string();
+                    if publish.retain && payload.is_empty() {
+                        state.retained.remove(&publish.topic);
+                    } else if publish.retain {
+                        state
+                            .retained
+                            .insert(publish.topic.clone(), payload.clone());
+                    }
+                    state.published.push((publish.topic, payload));
+                    None
+                }
+                Packet::PingReq => Some(Packet::PingResp),
+                Packet::Disconnect => {
+                    last_will = None;
+                    break;
+                }
+                _ => None,
+            }
+        };
+        if let Some(reply) = reply {
+            let _ = outgoing.send(reply);
+        }
+    }
+
+    // Dropped without saying goodbye
+    if let Some(will) = last_will
+        && will.retain
+    {
+        let payload = String::from_utf8_lossy(&will.message).to_string();
+        state.lock().unwrap().retained.insert(will.topic, payload);
+    }
+}
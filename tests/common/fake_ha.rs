@@ -0,0 +1,311 @@
+//! In-process stand-in for the parts of the Home Assistant REST API the scheduler uses
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use ha_heating_scheduler::clock::{Clock, MockClock};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+pub const FAKE_TOKEN: &str = "fake-ha-token";
+/// The user Home Assistant attributes API calls made with `FAKE_TOKEN` to
+pub const API_USER_ID: &str = "scheduler-token-user";
+
+#[derive(Debug, Clone)]
+pub struct FakeEntity {
+    pub state: String,
+    pub attributes: Map<String, Value>,
+    pub last_changed: DateTime<Utc>,
+    pub user_id: Option<String>,
+    /// Accept service calls but don't change state, like a TRV that misses a command
+    pub ignore_commands: bool,
+    /// Respond to state requests for this entity with this status instead
+    pub state_error: Option<StatusCode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceCall {
+    pub domain: String,
+    pub service: String,
+    pub data: Value,
+}
+
+#[derive(Debug, Default)]
+struct FakeHaState {
+    entities: HashMap<String, FakeEntity>,
+    service_calls: Vec<ServiceCall>,
+    /// How many times each entity's state has been requested
+    state_reads: HashMap<String, usize>,
+    /// Respond to every service call with this status instead
+    service_error: Option<StatusCode>,
+    /// Timestamps follow this clock rather than the system's, to agree with the scheduler's
+    clock: Option<MockClock>,
+}
+
+impl FakeHaState {
+    fn now(&self) -> DateTime<Utc> {
+        self.clock
+            .as_ref()
+            .map_or_else(Utc::now, |clock| clock.now().with_timezone(&Utc))
+    }
+}
+
+/// Handle to a running fake Home Assistant; clones share the same state
+#[derive(Debug, Clone)]
+pub struct FakeHa {
+    pub url: String,
+    state: Arc<Mutex<FakeHaState>>,
+}
+
+impl FakeHa {
+    /// Start the server on a random local port
+    pub async fn start() -> Self {
+        FakeHa::start_with(FakeHaState::default()).await
+    }
+
+    /// Start the server with state changes stamped by `clock`
+    pub async fn start_at(clock: &MockClock) -> Self {
+        FakeHa::start_with(FakeHaState {
+            clock: Some(clock.clone()),
+            ..Default::default()
+        })
+        .await
+    }
+
+    async fn start_with(state: FakeHaState) -> Self {
+        let state = Arc::new(Mutex::new(state));
+        let app = Router::new()
+            .route("/api/states", get(all_states))
+            .route("/api/states/{entity_id}", get(entity_state))
+            .route("/api/services/{domain}/{service}", post(call_service))
+            .with_state(Arc::clone(&state));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        FakeHa { url, state }
+    }
+
+    pub fn api_client(&self) -> ha_heating_scheduler::api_client::ApiClient {
+        ha_heating_scheduler::api_client::ApiClient::new(
+            reqwest::Url::parse(&self.url).unwrap(),
+            FAKE_TOKEN.to_string(),
+        )
+    }
+
+    /// Add or replace any entity
+    pub fn set_entity(&self, entity_id: &str, state: &str, attributes: Value) {
+        let attributes = match attributes {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        let mut fake_state = self.state.lock().unwrap();
+        let last_changed = fake_state.now();
+        fake_state.entities.insert(
+            entity_id.to_string(),
+            FakeEntity {
+                state: state.to_string(),
+                attributes,
+                last_changed,
+                user_id: None,
+                ignore_commands: false,
+                state_error: None,
+            },
+        );
+    }
+
+    /// Add a thermostat in the given hvac mode
+    pub fn add_climate(&self, entity_id: &str, hvac_mode: &str, current_temperature: f64) {
+        self.set_entity(
+            entity_id,
+            hvac_mode,
+            json!({
+                "hvac_modes": ["off", "heat"],
+                "min_temp": 7.0,
+                "max_temp": 35.0,
+                "current_temperature": current_temperature,
+                "temperature": 21.0,
+                "friendly_name": entity_id,
+            }),
+        );
+    }
+
+    /// Change an entity's state as if a person did it in Home Assistant
+    pub fn set_state_by_user(&self, entity_id: &str, state: &str, user_id: &str) {
+        let now = self.state.lock().unwrap().now();
+        self.update(entity_id, |entity| {
+            entity.state = state.to_string();
+            entity.last_changed = now;
+            entity.user_id = Some(user_id.to_string());
+        });
+    }
+
+    pub fn set_attribute(&self, entity_id: &str, name: &str, value: Value) {
+        self.update(entity_id, |entity| {
+            entity.attributes.insert(name.to_string(), value);
+        });
+    }
+
+    pub fn set_ignore_commands(&self, entity_id: &str, ignore: bool) {
+        self.update(entity_id, |entity| entity.ignore_commands = ignore);
+    }
+
+    pub fn set_state_error(&self, entity_id: &str, status: Option<StatusCode>) {
+        self.update(entity_id, |entity| entity.state_error = status);
+    }
+
+    pub fn set_service_error(&self, status: Option<StatusCode>) {
+        self.state.lock().unwrap().service_error = status;
+    }
+
+    pub fn state_of(&self, entity_id: &str) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .entities
+            .get(entity_id)
+            .map(|e| e.state.clone())
+    }
+
+    pub fn service_calls(&self) -> Vec<ServiceCall> {
+        self.state.lock().unwrap().service_calls.clone()
+    }
+
+    /// How many times the entity's state has been requested, which goes up once per pass
+    pub fn state_reads(&self, entity_id: &str) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .state_reads
+            .get(entity_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn update(&self, entity_id: &str, f: impl FnOnce(&mut FakeEntity)) {
+        let mut state = self.state.lock().unwrap();
+        let entity = state
+            .entities
+            .get_mut(entity_id)
+            .unwrap_or_else(|| panic!("Unknown fake entity {}", entity_id));
+        f(entity);
+    }
+}
+
+fn render(entity_id: &str, entity: &FakeEntity) -> Value {
+    let last_changed = entity.last_changed.to_rfc3339();
+    json!({
+        "entity_id": entity_id,
+        "state": entity.state,
+        "attributes": entity.attributes,
+        "last_changed": last_changed,
+        "last_reported": last_changed,
+        "last_updated": last_changed,
+        "context": {
+            "id": "01FAKECONTEXT",
+            "parent_id": null,
+            "user_id": entity.user_id,
+        },
+    })
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v == format!("Bearer {}", FAKE_TOKEN))
+}
+
+fn error(status: StatusCode) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "message": status.to_string() })))
+}
+
+async fn all_states(
+    State(state): State<Arc<Mutex<FakeHaState>>>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED);
+    }
+    let state = state.lock().unwrap();
+    let entities: Vec<Value> = state
+        .entities
+        .iter()
+        .map(|(id, entity)| render(id, entity))
+        .collect();
+    (StatusCode::OK, Json(Value::Array(entities)))
+}
+
+async fn entity_state(
+    State(state): State<Arc<Mutex<FakeHaState>>>,
+    Path(entity_id): Path<String>,
+    headers: HeaderMap,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED);
+    }
+    let mut state = state.lock().unwrap();
+    *state.state_reads.entry(entity_id.clone()).or_default() += 1;
+    match state.entities.get(&entity_id) {
+        Some(entity) => match entity.state_error {
+            Some(status) => error(status),
+            None => (StatusCode::OK, Json(render(&entity_id, entity))),
+        },
+        None => error(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn call_service(
+    State(state): State<Arc<Mutex<FakeHaState>>>,
+    Path((domain, service)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(data): Json<Value>,
+) -> (StatusCode, Json<Value>) {
+    if !authorized(&headers) {
+        return error(StatusCode::UNAUTHORIZED);
+    }
+    let mut state = state.lock().unwrap();
+    state.service_calls.push(ServiceCall {
+        domain: domain.clone(),
+        service: service.clone(),
+        data: data.clone(),
+    });
+    if let Some(status) = state.service_error {
+        return error(status);
+    }
+
+    let entity_id = data["entity_id"].as_str().unwrap_or_default().to_string();
+    let new_state = match (domain.as_str(), service.as_str()) {
+        ("climate", "set_hvac_mode") => data["hvac_mode"].as_str().map(str::to_string),
+        ("switch" | "input_boolean", "turn_on") => Some("on".to_string()),
+        ("switch" | "input_boolean", "turn_off") => Some("off".to_string()),
+        // A real water heater returns to its last operation mode
+        ("water_heater", "turn_on") => Some("electric".to_string()),
+        ("water_heater", "turn_off") => Some("off".to_string()),
+        _ => None,
+    };
+
+    let now = state.now();
+    let mut changed = Vec::new();
+    if let Some(entity) = state.entities.get_mut(&entity_id) {
+        if let Some(new_state) = new_state
+            && !entity.ignore_commands
+            && entity.state != new_state
+        {
+            entity.state = new_state;
+            entity.last_changed = now;
+            entity.user_id = Some(API_USER_ID.to_string());
+        }
+        if domain == "climate" && service == "set_temperature" && !entity.ignore_commands {
+            entity
+                .attributes
+                .insert("temperature".to_string(), data["temperature"].clone());
+        }
+        changed.push(render(&entity_id, entity));
+    }
+    (StatusCode::OK, Json(Value::Array(changed)))
+}
@@ -0,0 +1,187 @@
+#![allow(dead_code)]
+
+pub mod fake_broker;
+pub mod fake_ha;
+
+use chrono::{DateTime, Local, TimeZone};
+use ha_heating_scheduler::api_client::ApiClient;
+use ha_heating_scheduler::climate::{
+    ClimateEntity, ClimateEntityFactory, ClimateEntityWrapper, MockClimate, RunMode, SimulatedRoom,
+    ThermalModel,
+};
+use ha_heating_scheduler::clock::MockClock;
+use ha_heating_scheduler::history::{EventLog, RetentionPolicy};
+use ha_heating_scheduler::schedule::{HeatingState, Schedule, ScheduleEntry, TimePeriod};
+use ha_heating_scheduler::scheduler::SchedulerState;
+use ha_heating_scheduler::scheduler::runtime::OverridePolicy;
+use ha_heating_scheduler::server::AppState;
+use ha_heating_scheduler::stats::EnergySettings;
+use ha_heating_scheduler::timeseries::{SamplingPolicy, TemperatureHistory};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Midnight at the start of the simulated day
+pub fn start_of_day() -> DateTime<Local> {
+    Local.with_ymd_and_hms(2025, 1, 15, 0, 0, 0).unwrap()
+}
+
+pub fn at(hour: u32, minute: u32) -> DateTime<Local> {
+    Local
+        .with_ymd_and_hms(2025, 1, 15, hour, minute, 0)
+        .unwrap()
+}
+
+/// Heating on 06:00-09:00 and 17:00-22:00, off otherwise
+pub fn work_day_schedule() -> Schedule {
+    let mut schedule = Schedule::new("Work Day");
+    schedule.add_entry(ScheduleEntry::new(
+        "Morning",
+        TimePeriod::new(6, 0, 9, 0),
+        HeatingState::On,
+    ));
+    schedule.add_entry(ScheduleEntry::new(
+        "Evening",
+        TimePeriod::new(17, 0, 22, 0),
+        HeatingState::On,
+    ));
+    schedule
+}
+
+/// Scheduler state backed by a mock clock, with files written to `data_dir`
+pub fn scheduler_state<T: ClimateEntity + Clone>(
+    api_client: ApiClient,
+    schedule: Schedule,
+    entities: Vec<T>,
+    clock: &MockClock,
+    data_dir: &Path,
+) -> SchedulerState<T> {
+    SchedulerState {
+        api_client,
+        schedule: Arc::new(RwLock::new(schedule)),
+        climate_entities: Arc::new(RwLock::new(entities)),
+        hot_water_schedule: Arc::new(RwLock::new(Schedule::new("Hot Water"))),
+        hot_water_entities: Arc::new(RwLock::new(Vec::new())),
+        history: Arc::new(RwLock::new(EventLog::new(RetentionPolicy::default()))),
+        history_file_path: data_dir.join("history.json").to_string_lossy().to_string(),
+        temperature_history: Arc::new(RwLock::new(TemperatureHistory::new(
+            SamplingPolicy::default(),
+        ))),
+        temperature_history_file_path: data_dir
+            .join("temperature_history.json")
+            .to_string_lossy()
+            .to_string(),
+        clock: Arc::new(clock.clone()),
+        tick_interval: std::time::Duration::from_millis(50),
+        verify_delay: std::time::Duration::ZERO,
+        runtime: Arc::new(RwLock::new(Default::default())),
+        override_policy: OverridePolicy::default(),
+        entity_settings: Arc::new(RwLock::new(Default::default())),
+        frost_protection_temperature: None,
+        optimum_start_max_lead: chrono::Duration::hours(2),
+        presence_settings: None,
+        presence: Arc::new(RwLock::new(Default::default())),
+        weather: Arc::new(RwLock::new(None)),
+        tariff_sensor: None,
+        tariff: Arc::new(RwLock::new(Default::default())),
+        notifications: None,
+    }
+}
+
+/// A client for entities that never reach Home Assistant
+pub fn offline_api_client() -> ApiClient {
+    ApiClient::new(
+        reqwest::Url::parse("http://fake").unwrap(),
+        "fake_token".to_string(),
+    )
+}
+
+/// A mock entity whose simulated room follows the mock clock, starting off at 18°C
+pub fn mock_climate(entity_id: &str, clock: &MockClock) -> MockClimate {
+    MockClimate::with_simulation(
+        entity_id.to_string(),
+        SimulatedRoom::new(ThermalModel::default(), HeatingState::Off, 18.0),
+        Arc::new(clock.clone()),
+    )
+}
+
+/// API state sharing the scheduler's schedule, entities and journals
+pub fn app_state(
+    state: &SchedulerState<ClimateEntityWrapper>,
+    clock: &MockClock,
+    data_dir: &Path,
+) -> AppState<ClimateEntityWrapper> {
+    AppState {
+        schedule: Arc::clone(&state.schedule),
+        schedule_file_path: data_dir.join("schedule.json").to_string_lossy().to_string(),
+        climate_entities: Arc::clone(&state.climate_entities),
+        entities_file_path: data_dir.join("entities.json").to_string_lossy().to_string(),
+        hot_water_schedule: Arc::clone(&state.hot_water_schedule),
+        hot_water_schedule_file_path: data_dir
+            .join("hot_water_schedule.json")
+            .to_string_lossy()
+            .to_string(),
+        hot_water_entities: Arc::clone(&state.hot_water_entities),
+        history: Arc::clone(&state.history),
+        temperature_history: Arc::clone(&state.temperature_history),
+        entity_settings: Arc::clone(&state.entity_settings),
+        energy: EnergySettings::default(),
+        runtime: Arc::clone(&state.runtime),
+        presence: Arc::clone(&state.presence),
+        weather: Arc::clone(&state.weather),
+        tariff: Arc::clone(&state.tariff),
+        entity_factory: ClimateEntityFactory::new(
+            RunMode::Live,
+            ThermalModel::default(),
+            Arc::new(clock.clone()),
+        ),
+        clock: Arc::new(clock.clone()),
+    }
+}
+
+/// A local address nothing is listening on yet
+pub fn free_bind_address() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Poll until `condition` holds, failing the test after a few seconds
+pub async fn wait_for(description: &str, condition: impl Fn() -> bool) {
+    for _ in 0..200 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    panic!("Timed out waiting for {}", description);
+}
+
+/// GET `url` until `condition` holds for the JSON it returns, failing the test after a few
+/// seconds. Returns the JSON that satisfied it.
+pub async fn wait_for_json(
+    description: &str,
+    url: &str,
+    condition: impl Fn(&Value) -> bool,
+) -> Value {
+    let client = reqwest::Client::new();
+    for _ in 0..200 {
+        if let Ok(response) = client.get(url).send().await
+            && let Ok(json) = response.json::<Value>().await
+            && condition(&json)
+        {
+            return json;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    panic!("Timed out waiting for {}", description);
+}
+
+/// Wait until a whole scheduler pass has run since the call. A pass reads `entity_id` once, and
+/// again if it commands it, so four more reads means one started and finished in between.
+pub async fn wait_for_whole_pass(fake: &fake_ha::FakeHa, entity_id: &str) {
+    let reads = fake.state_reads(entity_id);
+    wait_for("a whole scheduler pass", || {
+        fake.state_reads(entity_id) >= reads + 4
+    })
+    .await;
+}
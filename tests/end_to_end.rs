@@ -0,0 +1,1587 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::fake_broker::FakeBroker;
+use common::fake_ha::{API_USER_ID, FakeHa};
+use common::{
+    app_state, at, free_bind_address, scheduler_state, wait_for, wait_for_json,
+    wait_for_whole_pass, work_day_schedule,
+};
+use ha_heating_scheduler::climate::{ClimateEntityWrapper, DefaultClimate};
+use ha_heating_scheduler::clock::MockClock;
+use ha_heating_scheduler::config::entities_persistence::{ControlMode, EntitySettings};
+use ha_heating_scheduler::mqtt::discovery::DiscoverySettings;
+use ha_heating_scheduler::mqtt::run_mqtt_bridge;
+use ha_heating_scheduler::notify::{NotificationRule, NotificationSettings, Trigger};
+use ha_heating_scheduler::presence::{AwayMode, PresenceSettings};
+use ha_heating_scheduler::scheduler::{SchedulerState, run_scheduler};
+use ha_heating_scheduler::server::start_server;
+use serde_json::{Value, json};
+use tempfile::tempdir;
+
+fn real(entity_id: &str) -> ClimateEntityWrapper {
+    ClimateEntityWrapper::Real(DefaultClimate::new(entity_id.to_string()))
+}
+
+/// Fake Home Assistant with two thermostats, plus the scheduler and API running against it
+async fn start(clock: &MockClock, data_dir: &std::path::Path) -> (FakeHa, String) {
+    let fake = fake_ha(clock).await;
+    let api = start_scheduler(&fake, clock, data_dir).await;
+    (fake, api)
+}
+
+async fn fake_ha(clock: &MockClock) -> FakeHa {
+    let fake = FakeHa::start_at(clock).await;
+    fake.add_climate("climate.bedroom", "off", 18.0);
+    fake.add_climate("climate.living_room", "off", 19.0);
+    fake
+}
+
+/// Run the scheduler and API against the fake, returning the API's base URL
+async fn start_scheduler(fake: &FakeHa, clock: &MockClock, data_dir: &std::path::Path) -> String {
+    start_scheduler_with(fake, clock, data_dir, |_| {}).await
+}
+
+/// Like `start_scheduler`, with a chance to adjust the scheduler's settings first
+async fn start_scheduler_with(
+    fake: &FakeHa,
+    clock: &MockClock,
+    data_dir: &std::path::Path,
+    configure: impl FnOnce(&mut SchedulerState<ClimateEntityWrapper>),
+) -> String {
+    let mut state = scheduler_state(
+        fake.api_client(),
+        work_day_schedule(),
+        vec![real("climate.bedroom"), real("climate.living_room")],
+        clock,
+        data_dir,
+    );
+    configure(&mut state);
+    let bind_address = free_bind_address();
+    tokio::spawn(start_server(
+        app_state(&state, clock, data_dir),
+        bind_address.clone(),
+    ));
+    tokio::spawn(run_scheduler(state));
+
+    for _ in 0..200 {
+        if tokio::net::TcpStream::connect(&bind_address).await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    format!("http://{}", bind_address)
+}
+
+#[tokio::test]
+async fn test_scheduler_drives_fake_home_assistant() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    let client = reqwest::Client::new();
+
+    // 07:00 is inside the morning On period
+    wait_for("both thermostats to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+            && fake.state_of("climate.living_room").as_deref() == Some("heat")
+    })
+    .await;
+
+    let status: Value = client
+        .get(format!("{}/status", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["active_entry"]["name"], "Morning");
+    assert_eq!(status["next_transition"]["heating_state"], "OFF");
+    assert_eq!(status["entities"][0]["effective_state"], "ON");
+
+    // Adding an Off entry over the current time through the API turns everything off
+    let response = client
+        .post(format!("{}/schedule", api))
+        .json(&json!({
+            "name": "Lie in",
+            "time_period": { "start": "06:30:00", "end": "07:30:00" },
+            "heating_state": "OFF",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    wait_for("both thermostats to turn off", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+            && fake.state_of("climate.living_room").as_deref() == Some("off")
+    })
+    .await;
+
+    // Decisions are journaled once the pass has checked its commands
+    let is_decision = |e: &&Value| e["type"] == "scheduler_decision";
+    let history = wait_for_json(
+        "both decisions to be journaled",
+        &format!("{}/history?entity_id=climate.bedroom", api),
+        |history| {
+            history
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(is_decision)
+                .count()
+                == 2
+        },
+    )
+    .await;
+    let decisions: Vec<&Value> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(is_decision)
+        .collect();
+    assert_eq!(decisions[0]["desired_state"], "ON");
+    assert_eq!(decisions[1]["desired_state"], "OFF");
+    assert_eq!(decisions[1]["active_entry"]["name"], "Lie in");
+}
+
+#[tokio::test]
+async fn test_boost_through_api_and_fetch_errors_are_journaled() {
+    let dir = tempdir().unwrap();
+    // 12:00 is outside every On period
+    let clock = MockClock::new(at(12, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    let client = reqwest::Client::new();
+    fake.set_state_error(
+        "climate.living_room",
+        Some(StatusCode::INTERNAL_SERVER_ERROR),
+    );
+
+    let response = client
+        .post(format!("{}/boost", api))
+        .json(&json!({ "climate_names": ["climate.bedroom"], "time_length": 30 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    wait_for("the boosted thermostat to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+
+    let history = wait_for_json(
+        "the fetch error to be journaled",
+        &format!("{}/history", api),
+        |history| {
+            history
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|e| e["type"] == "error" && e["entity_id"] == "climate.living_room")
+        },
+    )
+    .await;
+    assert!(
+        history
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e["type"] == "boost" && e["entity_id"] == "climate.bedroom")
+    );
+    assert_eq!(fake.state_of("climate.living_room").as_deref(), Some("off"));
+
+    // The outage is journaled once, however many passes it lasts, and so is its end
+    wait_for_whole_pass(&fake, "climate.living_room").await;
+    fake.set_state_error("climate.living_room", None);
+    let history = wait_for_json(
+        "the living room to be reachable",
+        &format!("{}/history?entity_id=climate.living_room", api),
+        |history| history.as_array().unwrap().len() == 2,
+    )
+    .await;
+    let kinds: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["error", "reachable"]);
+}
+
+#[tokio::test]
+async fn test_unavailable_entity_is_skipped_and_journaled_once() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_entity(
+        "climate.living_room",
+        "unavailable",
+        json!({ "friendly_name": "Living room" }),
+    );
+    let api = start_scheduler(&fake, &clock, dir.path()).await;
+
+    wait_for("the available thermostat to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    // Give the scheduler more passes over the unavailable entity
+    wait_for_whole_pass(&fake, "climate.living_room").await;
+
+    assert!(
+        fake.service_calls()
+            .iter()
+            .all(|call| call.data["entity_id"] == "climate.bedroom")
+    );
+
+    let client = reqwest::Client::new();
+    let history: Vec<Value> = client
+        .get(format!("{}/history?entity_id=climate.living_room", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["type"], "error");
+    assert_eq!(history[0]["message"], "entity is unavailable");
+
+    let entities: Vec<Value> = client
+        .get(format!("{}/entities", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let living_room = entities
+        .iter()
+        .find(|e| e["entity_id"] == "climate.living_room")
+        .unwrap();
+    assert_eq!(living_room["availability"], "unavailable");
+    assert_eq!(living_room["hvac_mode"], "unavailable");
+    assert_eq!(living_room["current_temperature"], Value::Null);
+
+    fake.add_climate("climate.living_room", "heat", 19.0);
+    let history = wait_for_json(
+        "the living room to come back",
+        &format!("{}/history?entity_id=climate.living_room", api),
+        |history| history.as_array().unwrap().len() > 1,
+    )
+    .await;
+    assert_eq!(history[1]["type"], "reachable");
+}
+
+#[tokio::test]
+async fn test_ignored_commands_back_off_degrade_and_recover() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_ignore_commands("climate.bedroom", true);
+    let api = start_scheduler(&fake, &clock, dir.path()).await;
+    let client = reqwest::Client::new();
+    let bedroom_calls = || {
+        fake.service_calls()
+            .iter()
+            .filter(|call| call.data["entity_id"] == "climate.bedroom")
+            .count()
+    };
+
+    wait_for("the first command", || bedroom_calls() == 1).await;
+    // The clock hasn't moved, so the scheduler keeps backing off
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    assert_eq!(bedroom_calls(), 1);
+
+    clock.advance(chrono::Duration::seconds(30));
+    wait_for("the first retry", || bedroom_calls() == 2).await;
+    clock.advance(chrono::Duration::seconds(60));
+    wait_for("the second retry", || bedroom_calls() == 3).await;
+
+    let get_bedroom = || async {
+        let entities: Vec<Value> = client
+            .get(format!("{}/entities", api))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        entities
+            .into_iter()
+            .find(|e| e["entity_id"] == "climate.bedroom")
+            .unwrap()
+    };
+    wait_for_degraded(&get_bedroom, true).await;
+    let bedroom = get_bedroom().await;
+    assert!(
+        bedroom["last_command_error"]
+            .as_str()
+            .unwrap()
+            .contains("Off")
+    );
+
+    // The device starts listening again and the next retry gets through
+    fake.set_ignore_commands("climate.bedroom", false);
+    clock.advance(chrono::Duration::seconds(120));
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    wait_for_degraded(&get_bedroom, false).await;
+
+    let history = wait_for_json(
+        "the recovery to be journaled",
+        &format!("{}/history?entity_id=climate.bedroom", api),
+        |history| history.as_array().unwrap().len() == 6,
+    )
+    .await;
+    let history = history.as_array().unwrap();
+    let kinds: Vec<&str> = history
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "error",
+            "error",
+            "error",
+            "degraded",
+            "recovered",
+            "scheduler_decision"
+        ]
+    );
+    assert_eq!(history[3]["failed_attempts"], 3);
+    assert_eq!(history[3]["desired_state"], "ON");
+}
+
+/// Poll `/entities` until the bedroom's degraded flag matches
+async fn wait_for_degraded<F, Fut>(get_bedroom: &F, degraded: bool)
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Value>,
+{
+    for _ in 0..200 {
+        if get_bedroom().await["degraded"] == degraded {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+    }
+    panic!("Timed out waiting for degraded = {}", degraded);
+}
+
+#[tokio::test]
+async fn test_added_switches_and_water_heaters_follow_the_schedule() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    fake.set_entity("switch.underfloor_relay", "off", json!({}));
+    fake.set_entity(
+        "water_heater.immersion",
+        "off",
+        json!({ "current_temperature": 40.0 }),
+    );
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/entities", api))
+        .json(&json!({ "entity_ids": ["light.kitchen"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(format!("{}/entities", api))
+        .json(&json!({ "entity_ids": ["switch.underfloor_relay", "water_heater.immersion"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    wait_for("the relay and the immersion to turn on", || {
+        fake.state_of("switch.underfloor_relay").as_deref() == Some("on")
+            && fake.state_of("water_heater.immersion").as_deref() == Some("electric")
+    })
+    .await;
+    let services: Vec<String> = fake
+        .service_calls()
+        .into_iter()
+        .filter(|call| call.data["entity_id"] != "climate.bedroom")
+        .filter(|call| call.data["entity_id"] != "climate.living_room")
+        .map(|call| format!("{}.{}", call.domain, call.service))
+        .collect();
+    assert_eq!(services, ["switch.turn_on", "water_heater.turn_on"]);
+
+    let immersion = |entities: &Value| {
+        entities
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["entity_id"] == "water_heater.immersion")
+            .cloned()
+            .unwrap()
+    };
+    let entities = wait_for_json(
+        "the immersion's new state",
+        &format!("{}/entities", api),
+        |entities| immersion(entities)["hvac_mode"] == "heat",
+    )
+    .await;
+    let immersion = immersion(&entities);
+    assert_eq!(immersion["current_temperature"], 40.0);
+}
+
+#[tokio::test]
+async fn test_hot_water_follows_its_own_schedule_and_boost() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    fake.set_entity(
+        "water_heater.tank",
+        "off",
+        json!({ "current_temperature": 45.0 }),
+    );
+    let client = reqwest::Client::new();
+
+    // A thermostat can't be on both channels
+    let response = client
+        .post(format!("{}/hot_water/entities", api))
+        .json(&json!({ "entity_ids": ["climate.bedroom"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(format!("{}/hot_water/entities", api))
+        .json(&json!({ "entity_ids": ["water_heater.tank"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The heating's morning period leaves the tank alone: its own schedule is empty
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    wait_for_whole_pass(&fake, "water_heater.tank").await;
+    assert_eq!(fake.state_of("water_heater.tank").as_deref(), Some("off"));
+
+    let boosted: Vec<String> = client
+        .post(format!("{}/hot_water/boost", api))
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(boosted, ["water_heater.tank"]);
+    wait_for("the tank to heat", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("electric")
+    })
+    .await;
+
+    let hot_water = wait_for_json(
+        "the tank's new state",
+        &format!("{}/hot_water", api),
+        |hot_water| hot_water["entities"][0]["current_state"] == "ON",
+    )
+    .await;
+    let tank = &hot_water["entities"][0];
+    assert_eq!(tank["effective_state"], "ON");
+    assert_eq!(tank["boost"]["boost_end"], json!(at(8, 0)));
+    assert_eq!(tank["current_temperature"], 45.0);
+
+    // Cancelling the boost turns it off again, then an entry of its own turns it back on
+    let response = client
+        .post(format!("{}/hot_water/cancel_boost", api))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the tank to turn off", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("off")
+    })
+    .await;
+
+    let response = client
+        .post(format!("{}/hot_water/schedule", api))
+        .json(&json!({
+            "name": "Morning showers",
+            "time_period": { "start": "06:30:00", "end": "07:30:00" },
+            "heating_state": "ON",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the tank to heat on schedule", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("electric")
+    })
+    .await;
+
+    let status: Value = client
+        .get(format!("{}/status", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        status["hot_water"]["active_entry"]["name"],
+        "Morning showers"
+    );
+    assert_eq!(
+        status["hot_water"]["next_transition"]["heating_state"],
+        "OFF"
+    );
+    assert_eq!(status["active_entry"]["name"], "Morning");
+    let heating: Vec<&Value> = status["entities"].as_array().unwrap().iter().collect();
+    assert!(
+        heating
+            .iter()
+            .all(|e| e["entity_id"] != "water_heater.tank")
+    );
+
+    // The heating schedule is untouched
+    let schedule: Value = client
+        .get(format!("{}/schedule", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(
+        schedule["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|entry| entry["name"] != "Morning showers")
+    );
+
+    // Paused, the tank is left off in its own period rather than switched back on
+    let paused: Vec<String> = client
+        .put(format!("{}/pause", api))
+        .json(&json!({ "entity_ids": ["water_heater.tank"], "paused": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(paused, ["water_heater.tank"]);
+    fake.set_entity(
+        "water_heater.tank",
+        "off",
+        json!({ "current_temperature": 45.0 }),
+    );
+    wait_for_whole_pass(&fake, "water_heater.tank").await;
+    assert_eq!(fake.state_of("water_heater.tank").as_deref(), Some("off"));
+    let hot_water: Value = client
+        .get(format!("{}/hot_water", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(hot_water["entities"][0]["paused_since"].is_string());
+
+    // Resumed, it's switched on for its own period, but switched off by hand it's kept off as a
+    // manual override
+    let response = client
+        .put(format!("{}/pause", api))
+        .json(&json!({ "entity_ids": ["water_heater.tank"], "paused": false }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the tank to heat again", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("electric")
+    })
+    .await;
+    wait_for_whole_pass(&fake, "water_heater.tank").await;
+    fake.set_state_by_user("water_heater.tank", "off", "someone");
+    wait_for_json("the override", &format!("{}/hot_water", api), |hot_water| {
+        hot_water["entities"][0]["manual_override"]["state"] == "OFF"
+    })
+    .await;
+    wait_for_whole_pass(&fake, "water_heater.tank").await;
+    assert_eq!(fake.state_of("water_heater.tank").as_deref(), Some("off"));
+}
+
+#[tokio::test]
+async fn test_manual_change_is_honoured_until_cleared_or_next_transition() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    let client = reqwest::Client::new();
+
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    // The clock stands still, so a change before the command is checked would look like the
+    // command failing
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+
+    // Someone turns the bedroom off in Home Assistant during the morning On period
+    fake.set_state_by_user("climate.bedroom", "off", "someone");
+    let status = wait_for_json("the override", &format!("{}/status", api), |status| {
+        !status["entities"][0]["manual_override"].is_null()
+    })
+    .await;
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+    let bedroom = &status["entities"][0];
+    assert_eq!(bedroom["effective_state"], "OFF");
+    assert_eq!(bedroom["manual_override"]["state"], "OFF");
+    assert_eq!(bedroom["manual_override"]["user_id"], "someone");
+    assert_eq!(
+        bedroom["manual_override"]["until"],
+        serde_json::to_value(at(9, 0)).unwrap()
+    );
+
+    // Clearing it through the API hands the bedroom back to the schedule
+    let response = client
+        .delete(format!("{}/entities/climate.bedroom/override", api))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the bedroom to heat again", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    let response = client
+        .delete(format!("{}/entities/climate.bedroom/override", api))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // A second change lasts until the schedule's next transition at 09:00
+    fake.set_state_by_user("climate.bedroom", "off", "someone");
+    wait_for_json(
+        "the second override",
+        &format!("{}/status", api),
+        |status| !status["entities"][0]["manual_override"].is_null(),
+    )
+    .await;
+    clock.advance(chrono::Duration::hours(2));
+
+    let history = wait_for_json(
+        "the second override to end",
+        &format!("{}/history?entity_id=climate.bedroom", api),
+        |history| history.as_array().unwrap().len() == 8,
+    )
+    .await;
+    let kinds: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "scheduler_decision",
+            "manual_change",
+            "manual_override",
+            "manual_override_ended",
+            "scheduler_decision",
+            "manual_change",
+            "manual_override",
+            "manual_override_ended",
+        ]
+    );
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+}
+
+#[tokio::test]
+async fn test_manual_change_as_the_schedulers_own_user_is_honoured() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    // The clock stands still, so a change before the command is checked would look like the
+    // command failing
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+
+    // The token belongs to the household's own account, which someone also uses in the app
+    fake.set_state_by_user("climate.bedroom", "off", API_USER_ID);
+    let status = wait_for_json("the override", &format!("{}/status", api), |status| {
+        !status["entities"][0]["manual_override"].is_null()
+    })
+    .await;
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+    let bedroom = &status["entities"][0];
+    assert_eq!(bedroom["manual_override"]["state"], "OFF");
+    assert_eq!(bedroom["manual_override"]["user_id"], API_USER_ID);
+}
+
+#[tokio::test]
+async fn test_command_followed_late_is_not_a_manual_change() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_ignore_commands("climate.bedroom", true);
+    let api = start_scheduler(&fake, &clock, dir.path()).await;
+    let history_url = format!("{}/history?entity_id=climate.bedroom", api);
+    wait_for_json(
+        "the missed command to be journaled",
+        &history_url,
+        |history| {
+            history
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|e| e["type"] == "error")
+        },
+    )
+    .await;
+
+    // The thermostat gets round to the missed command after the check
+    fake.set_state_by_user("climate.bedroom", "heat", API_USER_ID);
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+
+    let history = wait_for_json("the journal", &history_url, |_| true).await;
+    let kinds: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["error"]);
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("heat"));
+}
+
+#[tokio::test]
+async fn test_window_sensor_pauses_heating() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let (fake, api) = start(&clock, dir.path()).await;
+    let client = reqwest::Client::new();
+    fake.set_entity("binary_sensor.bedroom_window", "off", json!({}));
+
+    let response = client
+        .put(format!("{}/entities/climate.bedroom/settings", api))
+        .json(&json!({ "open_window": { "sensor": "binary_sensor.bedroom_window" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+
+    fake.set_entity("binary_sensor.bedroom_window", "on", json!({}));
+    wait_for("the bedroom to pause", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+    })
+    .await;
+    let status: Value = client
+        .get(format!("{}/status", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["entities"][0]["open_window"]["source"], "sensor");
+    assert_eq!(status["entities"][0]["effective_state"], "OFF");
+    // The living room has no sensor and keeps heating
+    assert_eq!(
+        fake.state_of("climate.living_room").as_deref(),
+        Some("heat")
+    );
+
+    fake.set_entity("binary_sensor.bedroom_window", "off", json!({}));
+    wait_for("the bedroom to heat again", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+
+    let history = wait_for_json(
+        "the last decision to be journaled",
+        &format!("{}/history?entity_id=climate.bedroom", api),
+        |history| history.as_array().unwrap().len() == 5,
+    )
+    .await;
+    let kinds: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "scheduler_decision",
+            "window_open",
+            "scheduler_decision",
+            "window_closed",
+            "scheduler_decision"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_room_sensor_replaces_the_thermostat_reading() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    // The radiator warms the TRV's own sensor well above the room
+    fake.set_attribute("climate.bedroom", "current_temperature", json!(23.0));
+    fake.set_entity("sensor.bedroom_temperature", "18.5", json!({}));
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        state.entity_settings.write().unwrap().insert(
+            "climate.bedroom".to_string(),
+            EntitySettings {
+                control_mode: ControlMode::Thermostat,
+                target_temperature: Some(20.0),
+                temperature_sensor: Some("sensor.bedroom_temperature".to_string()),
+                ..Default::default()
+            },
+        );
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    wait_for("the bedroom to heat to the room's reading", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    let bedroom_reading = |temperature: f64| {
+        move |entities: &Value| {
+            entities.as_array().unwrap().iter().any(|e| {
+                e["entity_id"] == "climate.bedroom" && e["current_temperature"] == temperature
+            })
+        }
+    };
+    wait_for_json(
+        "the room's reading",
+        &format!("{}/entities", api),
+        bedroom_reading(18.5),
+    )
+    .await;
+
+    fake.set_entity("sensor.bedroom_temperature", "21.0", json!({}));
+    wait_for("the bedroom to stop once the room is warm", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+    })
+    .await;
+
+    // A sensor that can't be read leaves the thermostat's own reading in place
+    fake.set_entity("sensor.bedroom_temperature", "unavailable", json!({}));
+    wait_for_json(
+        "the thermostat's reading",
+        &format!("{}/entities", api),
+        bedroom_reading(23.0),
+    )
+    .await;
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+
+    let response = client
+        .put(format!("{}/entities/climate.bedroom/settings", api))
+        .json(&json!({ "temperature_sensor": "binary_sensor.bedroom_window" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_heating_goes_off_while_everyone_is_out() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_entity("person.alex", "home", json!({}));
+    fake.set_entity("device_tracker.sam_phone", "not_home", json!({}));
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        state.presence_settings = Some(PresenceSettings {
+            trackers: vec![
+                "person.alex".to_string(),
+                "device_tracker.sam_phone".to_string(),
+            ],
+            away_mode: AwayMode::Off,
+            away_delay: chrono::Duration::zero(),
+        });
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+
+    fake.set_entity("person.alex", "not_home", json!({}));
+    wait_for("both thermostats to go off", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+            && fake.state_of("climate.living_room").as_deref() == Some("off")
+    })
+    .await;
+    let status: Value = client
+        .get(format!("{}/status", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["presence"]["occupancy"], "away");
+    assert_eq!(status["presence"]["trackers"]["person.alex"], "not_home");
+    assert_eq!(status["presence"]["away_mode"]["mode"], "off");
+    assert_eq!(status["scheduled_state"], "ON");
+    assert_eq!(status["entities"][0]["effective_state"], "OFF");
+
+    fake.set_entity("person.alex", "home", json!({}));
+    wait_for("the schedule to resume", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+            && fake.state_of("climate.living_room").as_deref() == Some("heat")
+    })
+    .await;
+
+    let presence = |history: &Value| -> Vec<String> {
+        history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["type"].as_str().unwrap().to_string())
+            .filter(|kind| kind == "away" || kind == "home")
+            .collect()
+    };
+    let history = wait_for_json(
+        "the return to be journaled",
+        &format!("{}/history", api),
+        |history| presence(history).len() == 2,
+    )
+    .await;
+    assert_eq!(presence(&history), ["away", "home"]);
+}
+
+#[tokio::test]
+async fn test_notifications_are_sent_through_notify_services() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(12, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_attribute("climate.living_room", "current_temperature", json!(4.0));
+    let rule = |min_interval_minutes| NotificationRule {
+        service: None,
+        min_interval_minutes,
+        after_ticks: None,
+    };
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        state.frost_protection_temperature = Some(5.0);
+        state.notifications = Some(NotificationSettings {
+            service: "notify.mobile_app_phone".to_string(),
+            rules: [
+                (
+                    Trigger::FrostProtection,
+                    NotificationRule {
+                        service: Some("notify.everyone".to_string()),
+                        ..rule(60)
+                    },
+                ),
+                (Trigger::BoostEnded, rule(60)),
+                (
+                    Trigger::Unreachable,
+                    NotificationRule {
+                        after_ticks: Some(2),
+                        ..rule(60)
+                    },
+                ),
+            ]
+            .into(),
+        });
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let notifications = || {
+        fake.service_calls()
+            .into_iter()
+            .filter(|call| call.domain == "notify")
+            .map(|call| {
+                (
+                    call.service,
+                    call.data["message"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    wait_for("the frost notification", || notifications().len() == 1).await;
+    assert_eq!(
+        notifications()[0],
+        (
+            "everyone".to_string(),
+            "climate.living_room is at 4.0°C, below 5.0°C; heating is forced on".to_string()
+        )
+    );
+    let call = fake
+        .service_calls()
+        .into_iter()
+        .find(|c| c.domain == "notify")
+        .unwrap();
+    assert_eq!(call.data["title"], "Heating");
+
+    // The second boost ending within the hour is held back
+    for _ in 0..2 {
+        let response = client
+            .post(format!("{}/boost", api))
+            .json(&json!({ "climate_names": ["climate.bedroom"], "time_length": 30 }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        wait_for("the boosted thermostat to heat", || {
+            fake.state_of("climate.bedroom").as_deref() == Some("heat")
+        })
+        .await;
+        clock.advance(chrono::Duration::minutes(31));
+        wait_for("the boost to end", || {
+            fake.state_of("climate.bedroom").as_deref() == Some("off")
+        })
+        .await;
+    }
+    wait_for("the boost notification", || notifications().len() == 2).await;
+    assert_eq!(
+        notifications()[1],
+        (
+            "mobile_app_phone".to_string(),
+            "climate.bedroom boost finished".to_string()
+        )
+    );
+
+    // Once per outage, however long it lasts
+    fake.set_state_error("climate.bedroom", Some(StatusCode::INTERNAL_SERVER_ERROR));
+    wait_for("the unreachable notification", || {
+        notifications().len() == 3
+    })
+    .await;
+    wait_for_whole_pass(&fake, "climate.bedroom").await;
+    let notifications = notifications();
+    assert_eq!(notifications.len(), 3);
+    assert_eq!(
+        notifications[2].1,
+        "climate.bedroom has been unreachable for 2 passes"
+    );
+}
+
+#[tokio::test]
+async fn test_events_from_api_calls_are_notified() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(12, 0));
+    let fake = fake_ha(&clock).await;
+    let rule = NotificationRule {
+        service: None,
+        min_interval_minutes: 60,
+        after_ticks: None,
+    };
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        state.notifications = Some(NotificationSettings {
+            service: "notify.mobile_app_phone".to_string(),
+            rules: [
+                (Trigger::BoostEnded, rule.clone()),
+                (Trigger::Away, rule.clone()),
+                (Trigger::Home, rule),
+            ]
+            .into(),
+        });
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let notifications = || {
+        fake.service_calls()
+            .into_iter()
+            .filter(|call| call.domain == "notify")
+            .map(|call| call.data["message"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let response = client
+        .post(format!("{}/boost", api))
+        .json(&json!({ "climate_names": ["climate.bedroom"], "time_length": 30 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .post(format!("{}/cancel_boost", api))
+        .json(&json!({ "entity_ids": ["climate.bedroom"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the cancelled boost notification", || {
+        notifications().len() == 1
+    })
+    .await;
+    assert_eq!(notifications()[0], "climate.bedroom boost finished");
+
+    for away in [true, false] {
+        let response = client
+            .put(format!("{}/away", api))
+            .json(&json!({ "away": away }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    wait_for("the away and home notifications", || {
+        notifications().len() == 3
+    })
+    .await;
+    assert_eq!(
+        notifications()[1..],
+        [
+            "Everyone is out, heating is Off".to_string(),
+            "Someone is home, the schedule applies again".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_warm_weather_skips_on_periods() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_entity("weather.home", "sunny", json!({ "temperature": 18.0 }));
+    let api = start_scheduler(&fake, &clock, dir.path()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .put(format!("{}/schedule/weather_compensation", api))
+        .json(&json!({ "sensor": "light.porch", "warm_cutoff": 16.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = client
+        .put(format!("{}/schedule/weather_compensation", api))
+        .json(&json!({ "sensor": "weather.home", "warm_cutoff": 16.0 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let schedule: Value = response.json().await.unwrap();
+    assert_eq!(schedule["weather_compensation"]["warm_cutoff"], 16.0);
+
+    // The morning period is On, but it's mild outside
+    let get_status = || async {
+        let status: Value = client
+            .get(format!("{}/status", api))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        status
+    };
+    for _ in 0..200 {
+        if get_status().await["weather"]["warm_weather"] == true {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let status = get_status().await;
+    assert_eq!(status["weather"]["temperature"], 18.0);
+    assert_eq!(status["scheduled_state"], "ON");
+    assert_eq!(status["entities"][0]["effective_state"], "OFF");
+    wait_for("both thermostats to stay off", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+            && fake.state_of("climate.living_room").as_deref() == Some("off")
+    })
+    .await;
+
+    fake.set_entity("weather.home", "cloudy", json!({ "temperature": 9.0 }));
+    wait_for("the morning heating to start", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+            && fake.state_of("climate.living_room").as_deref() == Some("heat")
+    })
+    .await;
+
+    let weather = |history: &Value| -> Vec<String> {
+        history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["type"].as_str().unwrap().to_string())
+            .filter(|kind| kind.starts_with("warm_weather"))
+            .collect()
+    };
+    let history = wait_for_json(
+        "the cold to be journaled",
+        &format!("{}/history", api),
+        |history| weather(history).len() == 2,
+    )
+    .await;
+    assert_eq!(weather(&history), ["warm_weather", "warm_weather_ended"]);
+}
+
+#[tokio::test]
+async fn test_flexible_entry_follows_tariff_sensor() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(14, 0));
+    let fake = fake_ha(&clock).await;
+    let rate = |from, to, price| {
+        json!({
+            "start": at(from, 0).to_rfc3339(),
+            "end": at(to, 0).to_rfc3339(),
+            "value_inc_vat": price
+        })
+    };
+    fake.set_entity(
+        "sensor.electricity_rates",
+        "0.30",
+        json!({ "rates": [rate(14, 17, 0.30), rate(17, 18, 0.09), rate(18, 20, 0.30)] }),
+    );
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        state.tariff_sensor = Some("sensor.electricity_rates".to_string());
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let entry = |duration_minutes| {
+        json!({
+            "name": "Afternoon",
+            "time_period": { "start": "14:00:00", "end": "20:00:00" },
+            "heating_state": "ON",
+            "flexible": { "duration_minutes": duration_minutes, "contiguous": true }
+        })
+    };
+    let response = client
+        .post(format!("{}/schedule", api))
+        .json(&entry(400))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = client
+        .post(format!("{}/schedule", api))
+        .json(&entry(60))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let get_status = || async {
+        let status: Value = client
+            .get(format!("{}/status", api))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        status
+    };
+    for _ in 0..200 {
+        if !get_status().await["flexible_plan"].is_null() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let status = get_status().await;
+    let slots = status["flexible_plan"]["slots"].as_array().unwrap();
+    assert_eq!(slots.len(), 1);
+    assert_eq!(slots[0]["start"], json!(at(17, 0)));
+    assert_eq!(slots[0]["end"], json!(at(18, 0)));
+    assert_eq!(status["scheduled_state"], "ON");
+    assert_eq!(status["entities"][0]["effective_state"], "OFF");
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+
+    let tariff: Value = client
+        .get(format!("{}/tariff", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(tariff["tariff"]["slots"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_mqtt_bridge_publishes_state_and_takes_commands() {
+    let dir = tempdir().unwrap();
+    // 12:00 is outside every On period
+    let clock = MockClock::new(at(12, 0));
+    let fake = fake_ha(&clock).await;
+    let broker = FakeBroker::start().await;
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        let app_state = app_state(state, &clock, dir.path());
+        tokio::spawn(run_mqtt_bridge(broker.settings("heating"), app_state));
+    })
+    .await;
+
+    // The first publish can beat the scheduler's first read of the entities
+    let bedroom_state = || -> Option<Value> {
+        serde_json::from_str(&broker.retained("heating/climate.bedroom/state")?).ok()
+    };
+    wait_for("the bridge to publish state", || {
+        broker.retained("heating/availability").as_deref() == Some("online")
+            && broker.retained("heating/climate.bedroom/paused").as_deref() == Some("OFF")
+            && bedroom_state().is_some_and(|bedroom| bedroom["current_temperature"] == 18.0)
+    })
+    .await;
+    let connect = &broker.connects()[0];
+    assert_eq!(connect.login.as_ref().unwrap().username, "user");
+    assert_eq!(connect.last_will.as_ref().unwrap().message, "offline");
+    assert_eq!(broker.subscriptions(), ["heating/+/set", "heating/+/+/set"]);
+    let active_entry: Value =
+        serde_json::from_str(&broker.retained("heating/active_entry").unwrap()).unwrap();
+    assert_eq!(active_entry["heating_state"], "OFF");
+    assert_eq!(broker.retained("heating/away").as_deref(), Some("OFF"));
+    assert_eq!(
+        broker.retained("heating/climate.bedroom/boost").as_deref(),
+        Some("null")
+    );
+    assert_eq!(bedroom_state().unwrap()["effective_state"], "OFF");
+
+    // Boosting just the bedroom
+    broker.publish("heating/climate.bedroom/boost/set", "30");
+    wait_for("the bedroom to heat", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("heat")
+    })
+    .await;
+    assert_eq!(fake.state_of("climate.living_room").as_deref(), Some("off"));
+    let boost: Value =
+        serde_json::from_str(&broker.retained("heating/climate.bedroom/boost").unwrap()).unwrap();
+    assert_eq!(boost["boost_end"], json!(at(12, 30)));
+
+    broker.publish("heating/cancel_boost/set", "");
+    wait_for("the bedroom to stop heating", || {
+        fake.state_of("climate.bedroom").as_deref() == Some("off")
+            && broker.retained("heating/climate.bedroom/boost").as_deref() == Some("null")
+    })
+    .await;
+
+    // A paused entity is left alone, even when it's switched on by hand
+    broker.publish("heating/climate.living_room/pause/set", "ON");
+    wait_for("the living room to pause", || {
+        broker
+            .retained("heating/climate.living_room/paused")
+            .as_deref()
+            == Some("ON")
+    })
+    .await;
+    fake.set_state_by_user("climate.living_room", "heat", "someone");
+    wait_for_whole_pass(&fake, "climate.living_room").await;
+    assert_eq!(
+        fake.state_of("climate.living_room").as_deref(),
+        Some("heat")
+    );
+
+    // Malformed commands are ignored
+    broker.publish("heating/away/set", "perhaps");
+    broker.publish("heating/away/set", "ON");
+    wait_for("the house to be away", || {
+        broker.retained("heating/away").as_deref() == Some("ON")
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let status: Value = client
+        .get(format!("{}/status", api))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["presence"]["occupancy"], "away");
+    assert_eq!(status["entities"][1]["effective_state"], "ON");
+    assert!(status["entities"][1]["paused_since"].is_string());
+
+    let history = wait_for_json("the journal", &format!("{}/history", api), |history| {
+        history.as_array().unwrap().len() == 6
+    })
+    .await;
+    let kinds: Vec<&str> = history
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "boost",
+            "scheduler_decision",
+            "boost_ended",
+            "scheduler_decision",
+            "paused",
+            "away",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_mqtt_bridge_covers_hot_water() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(12, 0));
+    let fake = fake_ha(&clock).await;
+    fake.set_entity(
+        "water_heater.tank",
+        "off",
+        json!({ "current_temperature": 45.0 }),
+    );
+    let broker = FakeBroker::start().await;
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        let app_state = app_state(state, &clock, dir.path());
+        tokio::spawn(run_mqtt_bridge(broker.settings("heating"), app_state));
+    })
+    .await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/hot_water/entities", api))
+        .json(&json!({ "entity_ids": ["water_heater.tank"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let tank_state = || -> Option<Value> {
+        serde_json::from_str(&broker.retained("heating/water_heater.tank/state")?).ok()
+    };
+    wait_for("the tank's state to be published", || {
+        tank_state().is_some_and(|tank| tank["current_temperature"] == 45.0)
+            && broker
+                .retained("heating/hot_water/next_transition")
+                .as_deref()
+                == Some("null")
+    })
+    .await;
+
+    // Boosting the hot water channel leaves the heating alone
+    broker.publish("heating/hot_water/boost/set", "30");
+    wait_for("the tank to heat", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("electric")
+    })
+    .await;
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+    wait_for("the tank's boost to be published", || {
+        broker
+            .retained("heating/water_heater.tank/boost")
+            .is_some_and(|boost| boost != "null")
+    })
+    .await;
+
+    // Its own topics cancel the boost
+    broker.publish("heating/water_heater.tank/cancel_boost/set", "");
+    wait_for("the tank to stop heating", || {
+        fake.state_of("water_heater.tank").as_deref() == Some("off")
+            && broker
+                .retained("heating/water_heater.tank/boost")
+                .as_deref()
+                == Some("null")
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn test_mqtt_discovery_announces_zone_controls() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(12, 0));
+    let fake = fake_ha(&clock).await;
+    let broker = FakeBroker::start().await;
+    let mut settings = broker.settings("heating");
+    settings.discovery = Some(DiscoverySettings {
+        prefix: "homeassistant".to_string(),
+        boost_presets: vec![30],
+    });
+    let api = start_scheduler_with(&fake, &clock, dir.path(), |state| {
+        let app_state = app_state(state, &clock, dir.path());
+        tokio::spawn(run_mqtt_bridge(settings, app_state));
+    })
+    .await;
+
+    let config = |topic: &str| -> Option<Value> {
+        broker
+            .retained(&format!("homeassistant/{}/config", topic))
+            .map(|payload| serde_json::from_str(&payload).unwrap())
+    };
+    let bedroom_switch = "switch/test-bridge/climate_bedroom_enabled";
+    wait_for("the zones to be announced", || {
+        config(bedroom_switch).is_some()
+    })
+    .await;
+
+    // The switch turns the scheduler off for its zone
+    let switch = config(bedroom_switch).unwrap();
+    assert_eq!(switch["name"], "Scheduler enabled");
+    assert_eq!(
+        broker
+            .retained(switch["state_topic"].as_str().unwrap())
+            .as_deref(),
+        Some("ON")
+    );
+    broker.publish(switch["command_topic"].as_str().unwrap(), "OFF");
+    wait_for("the bedroom to be disabled", || {
+        broker
+            .retained("heating/climate.bedroom/enabled")
+            .as_deref()
+            == Some("OFF")
+    })
+    .await;
+
+    // A boost button sends its preset
+    let button = config("button/test-bridge/climate_living_room_boost_30").unwrap();
+    assert_eq!(button["name"], "Boost 30 min");
+    broker.publish(
+        button["command_topic"].as_str().unwrap(),
+        button["payload_press"].as_str().unwrap(),
+    );
+    wait_for("the living room to heat", || {
+        fake.state_of("climate.living_room").as_deref() == Some("heat")
+    })
+    .await;
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+
+    let sensor = config("sensor/test-bridge/next_transition").unwrap();
+    assert_eq!(sensor["device_class"], "timestamp");
+    let next_transition: Value = serde_json::from_str(
+        &broker
+            .retained(sensor["state_topic"].as_str().unwrap())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        next_transition["time"],
+        serde_json::to_value(at(17, 0)).unwrap()
+    );
+
+    // Removing a zone withdraws its controls
+    let response = reqwest::Client::new()
+        .delete(format!("{}/entities", api))
+        .json(&json!({ "entity_id": "climate.bedroom" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for("the bedroom's controls to go", || {
+        config(bedroom_switch).is_none()
+            && config("button/test-bridge/climate_bedroom_boost_30").is_none()
+            && broker.retained("heating/climate.bedroom/state").is_none()
+    })
+    .await;
+    assert!(config("button/test-bridge/climate_living_room_boost_30").is_some());
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use common::fake_ha::FakeHa;
+use ha_heating_scheduler::api_client::ApiClient;
+use ha_heating_scheduler::climate::climate_state_api::ApiHeatingState;
+use ha_heating_scheduler::climate::{Availability, ClimateEntity, DefaultClimate, OnOffClimate};
+use ha_heating_scheduler::schedule::HeatingState;
+use serde_json::json;
+
+#[tokio::test]
+async fn test_fetch_climate_state() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "heat", 19.5);
+
+    let info = fake
+        .api_client()
+        .fetch_climate_state("climate.bedroom")
+        .await
+        .unwrap();
+
+    assert_eq!(info.state, HeatingState::On);
+    assert_eq!(info.current_temperature, Some(19.5));
+    assert_eq!(info.target_temperature, Some(21.0));
+}
+
+#[tokio::test]
+async fn test_fetch_unavailable_entity() {
+    let fake = FakeHa::start().await;
+    fake.set_entity(
+        "climate.bedroom",
+        "unavailable",
+        serde_json::json!({ "friendly_name": "Bedroom" }),
+    );
+
+    let info = fake
+        .api_client()
+        .fetch_climate_state("climate.bedroom")
+        .await
+        .unwrap();
+
+    assert_eq!(info.availability, Availability::Unavailable);
+    assert_eq!(info.current_temperature, None);
+}
+
+#[tokio::test]
+async fn test_fetch_fails_for_unknown_entity_and_bad_token() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "off", 18.0);
+
+    assert!(
+        fake.api_client()
+            .fetch_climate_state("climate.missing")
+            .await
+            .is_err()
+    );
+
+    let wrong_token = ApiClient::new(
+        reqwest::Url::parse(&fake.url).unwrap(),
+        "wrong-token".to_string(),
+    );
+    assert!(
+        wrong_token
+            .fetch_climate_state("climate.bedroom")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_default_climate_turns_on_and_off() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "off", 18.0);
+    let api_client = fake.api_client();
+    let mut climate = DefaultClimate::new("climate.bedroom".to_string());
+
+    climate.turn_on(&api_client).await.unwrap();
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("heat"));
+
+    let calls = fake.service_calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].domain, "climate");
+    assert_eq!(calls[0].service, "set_hvac_mode");
+    assert_eq!(calls[0].data["entity_id"], "climate.bedroom");
+    assert_eq!(calls[0].data["hvac_mode"], "heat");
+
+    climate.fetch_and_update_state(&api_client).await.unwrap();
+    assert_eq!(
+        climate.get_cached_state().as_ref().unwrap().state,
+        HeatingState::On
+    );
+
+    climate.turn_off(&api_client).await.unwrap();
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+}
+
+#[tokio::test]
+async fn test_switch_entities_turn_on_and_off() {
+    let fake = FakeHa::start().await;
+    fake.set_entity("switch.immersion", "off", json!({}));
+    fake.set_entity("input_boolean.underfloor", "off", json!({}));
+    let api_client = fake.api_client();
+
+    for entity_id in ["switch.immersion", "input_boolean.underfloor"] {
+        let mut switch = OnOffClimate::switch(entity_id.to_string());
+        switch.turn_on(&api_client).await.unwrap();
+        assert_eq!(fake.state_of(entity_id).as_deref(), Some("on"));
+
+        switch.fetch_and_update_state(&api_client).await.unwrap();
+        let info = switch.get_cached_state().clone().unwrap();
+        assert_eq!(info.state, HeatingState::On);
+        assert_eq!(info.hvac_mode, ApiHeatingState::Heat);
+        assert_eq!(info.current_temperature, None);
+
+        switch.turn_off(&api_client).await.unwrap();
+        assert_eq!(fake.state_of(entity_id).as_deref(), Some("off"));
+    }
+
+    let calls: Vec<(String, String)> = fake
+        .service_calls()
+        .into_iter()
+        .map(|call| (call.domain, call.service))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            ("switch", "turn_on"),
+            ("switch", "turn_off"),
+            ("input_boolean", "turn_on"),
+            ("input_boolean", "turn_off"),
+        ]
+        .map(|(domain, service)| (domain.to_string(), service.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_water_heater_turns_on_and_off() {
+    let fake = FakeHa::start().await;
+    fake.set_entity(
+        "water_heater.tank",
+        "off",
+        json!({ "current_temperature": 41.5, "temperature": 55.0 }),
+    );
+    let api_client = fake.api_client();
+    let mut water_heater = OnOffClimate::water_heater("water_heater.tank".to_string());
+
+    water_heater.turn_on(&api_client).await.unwrap();
+    assert_eq!(
+        fake.state_of("water_heater.tank").as_deref(),
+        Some("electric")
+    );
+    let calls = fake.service_calls();
+    assert_eq!(calls[0].domain, "water_heater");
+    assert_eq!(calls[0].service, "turn_on");
+    assert_eq!(calls[0].data["entity_id"], "water_heater.tank");
+
+    // Any operation mode but off counts as on
+    water_heater
+        .fetch_and_update_state(&api_client)
+        .await
+        .unwrap();
+    let info = water_heater.get_cached_state().clone().unwrap();
+    assert_eq!(info.state, HeatingState::On);
+    assert_eq!(info.current_temperature, Some(41.5));
+    assert_eq!(info.target_temperature, Some(55.0));
+
+    water_heater.turn_off(&api_client).await.unwrap();
+    water_heater
+        .fetch_and_update_state(&api_client)
+        .await
+        .unwrap();
+    let info = water_heater.get_cached_state().clone().unwrap();
+    assert_eq!(info.state, HeatingState::Off);
+    assert_eq!(info.hvac_mode, ApiHeatingState::Off);
+}
+
+#[tokio::test]
+async fn test_dry_run_climate_sends_nothing() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "off", 18.0);
+    let climate = DefaultClimate::new("climate.bedroom".to_string()).with_dry_run(true);
+
+    climate.turn_on(&fake.api_client()).await.unwrap();
+
+    assert!(fake.service_calls().is_empty());
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+}
+
+#[tokio::test]
+async fn test_service_call_errors_are_reported() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "off", 18.0);
+    let climate = DefaultClimate::new("climate.bedroom".to_string());
+
+    fake.set_service_error(Some(axum::http::StatusCode::INTERNAL_SERVER_ERROR));
+    assert!(climate.turn_on(&fake.api_client()).await.is_err());
+
+    fake.set_service_error(None);
+    let wrong_token = ApiClient::new(
+        reqwest::Url::parse(&fake.url).unwrap(),
+        "wrong-token".to_string(),
+    );
+    assert!(climate.turn_on(&wrong_token).await.is_err());
+    assert_eq!(fake.state_of("climate.bedroom").as_deref(), Some("off"));
+}
+
+#[tokio::test]
+async fn test_temperature_sensor_replaces_the_device_reading() {
+    let fake = FakeHa::start().await;
+    fake.add_climate("climate.bedroom", "heat", 24.0);
+    fake.set_entity("switch.underfloor", "on", json!({}));
+    fake.set_entity("sensor.bedroom_temperature", "19.5", json!({}));
+    let api_client = fake.api_client();
+
+    let mut climate = DefaultClimate::new("climate.bedroom".to_string());
+    let mut switch = OnOffClimate::switch("switch.underfloor".to_string());
+    climate.set_temperature_sensor(Some("sensor.bedroom_temperature".to_string()));
+    switch.set_temperature_sensor(Some("sensor.bedroom_temperature".to_string()));
+    climate.fetch_and_update_state(&api_client).await.unwrap();
+    switch.fetch_and_update_state(&api_client).await.unwrap();
+    let temperature = |entity: &dyn ClimateEntity| {
+        entity
+            .get_cached_state()
+            .as_ref()
+            .unwrap()
+            .current_temperature
+    };
+    assert_eq!(temperature(&climate), Some(19.5));
+    assert_eq!(temperature(&switch), Some(19.5));
+
+    // Without a usable reading the device's own is kept
+    fake.set_entity("sensor.bedroom_temperature", "unknown", json!({}));
+    climate.fetch_and_update_state(&api_client).await.unwrap();
+    assert_eq!(temperature(&climate), Some(24.0));
+    climate.set_temperature_sensor(Some("sensor.missing".to_string()));
+    climate.fetch_and_update_state(&api_client).await.unwrap();
+    assert_eq!(temperature(&climate), Some(24.0));
+}
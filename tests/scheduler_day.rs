@@ -0,0 +1,1020 @@
+mod common;
+
+use chrono::{DateTime, Duration, Local};
+use common::{
+    at, mock_climate, offline_api_client, scheduler_state, start_of_day, work_day_schedule,
+};
+use ha_heating_scheduler::api_client::ApiClient;
+use ha_heating_scheduler::climate::{
+    BoostInfo, ClimateEntity, ClimateEntityWrapper, ClimateInfo, MockClimate, SimulatedRoom,
+    ThermalModel,
+};
+use ha_heating_scheduler::clock::{Clock, MockClock};
+use ha_heating_scheduler::config::entities_persistence::{
+    ControlMode, EntitySettings, OpenWindowSettings,
+};
+use ha_heating_scheduler::history::EventKind;
+use ha_heating_scheduler::history::persistence::load_history;
+use ha_heating_scheduler::schedule::{HeatingState, Schedule, ScheduleEntry, TimePeriod};
+use ha_heating_scheduler::scheduler::{SchedulerMemory, SchedulerState, run_scheduler_tick};
+use ha_heating_scheduler::tariff::{FlexibleHeating, PriceSlot};
+use ha_heating_scheduler::weather::{OutdoorWeather, WeatherCompensation};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tempfile::tempdir;
+
+const TICK: i64 = 15;
+
+#[tokio::test]
+async fn test_simulated_day_follows_schedule_and_boost() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(start_of_day());
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![
+            mock_climate("climate.bedroom", &clock),
+            mock_climate("climate.living_room", &clock),
+        ],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() < start_of_day() + Duration::days(1) {
+        if clock.now() == at(12, 0) {
+            // Someone presses boost on the living room at noon
+            let mut climates = state.climate_entities.write().unwrap();
+            climates[1].set_boost(Some(BoostInfo {
+                boost_start: at(12, 0),
+                boost_end: at(12, 45),
+            }));
+        }
+
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(TICK));
+    }
+
+    let history = state.history.read().unwrap();
+    let decisions = |entity_id: &str| -> Vec<_> {
+        history
+            .events
+            .iter()
+            .filter(|e| e.entity_id.as_deref() == Some(entity_id))
+            .filter_map(|e| match &e.kind {
+                EventKind::SchedulerDecision {
+                    desired_state,
+                    boosted,
+                    active_entry,
+                    ..
+                } => Some((
+                    e.timestamp,
+                    desired_state.clone(),
+                    *boosted,
+                    active_entry.as_ref().map(|entry| entry.name.clone()),
+                )),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // The mock follows the commands, so there is exactly one decision per transition
+    let bedroom = decisions("climate.bedroom");
+    let transitions: Vec<_> = bedroom.iter().map(|d| (d.0, d.1.clone())).collect();
+    assert_eq!(
+        transitions,
+        vec![
+            (at(6, 0), HeatingState::On),
+            (at(9, 0), HeatingState::Off),
+            (at(17, 0), HeatingState::On),
+            (at(22, 0), HeatingState::Off),
+        ]
+    );
+    assert_eq!(bedroom[0].3.as_deref(), Some("Morning"));
+    assert!(bedroom.iter().all(|d| !d.2));
+
+    // The boost turns the living room on at noon and it goes off once the boost has run out
+    let living_room = decisions("climate.living_room");
+    let transitions: Vec<_> = living_room
+        .iter()
+        .map(|d| (d.0, d.1.clone(), d.2))
+        .collect();
+    assert_eq!(
+        transitions,
+        vec![
+            (at(6, 0), HeatingState::On, false),
+            (at(9, 0), HeatingState::Off, false),
+            (at(12, 0), HeatingState::On, true),
+            (at(13, 0), HeatingState::Off, false),
+            (at(17, 0), HeatingState::On, false),
+            (at(22, 0), HeatingState::Off, false),
+        ]
+    );
+
+    // Nothing changed behind the scheduler's back
+    assert!(
+        !history
+            .events
+            .iter()
+            .any(|e| matches!(e.kind, EventKind::ManualChange { .. }))
+    );
+
+    // The boost is cleared on the first tick after it runs out
+    let boost_ended: Vec<_> = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::BoostEnded))
+        .map(|e| (e.timestamp, e.entity_id.clone()))
+        .collect();
+    assert_eq!(
+        boost_ended,
+        vec![(at(13, 0), Some("climate.living_room".to_string()))]
+    );
+    let climates = state.climate_entities.read().unwrap();
+    assert!(climates[1].get_boosted_status().is_none());
+}
+
+#[tokio::test]
+async fn test_temperature_samples_use_the_injected_clock() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+
+    for _ in 0..4 {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(TICK));
+    }
+
+    let temperature_history = state.temperature_history.read().unwrap();
+    let samples = temperature_history.samples("climate.bedroom", None, None);
+    let timestamps: Vec<_> = samples.iter().map(|s| s.timestamp).collect();
+    assert_eq!(timestamps, vec![at(6, 0), at(6, 15), at(6, 30), at(6, 45)]);
+}
+
+/// A mock entity that logs its reads and commands, and that a person can switch back by hand
+/// straight after the scheduler next commands it
+#[derive(Debug, Clone)]
+struct WatchedClimate {
+    mock: MockClimate,
+    clock: MockClock,
+    log: Arc<Mutex<Vec<String>>>,
+    overrule_next_command: Arc<AtomicBool>,
+    changed_by_hand_at: Arc<Mutex<Option<DateTime<Local>>>>,
+}
+
+impl WatchedClimate {
+    fn new(entity_id: &str, clock: &MockClock, log: &Arc<Mutex<Vec<String>>>) -> Self {
+        WatchedClimate {
+            mock: mock_climate(entity_id, clock),
+            clock: clock.clone(),
+            log: Arc::clone(log),
+            overrule_next_command: Arc::new(AtomicBool::new(false)),
+            changed_by_hand_at: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn logged(&self, what: &str) {
+        let entry = format!("{} {}", what, self.mock.entity_id);
+        self.log.lock().unwrap().push(entry);
+    }
+
+    /// Switch the room back by hand a moment later, if a person is waiting to
+    async fn overrule(&self, state: HeatingState) {
+        if self.overrule_next_command.swap(false, Ordering::SeqCst) {
+            self.clock.advance(Duration::seconds(1));
+            match state {
+                HeatingState::On => self.mock.turn_on(&offline_api_client()).await.unwrap(),
+                HeatingState::Off => self.mock.turn_off(&offline_api_client()).await.unwrap(),
+            }
+            *self.changed_by_hand_at.lock().unwrap() = Some(self.clock.now());
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ClimateEntity for WatchedClimate {
+    fn get_entity_id(&self) -> &str {
+        self.mock.get_entity_id()
+    }
+
+    fn get_cached_state(&self) -> &Option<ClimateInfo> {
+        self.mock.get_cached_state()
+    }
+
+    fn update_cached_state(&mut self, climate_info: Option<ClimateInfo>) {
+        self.mock.update_cached_state(climate_info);
+    }
+
+    fn get_boosted_status(&self) -> &Option<BoostInfo> {
+        self.mock.get_boosted_status()
+    }
+
+    fn set_boost(&mut self, boost: Option<BoostInfo>) {
+        self.mock.set_boost(boost);
+    }
+
+    async fn fetch_and_update_state(&mut self, api_client: &ApiClient) -> anyhow::Result<()> {
+        self.logged("read");
+        self.mock.fetch_and_update_state(api_client).await?;
+        let changed_by_hand_at = *self.changed_by_hand_at.lock().unwrap();
+        if let Some(info) = &mut self.mock.info
+            && let Some(changed_at) = changed_by_hand_at
+        {
+            info.last_changed = Some(changed_at);
+            info.changed_by = Some("someone".to_string());
+        }
+        Ok(())
+    }
+
+    async fn turn_on(&self, api_client: &ApiClient) -> anyhow::Result<()> {
+        self.logged("turn on");
+        self.mock.turn_on(api_client).await?;
+        self.overrule(HeatingState::Off).await;
+        Ok(())
+    }
+
+    async fn turn_off(&self, api_client: &ApiClient) -> anyhow::Result<()> {
+        self.logged("turn off");
+        self.mock.turn_off(api_client).await?;
+        self.overrule(HeatingState::On).await;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_commands_in_a_pass_are_verified_after_one_shared_delay() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let entity_ids = [
+        "climate.bedroom",
+        "climate.living_room",
+        "climate.study",
+        "climate.hall",
+    ];
+    let mut state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        entity_ids
+            .iter()
+            .map(|id| WatchedClimate::new(id, &clock, &log))
+            .collect(),
+        &clock,
+        dir.path(),
+    );
+    state.verify_delay = std::time::Duration::from_millis(50);
+    let mut memory = SchedulerMemory::default();
+
+    run_scheduler_tick(&state, &mut memory).await;
+
+    // Every entity is commanded before any is checked, then each is re-read once
+    let log = log.lock().unwrap();
+    let commanded: Vec<String> = entity_ids
+        .iter()
+        .flat_map(|id| [format!("read {}", id), format!("turn on {}", id)])
+        .collect();
+    assert_eq!(log[..commanded.len()], commanded);
+    let mut verify_reads = log[commanded.len()..].to_vec();
+    verify_reads.sort();
+    let mut expected_reads: Vec<String> =
+        entity_ids.iter().map(|id| format!("read {}", id)).collect();
+    expected_reads.sort();
+    assert_eq!(verify_reads, expected_reads);
+    let history = state.history.read().unwrap();
+    let decisions = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::SchedulerDecision { .. }))
+        .count();
+    assert_eq!(decisions, entity_ids.len());
+}
+
+#[tokio::test]
+async fn test_change_by_hand_before_a_command_is_checked_is_not_a_failure() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let bedroom = WatchedClimate::new("climate.bedroom", &clock, &log);
+    let overrule = Arc::clone(&bedroom.overrule_next_command);
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![bedroom],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+
+    // Someone turns the bedroom off again as soon as the morning period switches it on
+    overrule.store(true, Ordering::SeqCst);
+    run_scheduler_tick(&state, &mut memory).await;
+
+    let history = state.history.read().unwrap();
+    let kinds: Vec<&EventKind> = history.events.iter().map(|e| &e.kind).collect();
+    assert!(matches!(kinds[0], EventKind::SchedulerDecision { .. }));
+    assert_eq!(
+        kinds[1],
+        &EventKind::ManualChange {
+            expected_state: HeatingState::On,
+            observed_state: HeatingState::Off,
+        }
+    );
+    assert!(matches!(
+        kinds[2],
+        EventKind::ManualOverride {
+            state: HeatingState::Off,
+            ..
+        }
+    ));
+    assert!(
+        !kinds
+            .iter()
+            .any(|kind| matches!(kind, EventKind::Error { .. }))
+    );
+    let runtime = state.runtime.read().unwrap();
+    assert_eq!(runtime["climate.bedroom"].failed_attempts, 0);
+    assert!(memory.unverified_commands.is_empty());
+}
+
+#[tokio::test]
+async fn test_change_by_hand_before_a_command_is_checked_is_honoured() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let bedroom = WatchedClimate::new("climate.bedroom", &clock, &log);
+    let overrule = Arc::clone(&bedroom.overrule_next_command);
+    let room = Arc::clone(&bedroom.mock.room);
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![bedroom],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+
+    overrule.store(true, Ordering::SeqCst);
+    run_scheduler_tick(&state, &mut memory).await;
+    log.lock().unwrap().clear();
+
+    // The room is left off for the rest of the morning, neither retried nor taken for the
+    // command arriving late
+    while clock.now() < at(9, 0) {
+        clock.advance(Duration::minutes(TICK));
+        run_scheduler_tick(&state, &mut memory).await;
+        assert_eq!(room.lock().unwrap().state, HeatingState::Off);
+    }
+    assert!(
+        log.lock()
+            .unwrap()
+            .iter()
+            .all(|entry| entry.starts_with("read"))
+    );
+    let history = state.history.read().unwrap();
+    let manual_changes = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::ManualChange { .. }))
+        .count();
+    assert_eq!(manual_changes, 1);
+    assert!(
+        !history
+            .events
+            .iter()
+            .any(|e| matches!(e.kind, EventKind::Error { .. } | EventKind::Degraded { .. }))
+    );
+}
+
+#[tokio::test]
+async fn test_hot_water_switched_on_by_hand_is_kept_on_until_its_next_transition() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(12, 0));
+    let tank = mock_climate("water_heater.tank", &clock);
+    let tank_room = Arc::clone(&tank.room);
+    let state = scheduler_state(
+        offline_api_client(),
+        Schedule::new("No Heating"),
+        Vec::new(),
+        &clock,
+        dir.path(),
+    );
+    state.hot_water_entities.write().unwrap().push(tank);
+    state
+        .hot_water_schedule
+        .write()
+        .unwrap()
+        .add_entry(ScheduleEntry::new(
+            "Evening",
+            TimePeriod::new(17, 0, 18, 0),
+            HeatingState::On,
+        ));
+    let mut memory = SchedulerMemory::default();
+
+    run_scheduler_tick(&state, &mut memory).await;
+    tank_room.lock().unwrap().state = HeatingState::On;
+
+    // Kept on by hand until the evening entry, which keeps it on until 18:00
+    while clock.now() < at(17, 45) {
+        clock.advance(Duration::minutes(TICK));
+        run_scheduler_tick(&state, &mut memory).await;
+        assert_eq!(
+            tank_room.lock().unwrap().state,
+            HeatingState::On,
+            "at {}",
+            clock.now()
+        );
+    }
+    clock.advance(Duration::minutes(TICK));
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(tank_room.lock().unwrap().state, HeatingState::Off);
+
+    let history = state.history.read().unwrap();
+    let kinds: Vec<&EventKind> = history.events.iter().map(|e| &e.kind).collect();
+    assert_eq!(
+        kinds[0],
+        &EventKind::ManualChange {
+            expected_state: HeatingState::Off,
+            observed_state: HeatingState::On,
+        }
+    );
+    assert!(matches!(
+        kinds[1],
+        EventKind::ManualOverride { state: HeatingState::On, until: Some(until), .. }
+            if *until == at(17, 0)
+    ));
+    assert_eq!(kinds[2], &EventKind::ManualOverrideEnded);
+}
+
+#[tokio::test]
+async fn test_history_is_saved_in_batches() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+    let saved_events = || load_history(&state.history_file_path).unwrap().events.len();
+
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(saved_events(), 1);
+
+    // An API call records straight away, but it's written with the next batch
+    state
+        .history
+        .write()
+        .unwrap()
+        .record(clock.now(), None, EventKind::Home);
+    clock.advance(Duration::seconds(TICK));
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(state.history.read().unwrap().events.len(), 2);
+    assert_eq!(saved_events(), 1);
+
+    clock.advance(Duration::minutes(1));
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(saved_events(), 2);
+}
+
+#[tokio::test]
+async fn test_stale_outdoor_reading_stops_weather_compensation() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(6, 0));
+    let mut schedule = work_day_schedule();
+    schedule.weather_compensation = Some(WeatherCompensation {
+        sensor: "weather.home".to_string(),
+        warm_cutoff: Some(16.0),
+        heating_curve: None,
+    });
+    let state = scheduler_state(
+        offline_api_client(),
+        schedule,
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    // The last reading before the sensor went quiet was a mild one
+    *state.weather.write().unwrap() = Some(OutdoorWeather {
+        sensor: "weather.home".to_string(),
+        temperature: 18.0,
+        read_at: at(6, 0),
+        warm_weather: true,
+        setpoint_shift: -1.0,
+    });
+    let mut memory = SchedulerMemory::default();
+    let bedroom_state = |state: &SchedulerState<MockClimate>| {
+        let climates = state.climate_entities.read().unwrap();
+        climates[0]
+            .get_cached_state()
+            .as_ref()
+            .unwrap()
+            .state
+            .clone()
+    };
+
+    // Within the hour the last reading still skips the morning period
+    while clock.now() <= at(7, 0) {
+        run_scheduler_tick(&state, &mut memory).await;
+        assert_eq!(bedroom_state(&state), HeatingState::Off);
+        clock.advance(Duration::minutes(TICK));
+    }
+    assert!(state.weather.read().unwrap().is_some());
+
+    run_scheduler_tick(&state, &mut memory).await;
+    assert!(state.weather.read().unwrap().is_none());
+    assert_eq!(bedroom_state(&state), HeatingState::On);
+    let stale: Vec<_> = state
+        .history
+        .read()
+        .unwrap()
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::OutdoorReadingStale { .. }))
+        .map(|e| (e.timestamp, e.kind.clone()))
+        .collect();
+    assert_eq!(
+        stale,
+        [(
+            at(7, 15),
+            EventKind::OutdoorReadingStale {
+                sensor: "weather.home".to_string(),
+                read_at: at(6, 0),
+            }
+        )]
+    );
+
+    // Journaled once, not on every pass without a reading
+    clock.advance(Duration::minutes(TICK));
+    run_scheduler_tick(&state, &mut memory).await;
+    let history = state.history.read().unwrap();
+    assert_eq!(
+        history
+            .events
+            .iter()
+            .filter(|e| matches!(e.kind, EventKind::OutdoorReadingStale { .. }))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_room_temperature_follows_heating() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(start_of_day());
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() < start_of_day() + Duration::days(1) {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(TICK));
+    }
+
+    let temperature_history = state.temperature_history.read().unwrap();
+    let temperature_at = |hour, minute| {
+        temperature_history.samples(
+            "climate.bedroom",
+            Some(at(hour, minute)),
+            Some(at(hour, minute)),
+        )[0]
+        .temperature
+    };
+
+    // Cools overnight, warms through the morning period, then cools again
+    assert!(temperature_at(6, 0) < 18.0);
+    assert!(temperature_at(9, 0) > temperature_at(6, 0));
+    assert!(temperature_at(17, 0) < temperature_at(9, 0));
+    assert!(temperature_at(22, 0) > temperature_at(17, 0));
+}
+
+#[tokio::test]
+async fn test_thermostat_mode_regulates_around_target() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(17, 0));
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    state.entity_settings.write().unwrap().insert(
+        "climate.bedroom".to_string(),
+        EntitySettings {
+            control_mode: ControlMode::Thermostat,
+            target_temperature: Some(20.0),
+            hysteresis: Some(0.5),
+            min_on_minutes: Some(30),
+            min_off_minutes: Some(30),
+            ..Default::default()
+        },
+    );
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() <= at(23, 0) {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(1));
+    }
+
+    let history = state.history.read().unwrap();
+    let decisions: Vec<_> = history
+        .events
+        .iter()
+        .filter_map(|e| match &e.kind {
+            EventKind::SchedulerDecision { desired_state, .. } => {
+                Some((e.timestamp, desired_state.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Heats straight away, then cycles on and off instead of staying on all evening
+    assert_eq!(decisions[0], (at(17, 0), HeatingState::On));
+    let starts = decisions.iter().filter(|d| d.1 == HeatingState::On).count();
+    assert!(
+        starts >= 3,
+        "expected several heating cycles, got {:?}",
+        decisions
+    );
+    // Off at the end of the evening period
+    assert_eq!(decisions.last().unwrap().1, HeatingState::Off);
+    assert!(decisions.last().unwrap().0 <= at(22, 0));
+
+    // No switch comes sooner than the minimum on/off time after the previous one
+    for pair in decisions.windows(2) {
+        assert!(pair[1].0 - pair[0].0 >= Duration::minutes(30), "{:?}", pair);
+    }
+
+    // Once warmed up the room stays around the target
+    let temperature_history = state.temperature_history.read().unwrap();
+    let samples = temperature_history.samples("climate.bedroom", Some(at(19, 0)), Some(at(22, 0)));
+    assert!(!samples.is_empty());
+    for sample in samples {
+        assert!(
+            (19.0..=21.5).contains(&sample.temperature),
+            "{} at {}",
+            sample.temperature,
+            sample.timestamp
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_frost_protection_keeps_an_empty_house_above_the_floor() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(start_of_day());
+    // A freezing January day with the schedule off throughout
+    let model = ThermalModel {
+        outdoor_temperature: -8.0,
+        ..ThermalModel::default()
+    };
+    let bedroom = ClimateEntityWrapper::Mock(MockClimate::with_simulation(
+        "climate.bedroom".to_string(),
+        SimulatedRoom::new(model, HeatingState::Off, 8.0),
+        Arc::new(clock.clone()),
+    ));
+    let mut state = scheduler_state(
+        offline_api_client(),
+        Schedule::new("Away"),
+        vec![bedroom],
+        &clock,
+        dir.path(),
+    );
+    state.frost_protection_temperature = Some(5.0);
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() < start_of_day() + Duration::days(1) {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(TICK));
+    }
+
+    let history = state.history.read().unwrap();
+    let started = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::FrostProtection { .. }))
+        .count();
+    let ended = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::FrostProtectionEnded))
+        .count();
+    assert!(
+        started >= 2,
+        "expected repeated frost protection, got {}",
+        started
+    );
+    assert!(ended >= started - 1);
+
+    // Every time it drops below the floor it is caught within a tick
+    let temperature_history = state.temperature_history.read().unwrap();
+    let samples = temperature_history.samples("climate.bedroom", None, None);
+    assert!(samples.iter().all(|sample| sample.temperature > 4.0));
+    assert!(samples.iter().any(|sample| sample.heating_on));
+}
+
+#[tokio::test]
+async fn test_frost_protection_switches_on_a_paused_entity() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(start_of_day());
+    let bedroom = MockClimate::with_simulation(
+        "climate.bedroom".to_string(),
+        SimulatedRoom::new(ThermalModel::default(), HeatingState::Off, 3.0),
+        Arc::new(clock.clone()),
+    );
+    let room = Arc::clone(&bedroom.room);
+    let mut state = scheduler_state(
+        offline_api_client(),
+        Schedule::new("Away"),
+        vec![ClimateEntityWrapper::Mock(bedroom)],
+        &clock,
+        dir.path(),
+    );
+    state.frost_protection_temperature = Some(5.0);
+    state
+        .runtime
+        .write()
+        .unwrap()
+        .entry("climate.bedroom".to_string())
+        .or_default()
+        .paused_since = Some(clock.now());
+    let mut memory = SchedulerMemory::default();
+
+    run_scheduler_tick(&state, &mut memory).await;
+
+    assert_eq!(room.lock().unwrap().state, HeatingState::On);
+    let history = state.history.read().unwrap();
+    assert!(
+        history
+            .events
+            .iter()
+            .any(|e| matches!(e.kind, EventKind::FrostProtection { .. }))
+    );
+}
+
+#[tokio::test]
+async fn test_sharp_temperature_drop_pauses_heating() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(7, 0));
+    let bedroom = MockClimate::with_simulation(
+        "climate.bedroom".to_string(),
+        SimulatedRoom::new(ThermalModel::default(), HeatingState::Off, 20.0),
+        Arc::new(clock.clone()),
+    );
+    let room = Arc::clone(&bedroom.room);
+    let state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![ClimateEntityWrapper::Mock(bedroom)],
+        &clock,
+        dir.path(),
+    );
+    state.entity_settings.write().unwrap().insert(
+        "climate.bedroom".to_string(),
+        EntitySettings {
+            open_window: Some(OpenWindowSettings {
+                sensor: None,
+                drop_celsius: Some(1.0),
+                drop_minutes: 5,
+                pause_minutes: 30,
+            }),
+            ..Default::default()
+        },
+    );
+    let mut memory = SchedulerMemory::default();
+    let heating = || room.lock().unwrap().state.clone();
+
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(heating(), HeatingState::On);
+
+    // Someone opens the window and the room loses 1.5°C in two minutes
+    clock.advance(Duration::minutes(2));
+    room.lock().unwrap().temperature -= 1.5;
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(heating(), HeatingState::Off);
+
+    // The pause lasts its 30 minutes, then the schedule takes over again
+    clock.advance(Duration::minutes(29));
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(heating(), HeatingState::Off);
+    clock.advance(Duration::minutes(1));
+    run_scheduler_tick(&state, &mut memory).await;
+    assert_eq!(heating(), HeatingState::On);
+
+    let history = state.history.read().unwrap();
+    let window_events: Vec<_> = history
+        .events
+        .iter()
+        .filter(|e| {
+            matches!(
+                e.kind,
+                EventKind::WindowOpen { .. } | EventKind::WindowClosed
+            )
+        })
+        .map(|e| e.timestamp)
+        .collect();
+    assert_eq!(window_events, vec![at(7, 2), at(7, 32)]);
+}
+
+#[tokio::test]
+async fn test_optimum_start_reaches_target_when_the_evening_begins() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(start_of_day());
+    let mut state = scheduler_state(
+        offline_api_client(),
+        work_day_schedule(),
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    state.optimum_start_max_lead = Duration::hours(5);
+    state.entity_settings.write().unwrap().insert(
+        "climate.bedroom".to_string(),
+        EntitySettings {
+            target_temperature: Some(21.0),
+            optimum_start: true,
+            ..Default::default()
+        },
+    );
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() <= at(17, 0) {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(1));
+    }
+
+    // Nothing has been learned before the first morning, so it starts on the hour
+    let history = state.history.read().unwrap();
+    let preheats: Vec<_> = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::PreheatStarted { .. }))
+        .map(|e| e.timestamp)
+        .collect();
+    assert_eq!(preheats.len(), 1, "{:?}", preheats);
+    assert!(
+        preheats[0] > at(12, 0) && preheats[0] < at(17, 0),
+        "{:?}",
+        preheats
+    );
+
+    // The morning's heating taught it how fast the room warms up
+    let temperature_history = state.temperature_history.read().unwrap();
+    let samples = temperature_history.samples("climate.bedroom", Some(at(17, 0)), None);
+    let temperature = samples.first().unwrap().temperature;
+    assert!(
+        (20.0..=22.5).contains(&temperature),
+        "{} at 17:00",
+        temperature
+    );
+}
+
+#[tokio::test]
+async fn test_flexible_entry_heats_in_the_cheapest_slots() {
+    let dir = tempdir().unwrap();
+    let clock = MockClock::new(at(13, 0));
+    let mut schedule = Schedule::new("Tariff");
+    schedule.add_entry(
+        ScheduleEntry::new("Afternoon", TimePeriod::new(14, 0, 20, 0), HeatingState::On)
+            .with_flexible(Some(FlexibleHeating {
+                duration_minutes: 90,
+                contiguous: false,
+            })),
+    );
+    let state = scheduler_state(
+        offline_api_client(),
+        schedule,
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    let price = |start, end, price| PriceSlot { start, end, price };
+    state.tariff.write().unwrap().tariff.slots = vec![
+        price(at(14, 0), at(15, 0), 0.30),
+        price(at(15, 0), at(16, 0), 0.12),
+        price(at(16, 0), at(18, 0), 0.45),
+        price(at(18, 0), at(19, 0), 0.08),
+        price(at(19, 0), at(20, 0), 0.30),
+    ];
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() <= at(21, 0) {
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(1));
+    }
+
+    let history = state.history.read().unwrap();
+    let planned = history
+        .events
+        .iter()
+        .filter(|e| matches!(e.kind, EventKind::FlexibleHeatingPlanned { .. }))
+        .count();
+    assert_eq!(planned, 1);
+    let decisions: Vec<_> = history
+        .events
+        .iter()
+        .filter_map(|e| match &e.kind {
+            EventKind::SchedulerDecision { desired_state, .. } => {
+                Some((e.timestamp, desired_state.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    // The whole 18:00 hour, then the cheaper half of the 15:00 hour
+    assert_eq!(
+        decisions,
+        vec![
+            (at(15, 0), HeatingState::On),
+            (at(15, 30), HeatingState::Off),
+            (at(18, 0), HeatingState::On),
+            (at(19, 0), HeatingState::Off),
+        ]
+    );
+    // The plan for a finished period is dropped
+    assert!(state.tariff.read().unwrap().plans.is_empty());
+}
+
+#[tokio::test]
+async fn test_flexible_entry_first_seen_mid_period_plans_from_now() {
+    let dir = tempdir().unwrap();
+    // Started half-way through the period, after the cheap 15:00 hour has gone
+    let clock = MockClock::new(at(16, 30));
+    let mut schedule = Schedule::new("Tariff");
+    schedule.add_entry(
+        ScheduleEntry::new("Afternoon", TimePeriod::new(14, 0, 20, 0), HeatingState::On)
+            .with_flexible(Some(FlexibleHeating {
+                duration_minutes: 90,
+                contiguous: false,
+            })),
+    );
+    let state = scheduler_state(
+        offline_api_client(),
+        schedule,
+        vec![mock_climate("climate.bedroom", &clock)],
+        &clock,
+        dir.path(),
+    );
+    let price = |start, end, price| PriceSlot { start, end, price };
+    state.tariff.write().unwrap().tariff.slots = vec![
+        price(at(14, 0), at(15, 0), 0.30),
+        price(at(15, 0), at(16, 0), 0.12),
+        price(at(16, 0), at(18, 0), 0.45),
+        price(at(18, 0), at(19, 0), 0.08),
+        price(at(19, 0), at(20, 0), 0.30),
+    ];
+    let mut memory = SchedulerMemory::default();
+
+    while clock.now() <= at(21, 0) {
+        if clock.now() == at(18, 30) {
+            // A restart forgets the plan, but the journal still has it
+            state.tariff.write().unwrap().plans.clear();
+            memory = SchedulerMemory::default();
+        }
+        run_scheduler_tick(&state, &mut memory).await;
+        clock.advance(Duration::minutes(1));
+    }
+
+    let history = state.history.read().unwrap();
+    let planned: Vec<_> = history
+        .events
+        .iter()
+        .filter_map(|e| match &e.kind {
+            EventKind::FlexibleHeatingPlanned { slots, .. } => Some(slots.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0].first().unwrap().start, at(18, 0));
+    let decisions: Vec<_> = history
+        .events
+        .iter()
+        .filter_map(|e| match &e.kind {
+            EventKind::SchedulerDecision { desired_state, .. } => {
+                Some((e.timestamp, desired_state.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    // The 18:00 hour, then the cheaper of what's left: the first half of the 19:00 hour
+    assert_eq!(
+        decisions,
+        vec![
+            (at(18, 0), HeatingState::On),
+            (at(19, 30), HeatingState::Off)
+        ]
+    );
+}